@@ -0,0 +1,86 @@
+use std::fmt::Write;
+use parser::build::{BuildAbility, FightBuilds, PlayerBuild};
+
+/// Escapes the characters that would break a Markdown table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn ability_name(ability: &BuildAbility) -> String {
+    let name = if ability.name.is_empty() { format!("Ability {}", ability.ability_id) } else { ability.name.clone() };
+    match &ability.scripts {
+        Some(scripts) if !scripts.is_empty() => format!("{} ({})", cell(&name), cell(&scripts.join(", "))),
+        _ => cell(&name),
+    }
+}
+
+fn player_markdown(out: &mut String, build: &PlayerBuild) {
+    let _ = writeln!(out, "## {} ({})\n", build.name, build.display_name);
+    let _ = writeln!(out, "{:?} {:?}, level {}, {} CP\n", build.race, build.class, build.level, build.champion_points);
+    if !build.subclasses.is_empty() {
+        let _ = writeln!(out, "Skill lines: {}\n", build.subclasses.join(", "));
+    }
+
+    if !build.sets.is_empty() {
+        let _ = writeln!(out, "### Sets\n");
+        for set in &build.sets {
+            let mut notes = Vec::new();
+            if set.mythic {
+                notes.push("mythic".to_string());
+            }
+            if set.perfected_pieces == set.pieces && set.pieces > 0 {
+                notes.push("perfected".to_string());
+            } else if set.perfected_pieces > 0 {
+                notes.push(format!("{} perfected", set.perfected_pieces));
+            }
+            if set.back_bar_pieces > 0 {
+                notes.push(format!("{} on back bar", set.back_bar_pieces));
+            }
+            let notes = if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) };
+            let _ = writeln!(out, "- {} x {}{notes}", set.pieces, set.name);
+        }
+        out.push('\n');
+    }
+
+    let _ = writeln!(out, "### Gear\n");
+    if build.gear.is_empty() {
+        let _ = writeln!(out, "No gear logged\n");
+    } else {
+        let _ = writeln!(out, "| Slot | Type | Set | Trait | Quality | Enchant |");
+        let _ = writeln!(out, "| --- | --- | --- | --- | --- | --- |");
+        for piece in &build.gear {
+            let set = match &piece.set_name {
+                Some(name) if piece.perfected => format!("{} (Perfected)", cell(name)),
+                Some(name) => cell(name),
+                None if piece.set_id == 0 => String::new(),
+                None => format!("Set {}", piece.set_id),
+            };
+            let _ = writeln!(
+                out, "| {} | {} | {set} | {} | {} | {} |",
+                piece.slot,
+                piece.item_type.as_deref().unwrap_or_default(),
+                piece.gear_trait.as_deref().unwrap_or_default(),
+                piece.quality,
+                piece.enchant.as_deref().unwrap_or_default(),
+            );
+        }
+        out.push('\n');
+    }
+
+    let _ = writeln!(out, "### Bars\n");
+    for (bar, abilities) in [("Front", &build.front_bar), ("Back", &build.back_bar)] {
+        let names: Vec<String> = abilities.iter().map(ability_name).collect();
+        let _ = writeln!(out, "- {bar}: {}", if names.is_empty() { "empty".to_string() } else { names.join(" / ") });
+    }
+    out.push('\n');
+}
+
+/// A Markdown build sheet of every player in a fight, numbered as in `fights`.
+pub fn build_markdown(number: usize, builds: &FightBuilds) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {} ({}s)\n", builds.fight.title(number), builds.fight.duration() / 1000);
+    for build in &builds.players {
+        player_markdown(&mut out, build);
+    }
+    out
+}
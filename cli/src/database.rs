@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use parser::fights::{Fight, FightTracker};
+use parser::line::{BeginLog, LogLine, PlayerInfo, UnitAdded, UnitChanged};
+use parser::parse;
+use parser::unit::UnitState;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+/// Every time is a Unix time in milliseconds. Units are rows of `units` rather than the log's unit ids, which are reused between logs,
+/// and enums are stored as their names, e.g. `result = 'Died'`.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS log_files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    log_file_id INTEGER NOT NULL REFERENCES log_files(id),
+    start_time INTEGER NOT NULL,
+    log_version INTEGER NOT NULL,
+    realm TEXT NOT NULL,
+    language TEXT NOT NULL,
+    game_version TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS fights (
+    id INTEGER PRIMARY KEY,
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    start_time INTEGER NOT NULL,
+    end_time INTEGER,
+    duration INTEGER,
+    zone_id INTEGER,
+    zone_name TEXT,
+    boss_name TEXT,
+    boss_monster_id INTEGER,
+    outcome TEXT,
+    trial_id INTEGER,
+    trial_score INTEGER
+);
+CREATE TABLE IF NOT EXISTS units (
+    id INTEGER PRIMARY KEY,
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    unit_id INTEGER NOT NULL,
+    added_time INTEGER NOT NULL,
+    unit_type TEXT NOT NULL,
+    monster_id INTEGER NOT NULL,
+    is_boss INTEGER NOT NULL,
+    is_local_player INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    display_name TEXT,
+    character_id INTEGER NOT NULL,
+    class TEXT NOT NULL,
+    race TEXT NOT NULL,
+    level INTEGER NOT NULL,
+    champion_points INTEGER NOT NULL,
+    owner_unit_id INTEGER NOT NULL,
+    reaction TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS players (
+    id INTEGER PRIMARY KEY,
+    unit INTEGER NOT NULL REFERENCES units(id),
+    time INTEGER NOT NULL,
+    long_term_effect_ids TEXT NOT NULL,
+    primary_abilities TEXT NOT NULL,
+    backup_abilities TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS gear (
+    player_id INTEGER NOT NULL REFERENCES players(id),
+    slot TEXT NOT NULL,
+    item_id INTEGER NOT NULL,
+    set_id INTEGER,
+    gear_trait TEXT,
+    quality TEXT NOT NULL,
+    enchant TEXT,
+    enchant_quality TEXT,
+    effective_level INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS abilities (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    icon TEXT NOT NULL,
+    interruptible INTEGER NOT NULL,
+    blockable INTEGER NOT NULL,
+    scribing_focus TEXT,
+    scribing_signature TEXT,
+    scribing_affix TEXT
+);
+CREATE TABLE IF NOT EXISTS combat_events (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    fight_id INTEGER REFERENCES fights(id),
+    time INTEGER NOT NULL,
+    result TEXT NOT NULL,
+    damage_type TEXT NOT NULL,
+    power_type INTEGER NOT NULL,
+    hit_value INTEGER NOT NULL,
+    overflow INTEGER NOT NULL,
+    cast_track_id INTEGER NOT NULL,
+    ability_id INTEGER NOT NULL,
+    source_unit INTEGER REFERENCES units(id),
+    source_health INTEGER NOT NULL,
+    source_max_health INTEGER NOT NULL,
+    target_unit INTEGER REFERENCES units(id),
+    target_health INTEGER NOT NULL,
+    target_max_health INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS effect_changes (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    fight_id INTEGER REFERENCES fights(id),
+    time INTEGER NOT NULL,
+    change_type TEXT NOT NULL,
+    stack_count INTEGER NOT NULL,
+    cast_track_id INTEGER NOT NULL,
+    ability_id INTEGER NOT NULL,
+    source_unit INTEGER REFERENCES units(id),
+    target_unit INTEGER REFERENCES units(id)
+);
+CREATE INDEX IF NOT EXISTS sessions_log_file ON sessions(log_file_id);
+CREATE INDEX IF NOT EXISTS fights_session ON fights(session_id);
+CREATE INDEX IF NOT EXISTS units_session ON units(session_id, unit_id);
+CREATE INDEX IF NOT EXISTS units_display_name ON units(display_name);
+CREATE INDEX IF NOT EXISTS players_unit ON players(unit);
+CREATE INDEX IF NOT EXISTS gear_player ON gear(player_id);
+CREATE INDEX IF NOT EXISTS combat_events_session ON combat_events(session_id);
+CREATE INDEX IF NOT EXISTS combat_events_fight ON combat_events(fight_id);
+CREATE INDEX IF NOT EXISTS combat_events_source ON combat_events(source_unit);
+CREATE INDEX IF NOT EXISTS combat_events_target ON combat_events(target_unit);
+CREATE INDEX IF NOT EXISTS combat_events_ability ON combat_events(ability_id);
+CREATE INDEX IF NOT EXISTS effect_changes_session ON effect_changes(session_id);
+CREATE INDEX IF NOT EXISTS effect_changes_fight ON effect_changes(fight_id);
+CREATE INDEX IF NOT EXISTS effect_changes_target ON effect_changes(target_unit);
+CREATE INDEX IF NOT EXISTS effect_changes_ability ON effect_changes(ability_id);
+";
+
+/// What one call to [`ingest_log_file`] added to the database.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IngestSummary {
+    pub sessions: usize,
+    pub fights: usize,
+    pub units: usize,
+    pub combat_events: usize,
+    pub effect_changes: usize,
+}
+
+/// Opens the database at `path`, creating it and its tables if needed.
+pub fn open_database(path: &Path) -> Result<Connection, String> {
+    let connection = Connection::open(path).map_err(|e| format!("Error opening {}: {e}", path.display()))?;
+    connection.pragma_update(None, "journal_mode", "WAL").map_err(|e| format!("Error opening {}: {e}", path.display()))?;
+    connection.pragma_update(None, "synchronous", "NORMAL").map_err(|e| format!("Error opening {}: {e}", path.display()))?;
+    connection.execute_batch(SCHEMA).map_err(|e| format!("Error creating tables in {}: {e}", path.display()))?;
+    Ok(connection)
+}
+
+/// Adds every BEGIN_LOG in a log file to the database in one transaction.
+///
+/// A file already in the database is skipped when its size and modification time are unchanged, and replaced otherwise,
+/// so the live Encounter.log can be ingested again as it grows. Returns `None` when the file was skipped.
+///
+/// For example, the number of times `@Player` died to Lava Whip this month:
+/// ```sql
+/// SELECT COUNT(*) FROM combat_events e
+/// JOIN units t ON t.id = e.target_unit
+/// JOIN abilities a ON a.id = e.ability_id
+/// WHERE t.display_name = '@Player' AND a.name = 'Lava Whip'
+///   AND e.result IN ('Died', 'DiedXP')
+///   AND e.time >= unixepoch('now', 'start of month') * 1000;
+/// ```
+pub fn ingest_log_file(connection: &mut Connection, path: &Path) -> Result<Option<IngestSummary>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    let metadata = file.metadata().map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let size = metadata.len() as i64;
+    let modified = metadata.modified().ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64);
+    let path_text = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()).to_string_lossy().into_owned();
+    let sql_error = |e: rusqlite::Error| format!("Error ingesting {}: {e}", path.display());
+
+    let transaction = connection.transaction().map_err(sql_error)?;
+    let existing: Option<(i64, i64, i64)> = transaction
+        .query_row("SELECT id, size, modified FROM log_files WHERE path = ?1", [&path_text], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .optional()
+        .map_err(sql_error)?;
+    if let Some((id, old_size, old_modified)) = existing {
+        if (old_size, old_modified) == (size, modified) {
+            return Ok(None);
+        }
+        remove_log_file(&transaction, id).map_err(sql_error)?;
+    }
+    transaction.execute("INSERT INTO log_files (path, size, modified) VALUES (?1, ?2, ?3)", params![path_text, size, modified]).map_err(sql_error)?;
+
+    let mut ingest = Ingest::new(&transaction, transaction.last_insert_rowid());
+    let mut reader = BufReader::new(file);
+    let mut buffer = Vec::new();
+    let mut offset = 0u64;
+    loop {
+        buffer.clear();
+        let length = reader.read_until(b'\n', &mut buffer).map_err(|e| format!("Error reading log file: {e}"))? as u64;
+        if length == 0 {break}
+        let text = String::from_utf8_lossy(&buffer);
+        let text = text.trim_end();
+        if let Ok(line) = LogLine::parse(text) {
+            ingest.handle_line(text, &line, offset, length).map_err(sql_error)?;
+        }
+        offset += length;
+    }
+    let summary = ingest.finish().map_err(sql_error)?;
+
+    transaction.commit().map_err(sql_error)?;
+    Ok(Some(summary))
+}
+
+fn remove_log_file(transaction: &Transaction, log_file_id: i64) -> rusqlite::Result<()> {
+    let sessions = "SELECT id FROM sessions WHERE log_file_id = ?1";
+    transaction.execute(&format!("DELETE FROM gear WHERE player_id IN (SELECT p.id FROM players p JOIN units u ON u.id = p.unit WHERE u.session_id IN ({sessions}))"), [log_file_id])?;
+    transaction.execute(&format!("DELETE FROM players WHERE unit IN (SELECT id FROM units WHERE session_id IN ({sessions}))"), [log_file_id])?;
+    for table in ["combat_events", "effect_changes", "units", "fights"] {
+        transaction.execute(&format!("DELETE FROM {table} WHERE session_id IN ({sessions})"), [log_file_id])?;
+    }
+    transaction.execute("DELETE FROM sessions WHERE log_file_id = ?1", [log_file_id])?;
+    transaction.execute("DELETE FROM log_files WHERE id = ?1", [log_file_id])?;
+    Ok(())
+}
+
+/// Inserts the lines of one log file, keeping track of the session, fight and unit rows that later lines refer to.
+struct Ingest<'a> {
+    transaction: &'a Transaction<'a>,
+    log_file_id: i64,
+    tracker: FightTracker,
+    session_id: Option<i64>,
+    log_start_time: u64,
+    /// Row of the fight in progress, filled in once it ends
+    open_fight: Option<i64>,
+    last_fight: Option<i64>,
+    /// Rows of the units currently in the session, by unit id
+    units: HashMap<u32, i64>,
+    summary: IngestSummary,
+}
+
+impl<'a> Ingest<'a> {
+    fn new(transaction: &'a Transaction<'a>, log_file_id: i64) -> Self {
+        Self {
+            transaction,
+            log_file_id,
+            tracker: FightTracker::new(),
+            session_id: None,
+            log_start_time: 0,
+            open_fight: None,
+            last_fight: None,
+            units: HashMap::new(),
+            summary: IngestSummary::default(),
+        }
+    }
+
+    fn handle_line(&mut self, text: &str, line: &LogLine, offset: u64, length: u64) -> rusqlite::Result<()> {
+        let fights_before = self.tracker.fights.len();
+        self.tracker.handle_line(line, offset, length);
+        if self.tracker.fights.len() > fights_before {
+            self.close_fight()?;
+        }
+
+        if let LogLine::BeginLog(begin) = line {
+            self.begin_session(begin)?;
+        }
+        let Some(session_id) = self.session_id else {return Ok(())};
+
+        if self.open_fight.is_none()
+            && let Some(fight) = self.tracker.current_fight() {
+            self.transaction.prepare_cached(
+                "INSERT INTO fights (session_id, start_time, zone_id, zone_name) VALUES (?1, ?2, ?3, ?4)"
+            )?.execute(params![session_id, self.unix_time(fight.start_time), fight.zone_id, fight.zone_name])?;
+            self.open_fight = Some(self.transaction.last_insert_rowid());
+            self.summary.fights += 1;
+        }
+
+        match line {
+            LogLine::UnitAdded(unit) => self.add_unit(session_id, unit)?,
+            LogLine::UnitChanged(unit) => self.change_unit(unit)?,
+            LogLine::UnitRemoved { unit_id, .. } => {
+                self.units.remove(unit_id);
+            }
+            LogLine::PlayerInfo(info) => self.add_player_info(info)?,
+            LogLine::AbilityInfo(_) => {
+                if let Ok(ability) = parse::ability(&parse::split_line(text)) {
+                    let scribing = |i: usize| ability.scribing.as_ref().and_then(|s| s.get(i).cloned());
+                    self.transaction.prepare_cached(
+                        "INSERT OR REPLACE INTO abilities (id, name, icon, interruptible, blockable, scribing_focus, scribing_signature, scribing_affix)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+                    )?.execute(params![ability.id, &*ability.name, &*ability.icon, ability.interruptible, ability.blockable, scribing(0), scribing(1), scribing(2)])?;
+                }
+            }
+            LogLine::CombatEvent(event) => {
+                let (source, target) = (&event.source_unit_state, &event.target_unit_state);
+                self.transaction.prepare_cached(
+                    "INSERT INTO combat_events (session_id, fight_id, time, result, damage_type, power_type, hit_value, overflow, cast_track_id, ability_id,
+                    source_unit, source_health, source_max_health, target_unit, target_health, target_max_health)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)"
+                )?.execute(params![
+                    session_id, self.open_fight, self.unix_time(event.time), format!("{:?}", event.result), format!("{:?}", event.damage_type),
+                    event.power_type, event.hit_value, event.overflow, event.cast_track_id, event.ability_id,
+                    self.unit_row(source), source.health, source.max_health, self.unit_row(target), target.health, target.max_health,
+                ])?;
+                self.summary.combat_events += 1;
+            }
+            LogLine::EffectChanged(effect) => {
+                self.transaction.prepare_cached(
+                    "INSERT INTO effect_changes (session_id, fight_id, time, change_type, stack_count, cast_track_id, ability_id, source_unit, target_unit)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+                )?.execute(params![
+                    session_id, self.open_fight, self.unix_time(effect.time), format!("{:?}", effect.change_type), effect.stack_count,
+                    effect.cast_track_id, effect.ability_id, self.unit_row(&effect.source_unit_state), self.unit_row(&effect.target_unit_state),
+                ])?;
+                self.summary.effect_changes += 1;
+            }
+            LogLine::EndTrial(_) => {
+                let fight = self.tracker.current_fight().map(|f| (self.open_fight, f)).or_else(|| self.tracker.fights.last().map(|f| (self.last_fight, f)));
+                if let Some((Some(fight_id), fight)) = fight {
+                    self.transaction.prepare_cached("UPDATE fights SET trial_id = ?2, trial_score = ?3 WHERE id = ?1")?
+                        .execute(params![fight_id, fight.trial_id, fight.trial_score])?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> rusqlite::Result<IngestSummary> {
+        let fights_before = self.tracker.fights.len();
+        self.tracker.close_open_fight();
+        if self.tracker.fights.len() > fights_before {
+            self.close_fight()?;
+        }
+        Ok(self.summary)
+    }
+
+    fn unix_time(&self, time: u64) -> i64 {
+        (self.log_start_time + time) as i64
+    }
+
+    fn unit_row(&self, unit_state: &UnitState) -> Option<i64> {
+        self.units.get(&unit_state.unit_id).copied()
+    }
+
+    fn begin_session(&mut self, begin: &BeginLog) -> rusqlite::Result<()> {
+        self.transaction.prepare_cached(
+            "INSERT INTO sessions (log_file_id, start_time, log_version, realm, language, game_version) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )?.execute(params![self.log_file_id, begin.log_time as i64, begin.log_version, begin.realm, begin.language, begin.game_version])?;
+        self.session_id = Some(self.transaction.last_insert_rowid());
+        self.log_start_time = begin.log_time;
+        self.units.clear();
+        self.summary.sessions += 1;
+        Ok(())
+    }
+
+    /// Fills in the fight row of the fight the tracker has just closed.
+    fn close_fight(&mut self) -> rusqlite::Result<()> {
+        let (Some(fight_id), Some(fight)) = (self.open_fight.take(), self.tracker.fights.last()) else {return Ok(())};
+        self.update_fight(fight_id, fight)?;
+        self.last_fight = Some(fight_id);
+        Ok(())
+    }
+
+    fn update_fight(&self, fight_id: i64, fight: &Fight) -> rusqlite::Result<()> {
+        self.transaction.prepare_cached(
+            "UPDATE fights SET end_time = ?2, duration = ?3, boss_name = ?4, boss_monster_id = ?5, outcome = ?6, trial_id = ?7, trial_score = ?8 WHERE id = ?1"
+        )?.execute(params![
+            fight_id, self.unix_time(fight.end_time), fight.duration() as i64, fight.boss_name(), fight.bosses.first().map(|b| b.monster_id),
+            format!("{:?}", fight.outcome), fight.trial_id, fight.trial_score,
+        ])?;
+        Ok(())
+    }
+
+    fn add_unit(&mut self, session_id: i64, unit: &UnitAdded) -> rusqlite::Result<()> {
+        self.transaction.prepare_cached(
+            "INSERT INTO units (session_id, unit_id, added_time, unit_type, monster_id, is_boss, is_local_player, name, display_name, character_id,
+            class, race, level, champion_points, owner_unit_id, reaction)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)"
+        )?.execute(params![
+            session_id, unit.unit_id, self.unix_time(unit.time), format!("{:?}", unit.unit_type), unit.monster_id, unit.is_boss, unit.is_local_player,
+            unit.name, Some(unit.display_name).filter(|n| !n.is_empty()), unit.character_id as i64,
+            format!("{:?}", unit.class_id), format!("{:?}", unit.race_id), unit.level, unit.champion_points, unit.owner_unit_id, format!("{:?}", unit.reaction),
+        ])?;
+        self.units.insert(unit.unit_id, self.transaction.last_insert_rowid());
+        self.summary.units += 1;
+        Ok(())
+    }
+
+    fn change_unit(&self, unit: &UnitChanged) -> rusqlite::Result<()> {
+        let Some(row) = self.units.get(&unit.unit_id) else {return Ok(())};
+        self.transaction.prepare_cached(
+            "UPDATE units SET name = ?2, display_name = ?3, level = ?4, champion_points = ?5, owner_unit_id = ?6, reaction = ?7 WHERE id = ?1"
+        )?.execute(params![
+            row, unit.name, Some(unit.display_name).filter(|n| !n.is_empty()), unit.level, unit.champion_points, unit.owner_unit_id, format!("{:?}", unit.reaction),
+        ])?;
+        Ok(())
+    }
+
+    /// Stores a PLAYER_INFO snapshot and the gear in it. Pieces [`parse::gear_piece`] can't read are left out.
+    fn add_player_info(&self, info: &PlayerInfo) -> rusqlite::Result<()> {
+        let Some(unit_row) = self.units.get(&info.unit_id) else {return Ok(())};
+        self.transaction.prepare_cached(
+            "INSERT INTO players (unit, time, long_term_effect_ids, primary_abilities, backup_abilities) VALUES (?1, ?2, ?3, ?4, ?5)"
+        )?.execute(params![unit_row, self.unix_time(info.time), info.long_term_effect_ids, info.primary_abilities, info.backup_abilities])?;
+        let player_id = self.transaction.last_insert_rowid();
+
+        let mut insert_gear = self.transaction.prepare_cached(
+            "INSERT INTO gear (player_id, slot, item_id, set_id, gear_trait, quality, enchant, enchant_quality, effective_level)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+        )?;
+        for (piece, slot) in info.gear.iter().filter_map(|g| parse::gear_piece(g).ok().flatten()) {
+            insert_gear.execute(params![
+                player_id, format!("{slot:?}"), piece.item_id, piece.set_id, piece.gear_trait.map(|t| format!("{t:?}")), format!("{:?}", piece.quality),
+                piece.enchant.as_ref().map(|e| format!("{:?}", e.glyph)), piece.enchant.as_ref().map(|e| format!("{:?}", e.quality)), piece.effective_level,
+            ])?;
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,11 @@
-use std::{collections::{HashMap, HashSet}, error::Error, fs::File, io::{BufRead, BufReader, BufWriter}, path::Path, sync::{Arc, atomic::{AtomicBool, Ordering}}, u16};
+use std::{collections::{BTreeMap, HashMap, HashSet}, error::Error, fs::File, io::{BufRead, BufReader, BufWriter}, path::Path, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}, mpsc}, thread, u16};
 use std::io::Write;
 // use esosim::{data::{critical_damage::LUCENT_ECHOES_ID, item_type::GearSlot, major_minor::SAVAGERY_MINOR_ID}, engine::player::character::Character, models::player::{ActiveBar, GearPiece}};
-use parser::{EventType, UnitAddedEventType, effect::{self, StatusEffectType}, event::{self, CastEndReason, DamageType, EventResult, is_damage_event, parse_cast_end_reason}, parse::{self}, player::{Class, Race}, set::get_caused_by_id, unit::{self, Reaction, UnitState, blank_unit_state}};
+use parser::{EventType, UnitAddedEventType, effect::{self, StatusEffectType}, event::{self, CastEndReason, DamageType, EventResult, is_damage_event, parse_cast_end_reason}, error::ParseReport, index::{LogContext, is_indexed}, line::LogLine, parse::{self}, player::{Class, Race}, set::get_caused_by_id, unit::{self, Reaction, UnitState, blank_unit_state}};
 use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
 use std::fs;
 
-use crate::{esologs_format::*, log_edit::{handle_line, CustomLogData}};
+use crate::{esologs_format::*, log_edit::{handle_line, LogFixes}};
 
 pub fn event_timestamp(e: &ESOLogsEvent) -> Option<u64> {
     match e {
@@ -32,6 +32,9 @@ pub fn event_timestamp(e: &ESOLogsEvent) -> Option<u64> {
 // const SWAP_WEAPONS_FRONTBAR: u32 = 61874;
 // const SWAP_WEAPONS_BACKBAR: u32 = 61875;
 
+/// How long after a unit's death another death of the same unit is skipped
+const DEATH_COOLDOWN: u64 = 3000;
+
 pub struct ESOLogProcessor {
     pub eso_logs_log: ESOLogsLog,
     pub megaserver: Arc<str>,
@@ -42,8 +45,12 @@ pub struct ESOLogProcessor {
     last_interrupt: Option<u32>, // unit_id of last interrupted unit
     base_timestamp: Option<u64>,
     most_recent_begin_log_timestamp: Option<u64>,
+    /// Unix time in milliseconds of the first BEGIN_LOG line
+    pub start_timestamp: Option<u64>,
     zone: Option<u16>,
     in_combat: bool,
+    /// Lines that could not be parsed are skipped and recorded here
+    pub parse_report: ParseReport,
 }
 
 impl Default for ESOLogProcessor {
@@ -82,8 +89,10 @@ impl ESOLogProcessor {
             last_interrupt: None,
             base_timestamp: None,
             most_recent_begin_log_timestamp: None,
+            start_timestamp: None,
             zone: None,
             in_combat: false,
+            parse_report: ParseReport::default(),
         }
         // let crit_damage = ESOLogsBuff {
         //     name: "Critical Damage".into(),
@@ -441,42 +450,31 @@ impl ESOLogProcessor {
         reaction.unwrap_or(Reaction::None)
     }
 
+    /// Converts every line of `file_path` on a pool of threads and merges the results into this processor
     pub fn convert_log_file_to_esolog_format(&mut self, file_path: &Path) -> Result<(), Box<dyn Error>> {
-        let file: File = File::open(file_path)?;
-        let reader = BufReader::new(file);
-        let mut lines = 0;
-        let mut custom_log_data = CustomLogData::new();
-
-        for line_result in reader.lines() {
-            let line = match line_result {
-                Ok(l) => l,
-                Err(e) => {
-                    log::warn!("Error reading line: {e}");
-                    continue;
-                }
-            };
-            let modified_line = handle_line(line, &mut custom_log_data);
-            for line in modified_line {
-                self.handle_line(line);
-            }
-            lines += 1;
-            if lines % 250_000 == 0 {
-                log::info!("Processed {lines} lines");
-                log::info!("Length of stuff: buffs:{}, effects:{}, units:{}, lines:{}", self.eso_logs_log.buffs.len(), self.eso_logs_log.effects.len(), self.eso_logs_log.units.len(), self.eso_logs_log.events.len());
-            }
-        }
-
+        let never_cancel = AtomicBool::new(false);
+        convert_in_parallel(self, file_path, false, |_| {}, &never_cancel, |elp, events| {
+            elp.eso_logs_log.events.extend(events);
+            Ok(())
+        })?;
+        log::info!("Length of stuff: buffs:{}, effects:{}, units:{}, lines:{}", self.eso_logs_log.buffs.len(), self.eso_logs_log.effects.len(), self.eso_logs_log.units.len(), self.eso_logs_log.events.len());
         Ok(())
     }
 
     pub fn handle_line(&mut self, line: String) {
-        let parts: Vec<String> = parser::parse::handle_line(&line);
+        let parts: Vec<&str> = parser::parse::split_line(&line);
 
         self.parse_line(&parts);
     }
 
-    fn parse_line(&mut self, parts: &[String]) {
-        let event = parts.get(1).map(|s| EventType::from(s.as_str())).unwrap_or(EventType::Unknown);
+    fn parse_line(&mut self, parts: &[&str]) {
+        self.parse_report.lines += 1;
+        if let Err(e) = LogLine::from_parts(parts) {
+            log::debug!("Skipping line: {e}");
+            self.parse_report.record(e.at_line(self.parse_report.lines));
+            return;
+        }
+        let event = parts.get(1).map(|s| EventType::from(*s)).unwrap_or(EventType::Unknown);
 
         let r = match event {
             EventType::BeginLog      => self.handle_begin_log(parts),
@@ -496,6 +494,15 @@ impl ESOLogProcessor {
             EventType::UnitChanged   => self.handle_unit_changed(parts),
             EventType::EndCast       => self.handle_end_cast(parts),
             EventType::UnitRemoved   => Ok(()),
+            EventType::EndLog
+            | EventType::TrialInit
+            | EventType::BeginTrial
+            | EventType::EndlessDungeonInit
+            | EventType::EndlessDungeonBegin
+            | EventType::EndlessDungeonEnd
+            | EventType::EndlessDungeonStageEnd
+            | EventType::EndlessDungeonBuffAdded
+            | EventType::EndlessDungeonBuffRemoved => Ok(()),
             EventType::Unknown       => {log::debug!("Unknown log line:\n{parts:?}"); Ok(())}
         };
         match r {
@@ -525,7 +532,7 @@ impl ESOLogProcessor {
         Some(if flip { (second, first) } else { (first, second) })
     }
 
-    fn handle_begin_log(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_begin_log(&mut self, parts: &[&str]) -> Result<(), String> {
         self.megaserver = parts[4].to_owned().into();
 
         let log_ts = parts[2]
@@ -542,11 +549,14 @@ impl ESOLogProcessor {
         if self.base_timestamp.is_none() {
             self.base_timestamp = Some(log_ts);
         }
+        if self.start_timestamp.is_none() {
+            self.start_timestamp = Some(log_ts + rel_ticks);
+        }
 
         Ok(())
     }
 
-    fn handle_end_combat(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_end_combat(&mut self, parts: &[&str]) -> Result<(), String> {
         let rel_ticks = parts[0]
             .parse::<u64>()
             .map_err(|e| format!("Failed to parse end combat timestamp: {e}"))?;
@@ -578,7 +588,7 @@ impl ESOLogProcessor {
     //     self.eso_logs_log.penetration.clear();
     // }
 
-    fn handle_begin_combat(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_begin_combat(&mut self, parts: &[&str]) -> Result<(), String> {
         let rel_ticks = parts[0]
             .parse::<u64>()
             .map_err(|e| format!("Failed to parse begin combat timestamp: {e}"))?;
@@ -601,14 +611,14 @@ impl ESOLogProcessor {
     const BOSS_CLASS_ID: u8 = 100;
     const PET_CLASS_ID: u8 = 50;
     const OBJECT_CLASS_ID: u8 = 0;
-    fn handle_unit_added(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_unit_added(&mut self, parts: &[&str]) -> Result<(), String> {
         let event = parts.get(3)
+        .copied()
         .ok_or("Missing field at index 3 for UnitAddedEventType")?
-        .as_str()
         .into();
         match event {
             UnitAddedEventType::Player => {
-                let player = parse::player(parts);
+                let player = parse::player(parts).map_err(|e| format!("Failed to parse player: {e}"))?;
 
                 let mut unit = ESOLogsUnit {
                     name: player.name.trim_matches('"').into(),
@@ -643,13 +653,7 @@ impl ESOLogProcessor {
 
                 self.map_unit_id_to_monster_id(player.unit_id, &unit);
                 self.eso_logs_log.shield_values.insert(player.unit_id, 0);
-                if let Some(unit_index) = self.eso_logs_log.session_id_to_units_index.get(&unit.unit_id) {
-                    if let Some(player) = self.eso_logs_log.units.get_mut(*unit_index) {
-                        if player.name == "Offline".into() {
-                            *player = unit.clone();
-                        }
-                    }
-                }
+                self.eso_logs_log.replace_offline_unit(&unit);
                 self.eso_logs_log.players.insert(player.unit_id, true);
                 self.eso_logs_log.players.insert(player.player_per_session_id, true);
                 let index = self.add_unit(unit);
@@ -657,7 +661,7 @@ impl ESOLogProcessor {
                 // self.eso_logs_log.esosim_characters.insert(player.unit_id, Character::new(player.unit_id.clone()));
             }
             UnitAddedEventType::Monster => {
-                let monster = parse::monster(parts);
+                let monster = parse::monster(parts).map_err(|e| format!("Failed to parse monster: {e}"))?;
                 let unit = ESOLogsUnit {
                     name: monster.name.trim_matches('"').into(),
                     player_data: None,
@@ -698,7 +702,7 @@ impl ESOLogProcessor {
                 }
             }
             UnitAddedEventType::Object | UnitAddedEventType::SiegeWeapon => {
-                let object = parse::object(parts);
+                let object = parse::object(parts).map_err(|e| format!("Failed to parse object: {e}"))?;
                 let unit = ESOLogsUnit {
                     name: object.name.trim_matches('"').into(),
                     player_data: None,
@@ -721,7 +725,7 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_player_info(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_player_info(&mut self, parts: &[&str]) -> Result<(), String> {
         let length = parts.len();
         if length < 8 {
             log::warn!("Invalid PLAYER_INFO line: {parts:?}");
@@ -767,8 +771,8 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_ability_info(&mut self, parts: &[String]) -> Result<(), String> {
-        let ability = parse::ability(parts);
+    fn handle_ability_info(&mut self, parts: &[&str]) -> Result<(), String> {
+        let ability = parse::ability(parts).map_err(|e| format!("Failed to parse ability: {e}"))?;
         let interruptible_blockable = (ability.interruptible as u8) * 2 + (ability.blockable as u8);
         let damage_type = DamageType::None;
         let buff = ESOLogsBuff {
@@ -784,9 +788,14 @@ impl ESOLogProcessor {
         Ok(())
     }
 
+    /// Whether a unit's shield changing from `stored` to `shield` is put down to `ability_id`
+    fn shield_changed(stored: u32, shield: u32, ability_id: u32) -> bool {
+        shield != stored || ability_id == 146311 /* frost safeguard */
+    }
+
     fn update_shield_history(esolog: &mut ESOLogsLog, unit_id: u32, shield: u32, buff_event: &ESOLogsBuffEventKey2, ability_id: u32) -> bool {
         let units_stored_shield = *esolog.shield_values.get(&unit_id).unwrap_or(&0);
-        if shield != units_stored_shield || ability_id == 146311 /* frost safeguard */ {
+        if Self::shield_changed(units_stored_shield, shield, ability_id) {
             // log::trace!("Comparing shields for unit {}: {} new vs stored {}", unit_id, shield, units_stored_shield);
             if let Some(shield_buffs_for_unit) = esolog.shields.get_mut(&unit_id) {
                 shield_buffs_for_unit.insert(buff_event.buff_index, buff_event.clone());
@@ -803,14 +812,14 @@ impl ESOLogProcessor {
         return false
     }
 
-    fn handle_combat_event(&mut self, parts: &[String]) -> Result<(), String> {
-        let source = parse::unit_state(parts, 9);
+    fn handle_combat_event(&mut self, parts: &[&str]) -> Result<(), String> {
+        let source = parse::unit_state(parts, 9).map_err(|e| format!("Failed to parse unit state: {e}"))?;
         let target = if parts[19] == "*" {
             source
         } else {
-            parse::unit_state(parts, 19)
+            parse::unit_state(parts, 19).map_err(|e| format!("Failed to parse unit state: {e}"))?
         };
-        let result = event::parse_event_result(&parts[2]).ok_or_else(|| "Failed to parse combat event_result".to_string())?;
+        let result = event::parse_event_result(parts[2]).ok_or_else(|| "Failed to parse combat event_result".to_string())?;
         if is_damage_event(result) && !self.in_combat {return Ok(())}
         let mut ability_id = parts[8].parse().map_err(|e| format!("Failed to parse ability_id: {e}"))?;
         if ability_id == 0 && result == EventResult::SoulGemResurrectionAccepted {ability_id = 26770}
@@ -826,7 +835,7 @@ impl ESOLogProcessor {
         let ev = event::Event {
             time: timestamp,
             result,
-            damage_type: event::parse_damage_type(&parts[3]),
+            damage_type: event::parse_damage_type(parts[3]),
             power_type: parts[4].parse().map_err(|e| format!("Failed to parse power_type: {e}"))?,
             hit_value: parts[5].parse().map_err(|e| format!("Failed to parse hit_value: {e}"))?,
             overflow: parts[6].parse().map_err(|e| format!("Failed to parse overflow: {e}"))?,
@@ -944,6 +953,7 @@ impl ESOLogProcessor {
                                         damage_source_allegiance: source_allegiance,
                                         unit_instance_id: instance_ids,
                                         orig_shield_instance_ids: shield_instance_ids,
+                                        damage_source_unit_index: buff_event.source_unit_index,
                                         hit_value: ev.hit_value,
                                         source_ability_cast_index: None,
                                         damage_source_caster_index: None,
@@ -1071,7 +1081,7 @@ impl ESOLogProcessor {
                 }
                 self.temporary_damage_buffer.insert(target.unit_id, 0);
                 if should_add_death_event {
-                    if timestamp - self.last_death_events.get(&buff_event.target_unit_index).unwrap_or(&0) < 3000 {return Ok(())}
+                    if timestamp.saturating_sub(*self.last_death_events.get(&buff_event.target_unit_index).unwrap_or(&0)) < DEATH_COOLDOWN {return Ok(())}
                     self.last_death_events.insert(buff_event.target_unit_index, timestamp);
                     if source_allegiance == 32 {source_allegiance = 64} // if it's not an enemy then it is a friend (edge case)
                     self.add_log_event(ESOLogsEvent::CastLine(
@@ -1230,7 +1240,7 @@ impl ESOLogProcessor {
             EventResult::Died | EventResult::DiedXP => {
                 if !dont_skip_enemy_id {
                     let instance_ids = (self.index_in_session(source.unit_id).unwrap_or(0), self.index_in_session(target.unit_id).unwrap_or(0));
-                    if timestamp - self.last_death_events.get(&buff_event.target_unit_index).unwrap_or(&0) < 3000 {return Ok(())}
+                    if timestamp - self.last_death_events.get(&buff_event.target_unit_index).unwrap_or(&0) < DEATH_COOLDOWN {return Ok(())}
                     self.last_death_events.insert(buff_event.target_unit_index, timestamp);
                     if source_allegiance == 32 {source_allegiance = 64} // if it's not an enemy then it is a friend (edge case)
                     self.add_log_event(ESOLogsEvent::CastLine(
@@ -1280,12 +1290,12 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_begin_cast(&mut self, parts: &[String]) -> Result<(), String> {
-        let source = parse::unit_state(parts, 6);
+    fn handle_begin_cast(&mut self, parts: &[&str]) -> Result<(), String> {
+        let source = parse::unit_state(parts, 6).map_err(|e| format!("Failed to parse unit state: {e}"))?;
         let target = if parts[16] == "*" {
             source
         } else {
-            parse::unit_state(parts, 16)
+            parse::unit_state(parts, 16).map_err(|e| format!("Failed to parse unit state: {e}"))?
         };
         let ability_id = parts[5].parse().map_err(|e| format!("Failed to parse ability_id: {e}"))?;
         // if let Some(character) = self.eso_logs_log.esosim_characters.get_mut(&source.unit_id) {
@@ -1362,14 +1372,14 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_effect_changed(&mut self, parts: &[String]) -> Result<(), String> {
-        let source = parse::unit_state(parts, 6);
+    fn handle_effect_changed(&mut self, parts: &[&str]) -> Result<(), String> {
+        let source = parse::unit_state(parts, 6).map_err(|e| format!("Failed to parse unit state: {e}"))?;
         if parts.len() == 16 {log::error!("{parts:?}")}
         let target_equal_source = parts[16] == "*";
         let target = if target_equal_source {
             source
         } else {
-            parse::unit_state(parts, 16)
+            parse::unit_state(parts, 16).map_err(|e| format!("Failed to parse unit state: {e}"))?
         };
         let ability_id = parts[5].parse().map_err(|e| format!("Failed to parse timestamp: {e}"))?;
         let mut buff_event= ESOLogsBuffEvent {
@@ -1538,7 +1548,7 @@ impl ESOLogProcessor {
     //     // debug_assert!(diff >= 0 || target < 12000);
     // }
 
-    fn handle_map_changed(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_map_changed(&mut self, parts: &[&str]) -> Result<(), String> {
         let zone_id = parts[2].parse().unwrap_or(0);
         let zone_name: Arc<str> = parts[3].to_string().trim_matches('"').into();
         let map_url = parts[4].trim_matches('"').to_lowercase().into();
@@ -1553,7 +1563,7 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_zone_changed(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_zone_changed(&mut self, parts: &[&str]) -> Result<(), String> {
         let zone_id: u16 = parts[2].parse().unwrap_or(0);
         let zone_name = parts[3].to_string().trim_matches('"').into();
         let difficulty: String = parts[4].trim_matches('"').into();
@@ -1578,10 +1588,10 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_trial_end(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_trial_end(&mut self, parts: &[&str]) -> Result<(), String> {
         let id = parts[2].parse::<u32>().unwrap_or(0);
         let duration = parts[3].parse::<u64>().unwrap_or(0);
-        let success = parse::is_true(&parts[4]);
+        let success = parse::is_true(parts[4]);
         let final_score = parts[5].parse::<u32>().unwrap_or(0);
         let timestamp = self.calculate_timestamp(parts[0].parse::<u64>().map_err(|e| format!("Failed to parse timestamp: {e}"))?);
         self.add_log_event(ESOLogsEvent::EndTrial(
@@ -1598,8 +1608,8 @@ impl ESOLogProcessor {
     }
 
     const HEALTH_RECOVERY_BUFF_ID: u32 = 61322;
-    fn handle_health_recovery(&mut self, parts: &[String]) -> Result<(), String> {
-        let source = parse::unit_state(parts, 3);
+    fn handle_health_recovery(&mut self, parts: &[&str]) -> Result<(), String> {
+        let source = parse::unit_state(parts, 3).map_err(|e| format!("Failed to parse unit state: {e}"))?;
         let source_id = self.unit_index(source.unit_id).ok_or_else(|| format!("health_recovery source_index {} is out of bounds", source.unit_id))?;
         let buff_index = self.buff_index(Self::HEALTH_RECOVERY_BUFF_ID).expect("health_recovery_buff_index should always exist");
         let mut buff_event = ESOLogsBuffEvent {
@@ -1622,10 +1632,10 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_effect_info(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_effect_info(&mut self, parts: &[&str]) -> Result<(), String> {
         let effect_id: u32 = parts[2].parse().map_err(|e| format!("Failed to parse effect_id: {e}"))?;
         // let effect_type = effect::parse_effect_type(parts[3]);
-        let status_effect_type = effect::parse_status_effect_type(&parts[4]);
+        let status_effect_type = effect::parse_status_effect_type(parts[4]);
         if let Some(&idx) = self.eso_logs_log.buffs_hashmap.get(&effect_id) {
             if let Some(buff) = self.eso_logs_log.buffs.get_mut(idx) {
                 buff.status_type = status_effect_type;
@@ -1634,14 +1644,14 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_unit_changed(&mut self, parts: &[String]) -> Result<(), String> {
+    fn handle_unit_changed(&mut self, parts: &[&str]) -> Result<(), String> {
         let unit_id = parts[2]
             .parse()
             .map_err(|e| format!("Failed to parse unit_id: {e}"))?;
 
         if let Some(idx) = self.unit_index(unit_id) {
             let unit = &mut self.eso_logs_log.units[idx];
-            unit.unit_type = unit::match_reaction(&parts[11]);
+            unit.unit_type = unit::match_reaction(parts[11]);
         }
         if parts.len() > 6 {
             let id = parts[7].trim_matches('"');
@@ -1650,13 +1660,13 @@ impl ESOLogProcessor {
             if char_id > 0 {
                 if let Some(unit_index) = self.eso_logs_log.unit_id_to_units_index.get(&parts[2].parse::<u32>().map_err(|e| format!("Failed to parse unit_id: {e}"))?) {
                     if let Some(unit) = self.eso_logs_log.units.get_mut(*unit_index) {
-                        unit.name = parts[5].clone().trim_matches('"').into();
+                        unit.name = parts[5].trim_matches('"').into();
                         let logging = if let Some(data) = unit.player_data.as_ref() {
                             data.is_logging_player
                         } else {
                             false
                         };
-                        unit.player_data = Some(ESOLogsPlayerSpecificData { username: parts[6].clone().trim_matches('"').into(), character_id: char_id, is_logging_player: logging });
+                        unit.player_data = Some(ESOLogsPlayerSpecificData { username: parts[6].trim_matches('"').into(), character_id: char_id, is_logging_player: logging });
                         // println!("{char_id}");
                     }
                 }
@@ -1665,14 +1675,14 @@ impl ESOLogProcessor {
         Ok(())
     }
 
-    fn handle_end_cast(&mut self, parts: &[String]) -> Result<(), String> {
-        let end_reason = parse_cast_end_reason(&parts[2]);
+    fn handle_end_cast(&mut self, parts: &[&str]) -> Result<(), String> {
+        let end_reason = parse_cast_end_reason(parts[2]);
         let timestamp = self.calculate_timestamp(parts[0].parse::<u64>().map_err(|e| format!("Failed to parse timestamp: {e}"))?);
         if end_reason == Some(CastEndReason::Interrupted) {
             let interrupted_cast_id = parts[3].parse::<u32>().map_err(|e| format!("Failed to parse interrupted_cast_id: {e}"))?;
             let interrupted_ability = parts[4].parse::<u32>().map_err(|e| format!("Failed to parse interrupted_ability_id: {e}"))?;
-            let interrupting_ability = parts[5].parse::<u32>().map_err(|e| format!("Failed to parse interrupting_ability_id: {e}"))?;
-            let interrupting_unit = parts[6].parse::<u32>().map_err(|e| format!("Failed to parse interrupting_unit_id: {e}"))?; // can be zero sometimes
+            let interrupting_ability = parts.get(5).ok_or("Missing interrupting_ability_id")?.parse::<u32>().map_err(|e| format!("Failed to parse interrupting_ability_id: {e}"))?;
+            let interrupting_unit = parts.get(6).ok_or("Missing interrupting_unit_id")?.parse::<u32>().map_err(|e| format!("Failed to parse interrupting_unit_id: {e}"))?; // can be zero sometimes
             let mut target_id_option = self.eso_logs_log.cast_id_source_unit_id.get(&interrupted_cast_id).cloned();
 
             if interrupting_unit == 0 {return Err("Interrupting unit has id zero".to_string())}
@@ -1798,104 +1808,568 @@ impl ESOLogProcessor {
     }
 }
 
-pub fn split_and_zip_log_by_fight<InputPath, OutputDir, F>(input_path: InputPath, output_dir: OutputDir, mut progress_callback: F, cancel_flag: &AtomicBool) -> Result<(), String> where InputPath: AsRef<Path>, OutputDir: AsRef<Path>, F: FnMut(u8) {
-    if output_dir.as_ref().exists() {
-        fs::remove_dir_all(&output_dir)
-            .map_err(|e| format!("Failed to remove existing output dir: {e}"))?;
+/// A job is cut at the end of the first fight after it reaches this many bytes, so that long BEGIN_LOGs are converted in parallel too.
+/// Jobs are never cut partway through a fight, so a job holds at least the whole of its longest fight
+const JOB_BYTES: usize = 8 * 1024 * 1024;
+
+/// A BEGIN_CAST, or the first EFFECT_CHANGED of a cast that has none, kept for the lines that refer back to it
+struct ContextCast {
+    position: usize,
+    line: String,
+    active_effects: u32,
+    referenced: bool,
+    /// Cast with a cast time that has not ended yet
+    casting: bool,
+}
+
+/// A unit's shield as the converter last recorded it
+#[derive(Clone, Default)]
+struct ContextShield {
+    value: u32,
+    /// By ability, the source and target unit ids and the cast id of the effect line that last changed the shield
+    effects: HashMap<u32, (u32, u32, u32)>,
+}
+
+/// The lines that may be deaths or resurrections of a unit since its last death that was certainly not skipped
+#[derive(Default)]
+struct ContextDeaths {
+    /// Time of the latest line that may be a death
+    last: u64,
+    /// The time of each line and the damage shields had absorbed from the unit just before it
+    lines: Vec<(u64, u32, String)>,
+}
+
+/// The lines a job that starts partway through a BEGIN_LOG has to read first, kept up to date as the log is read
+#[derive(Default)]
+struct JobContext {
+    log: LogContext<String>,
+    /// By cast id. At the end of each fight, casts are forgotten unless they are still being cast, still have active effects or an effect line referred to them during the fight
+    casts: HashMap<u64, ContextCast>,
+    cast_count: usize,
+    /// The ability of every cast since the BEGIN_LOG, as effects can refer back to a cast long after its line was forgotten
+    cast_abilities: HashMap<u32, u32>,
+    /// By unit id, as shields last from one fight into the next
+    shields: HashMap<u32, ContextShield>,
+    /// By unit id, as a death following another of the same unit within [`DEATH_COOLDOWN`] is skipped
+    deaths: BTreeMap<u32, ContextDeaths>,
+    /// By unit id, damage shields absorbed that the converter adds to the next hit on the unit, as it is only cleared by other lines
+    damage_absorbed: HashMap<u32, u32>,
+    in_fight: bool,
+}
+
+impl JobContext {
+    fn add(&mut self, event_type: EventType, id: u64, line: &str) {
+        if is_indexed(event_type) {
+            self.log.add(event_type, id, line.to_owned());
+        }
+        match event_type {
+            EventType::BeginLog => {
+                self.casts.clear();
+                self.cast_abilities.clear();
+                self.shields.clear();
+                self.deaths.clear();
+                self.damage_absorbed.clear();
+                self.in_fight = false;
+            }
+            EventType::BeginCombat => self.in_fight = true,
+            EventType::EndCombat => {
+                self.in_fight = false;
+                self.casts.retain(|_, cast| cast.casting || cast.active_effects > 0 || cast.referenced);
+                for cast in self.casts.values_mut() {
+                    cast.referenced = false;
+                }
+                // the next death of these units is not skipped whatever came before it
+                let time = line.split(',').next().and_then(|time| time.parse::<u64>().ok()).unwrap_or(0);
+                self.deaths.retain(|_, deaths| deaths.last + DEATH_COOLDOWN > time);
+            }
+            EventType::UnitAdded => {
+                let mut fields = line.splitn(5, ',').skip(2);
+                let unit_id = fields.next().and_then(|id| id.parse::<u32>().ok());
+                if let Some(unit_id) = unit_id && matches!(fields.next(), Some("PLAYER" | "MONSTER" | "OBJECT" | "SIEGE_WEAPON")) {
+                    self.shields.entry(unit_id).or_default().value = 0;
+                }
+            }
+            EventType::BeginCast => {
+                let fields: Vec<&str> = line.splitn(7, ',').collect();
+                let cast_id = fields.get(4).and_then(|id| id.parse::<u64>().ok()).unwrap_or(0);
+                if cast_id != 0 {
+                    if let Some(ability_id) = fields.get(5).and_then(|id| id.parse().ok()) {
+                        self.cast_abilities.insert(cast_id as u32, ability_id);
+                    }
+                    let active_effects = self.casts.get(&cast_id).map_or(0, |cast| cast.active_effects);
+                    let casting = fields.get(2).is_some_and(|cast_time| *cast_time != "0");
+                    self.casts.insert(cast_id, ContextCast { position: self.cast_count, line: line.to_owned(), active_effects, referenced: false, casting });
+                    self.cast_count += 1;
+                }
+            }
+            EventType::CombatEvent => {
+                let fields: Vec<&str> = line.splitn(27, ',').collect();
+                let time = fields.first().and_then(|time| time.parse::<u64>().ok()).unwrap_or(0);
+                let target = if fields.get(19) == Some(&"*") { 9 } else { 19 };
+                let target_id = fields.get(target).and_then(|id| id.parse::<u32>().ok()).unwrap_or(0);
+                let number = |index: usize| fields.get(index).and_then(|value| value.parse::<u32>().ok()).unwrap_or(0);
+                let absorbed = self.damage_absorbed.get(&target_id).copied().unwrap_or(0);
+                // follows when the converter reads, and clears, the damage shields absorbed
+                let is_death = match fields.get(2) {
+                    Some(&("DIED" | "DIED_XP")) => {
+                        self.damage_absorbed.clear();
+                        true
+                    }
+                    // damage is only read during a fight
+                    Some(&("DAMAGE" | "DOT_TICK" | "CRITICAL_DAMAGE" | "DOT_TICK_CRITICAL" | "BLOCKED_DAMAGE")) => {
+                        if !self.in_fight || (number(5) == 0 && number(6) == 0 && absorbed == 0) || fields.get(3) == Some(&"GENERIC") {
+                            false
+                        } else {
+                            self.damage_absorbed.insert(target_id, 0);
+                            fields.get(target + 1).is_some_and(|health| health.split('/').next() == Some("0"))
+                        }
+                    }
+                    Some(&"DAMAGE_SHIELDED") => {
+                        let ability_id = number(8);
+                        let shield = fields.get(target + 6).and_then(|shield| shield.parse::<u32>().ok());
+                        if number(5) != 0 {
+                            *self.damage_absorbed.entry(target_id).or_default() += number(5);
+                        }
+                        // damage a shield absorbs only changes its value, and only when the effect that gave it is known
+                        if number(5) != 0 && number(7) != 0 && let Some(shield) = shield && let Some(stored) = self.shields.get_mut(&target_id)
+                            && stored.effects.contains_key(&ability_id) && ESOLogProcessor::shield_changed(stored.value, shield, ability_id) {
+                            stored.value = shield;
+                        }
+                        false
+                    }
+                    Some(&("POWER_ENERGIZE" | "POWER_DRAIN")) => false,
+                    Some(&"SOUL_GEM_RESURRECTION_ACCEPTED") => {
+                        self.damage_absorbed.clear();
+                        if let Some(deaths) = self.deaths.get_mut(&target_id) {
+                            deaths.lines.push((time, absorbed, line.to_owned()));
+                        }
+                        false
+                    }
+                    _ => {
+                        self.damage_absorbed.clear();
+                        false
+                    }
+                };
+                if is_death {
+                    let deaths = self.deaths.entry(target_id).or_default();
+                    // a death this long after the one before is never skipped, so the deaths before it no longer matter
+                    if deaths.last + DEATH_COOLDOWN <= time {
+                        deaths.lines.clear();
+                    }
+                    deaths.last = time;
+                    deaths.lines.push((time, absorbed, line.to_owned()));
+                }
+            }
+            EventType::EndCast => {
+                let cast_id = line.split(',').nth(3).and_then(|id| id.parse::<u64>().ok()).unwrap_or(0);
+                if let Some(cast) = self.casts.get_mut(&cast_id) {
+                    cast.casting = false;
+                }
+            }
+            EventType::EffectChanged => {
+                let fields: Vec<&str> = line.splitn(24, ',').collect();
+                let change = fields.get(2).copied();
+                let cast_id = fields.get(4).and_then(|id| id.parse::<u64>().ok()).unwrap_or(0);
+                if let Some(cast) = self.casts.get_mut(&cast_id) {
+                    cast.referenced = true;
+                    match change {
+                        Some("GAINED") => cast.active_effects += 1,
+                        Some("FADED") => cast.active_effects = cast.active_effects.saturating_sub(1),
+                        _ => {}
+                    }
+                } else if cast_id != 0 && change == Some("GAINED") {
+                    // some casts are only logged as the effect they give, and the log fixes add the BEGIN_CAST
+                    self.casts.insert(cast_id, ContextCast { position: self.cast_count, line: line.to_owned(), active_effects: 1, referenced: true, casting: false });
+                    self.cast_count += 1;
+                }
+
+                let ability_id = fields.get(5).and_then(|id| id.parse::<u32>().ok()).unwrap_or(0);
+                let source_id = fields.get(6).and_then(|id| id.parse::<u32>().ok()).unwrap_or(0);
+                let target = if fields.get(16) == Some(&"*") { 6 } else { 16 };
+                let target_id = fields.get(target).and_then(|id| id.parse::<u32>().ok()).unwrap_or(0);
+                // the converter only records the shields of effects with a source
+                if source_id != 0 {
+                    let effect = (source_id, target_id, cast_id as u32);
+                    self.update_shield(source_id, fields.get(12), ability_id, effect);
+                    self.update_shield(target_id, fields.get(target + 6), ability_id, effect);
+                }
+            }
+            _ => {}
+        }
     }
-    fs::create_dir_all(&output_dir)
-        .map_err(|e| format!("Failed to create output dir: {e}"))?;
-    let timestamps_path = output_dir.as_ref().join("timestamps");
-    if let Err(e) = fs::remove_file(&timestamps_path) {
-        if e.kind() != std::io::ErrorKind::NotFound {
-            return Err(format!("Failed to clear timestamps file: {e}"));
+
+    /// Follows [`ESOLogProcessor::update_shield_history`] for the effect line that gave `unit_id` the shield `shield`
+    fn update_shield(&mut self, unit_id: u32, shield: Option<&&str>, ability_id: u32, effect: (u32, u32, u32)) {
+        let Some(shield) = shield.and_then(|shield| shield.parse::<u32>().ok()) else {return};
+        let stored = self.shields.entry(unit_id).or_default();
+        if ESOLogProcessor::shield_changed(stored.value, shield, ability_id) {
+            stored.value = shield;
+            stored.effects.insert(ability_id, effect);
         }
     }
 
-    let total_lines = {
-        let file = File::open(&input_path)
-            .map_err(|e| format!("Failed to open input file: {e}"))?;
-        BufReader::new(file).lines().count()
-    };
+    /// A job carrying on from here: the BEGIN_LOG, zone, units and abilities, then the casts, shields and deaths that outlast the fight before
+    fn job(&self, index: usize, base_timestamp: Option<u64>) -> ConvertJob {
+        let mut casts: Vec<&ContextCast> = self.casts.values().collect();
+        casts.sort_by_key(|cast| cast.position);
+        let context = self.log.lines().into_iter()
+            .chain(casts.into_iter().map(|cast| &cast.line))
+            .cloned()
+            .collect();
+        let mut deaths: Vec<(u32, &(u64, u32, String))> = self.deaths.iter().flat_map(|(unit_id, deaths)| deaths.lines.iter().map(|line| (*unit_id, line))).collect();
+        deaths.sort_by_key(|(_, (time, _, _))| *time);
+        let recent_deaths = deaths.into_iter().map(|(unit_id, (_, absorbed, line))| (unit_id, *absorbed, line.clone())).collect();
+        ConvertJob {
+            index, context, cast_abilities: self.cast_abilities.clone(), shields: self.shields.clone(), recent_deaths,
+            damage_absorbed: self.damage_absorbed.clone(), lines: Vec::new(), base_timestamp,
+        }
+    }
+}
 
-    let input_file = File::open(&input_path)
-        .map_err(|e| format!("Failed to open input file: {e}"))?;
-    let lines = BufReader::new(input_file).lines();
+/// A stretch of a log converted on one thread. Its lines have been through the log fixes already
+struct ConvertJob {
+    index: usize,
+    /// Lines to read before `lines` when the job starts partway through a BEGIN_LOG: the BEGIN_LOG and the zone, units,
+    /// abilities and casts the lines refer to. Their events were already converted by the job before, so they are thrown away
+    context: Vec<String>,
+    /// The ability of every cast before the job, for the casts no longer in `context`
+    cast_abilities: HashMap<u32, u32>,
+    /// The shields of each unit by unit id when the job starts
+    shields: HashMap<u32, ContextShield>,
+    /// Lines before the job that may be deaths or resurrections, which decide whether the first deaths of the job are skipped,
+    /// with the unit id and the damage shields had absorbed from the unit just before each
+    recent_deaths: Vec<(u32, u32, String)>,
+    /// By unit id, damage shields absorbed that the first hit on each unit in the job adds to
+    damage_absorbed: HashMap<u32, u32>,
+    lines: Vec<String>,
+    base_timestamp: Option<u64>,
+}
+
+impl ConvertJob {
+    /// A job that starts a BEGIN_LOG, so needs no context
+    fn new(index: usize, base_timestamp: Option<u64>) -> Self {
+        Self { index, context: Vec::new(), cast_abilities: HashMap::new(), shields: HashMap::new(), recent_deaths: Vec::new(), damage_absorbed: HashMap::new(), lines: Vec::new(), base_timestamp }
+    }
+}
+
+/// A job converted on its own, alongside its events split per fight or as a whole
+struct ConvertedLog {
+    elp: ESOLogProcessor,
+    segments: Vec<Vec<ESOLogsEvent>>,
+    /// Whether the job carries on the BEGIN_LOG of the job before it
+    continues: bool,
+}
 
+fn convert_log(job: ConvertJob, by_fight: bool) -> ConvertedLog {
     let mut elp = ESOLogProcessor::new();
-    let mut custom_state = CustomLogData::new();
-    let mut fight_index: u16 = 1;
+    elp.base_timestamp = job.base_timestamp;
+    let mut segments = Vec::new();
+    let continues = !job.context.is_empty();
 
-    let mut first_timestamp: Option<u64> = None;
-    let mut current_line: usize = 0;
-    for line in lines {
-        current_line += 1;
-        if current_line % LINE_COUNT_FOR_PROGRESS == 0 {
-            progress_callback(((current_line as f64 / total_lines as f64) * 100.0).round() as u8);
+    if continues {
+        for line in job.context {
+            elp.handle_line(line);
+        }
+        // the deaths are from fights that have since ended
+        let in_combat = std::mem::replace(&mut elp.in_combat, true);
+        for (unit_id, absorbed, line) in job.recent_deaths {
+            elp.temporary_damage_buffer.insert(unit_id, absorbed);
+            elp.handle_line(line);
+        }
+        elp.in_combat = in_combat;
+        elp.temporary_damage_buffer = job.damage_absorbed;
+        for (cast_id, ability_id) in job.cast_abilities {
+            if let Some(buff_index) = elp.buff_index(ability_id) {
+                elp.eso_logs_log.buffs_hashmap.insert(cast_id, buff_index);
+            }
+        }
+        elp.eso_logs_log.shields.clear();
+        elp.eso_logs_log.shield_values.clear();
+        for (unit_id, shield) in job.shields {
+            elp.eso_logs_log.shield_values.insert(unit_id, shield.value);
+            for (ability_id, (source_id, target_id, cast_id)) in shield.effects {
+                let (Some(source_unit_index), Some(target_unit_index), Some(buff_index)) = (elp.unit_index(source_id), elp.unit_index(target_id), elp.buff_index(ability_id)) else {continue};
+                // damage the shield absorbs is put down to this effect
+                elp.add_buff_event(ESOLogsBuffEvent { unique_index: 0, source_unit_index, target_unit_index, buff_index });
+                elp.eso_logs_log.shields.entry(unit_id).or_default().insert(buff_index, ESOLogsBuffEventKey2 {
+                    source_unit_index,
+                    source_unit_id: source_id,
+                    target_unit_index,
+                    target_unit_id: target_id,
+                    buff_index,
+                    source_cast_id: Some(cast_id),
+                });
+            }
+        }
+        elp.eso_logs_log.events.clear();
+        // the icons and damage types the context gave may come from older lines than the ones the job before ended on
+        for unit in &mut elp.eso_logs_log.units {
+            unit.icon = None;
+        }
+        for buff in &mut elp.eso_logs_log.buffs {
+            buff.damage_type = DamageType::None;
+        }
+        elp.eso_logs_log.fight_units.clear();
+        elp.eso_logs_log.unit_index_during_fight.clear();
+        elp.parse_report = ParseReport::default();
+    }
+
+    for line in job.lines {
+        let is_end_combat = by_fight && matches!(line.split(',').nth(1), Some("END_COMBAT") | Some("END_LOG"));
+        elp.handle_line(line);
+        if is_end_combat {
+            elp.remove_overabundant_events();
+            segments.push(std::mem::take(&mut elp.eso_logs_log.events));
+        }
+    }
+
+    if !by_fight {
+        elp.remove_overabundant_events();
+        segments.push(std::mem::take(&mut elp.eso_logs_log.events));
+    }
+    ConvertedLog { elp, segments, continues }
+}
+
+/// Merges `converted` into `master` and hands each of its segments to `on_segment`
+fn merge_converted_log<S>(master: &mut ESOLogProcessor, converted: ConvertedLog, on_segment: &mut S) -> Result<(), String>
+where S: FnMut(&mut ESOLogProcessor, Vec<ESOLogsEvent>) -> Result<(), String> {
+    let ConvertedLog { elp, segments, continues } = converted;
+    let remap = master.eso_logs_log.merge(&elp.eso_logs_log, continues);
+    master.megaserver = elp.megaserver;
+    if master.base_timestamp.is_none() {
+        master.base_timestamp = elp.base_timestamp;
+    }
+    if master.start_timestamp.is_none() {
+        master.start_timestamp = elp.start_timestamp;
+    }
+    for mut error in elp.parse_report.errors {
+        error.line_number = error.line_number.map(|n| n + master.parse_report.lines);
+        master.parse_report.record(error);
+    }
+    master.parse_report.lines += elp.parse_report.lines;
+
+    for mut events in segments {
+        for event in &mut events {
+            remap.event(event);
+        }
+        on_segment(master, events)?;
+    }
+    Ok(())
+}
+
+/// Collects converted logs from the worker threads and merges them in file order
+struct OrderedMerge<S> {
+    results: mpsc::Receiver<(usize, Result<ConvertedLog, String>)>,
+    pending: BTreeMap<usize, ConvertedLog>,
+    received: usize,
+    merged: usize,
+    /// How many jobs may be sent before the oldest of them is received
+    max_in_flight: usize,
+    on_segment: S,
+}
+
+impl<S> OrderedMerge<S> where S: FnMut(&mut ESOLogProcessor, Vec<ESOLogsEvent>) -> Result<(), String> {
+    /// Takes one finished log, waiting for it if `wait` is set, then merges every log that is next in line. Returns whether a log was taken
+    fn receive(&mut self, master: &mut ESOLogProcessor, wait: bool) -> Result<bool, String> {
+        let result = if wait {
+            Some(self.results.recv().map_err(|_| "Conversion threads stopped unexpectedly".to_string())?)
+        } else {
+            self.results.try_recv().ok()
+        };
+        let Some((index, converted)) = result else {return Ok(false)};
+        self.received += 1;
+        self.pending.insert(index, converted?);
+        while let Some(converted) = self.pending.remove(&self.merged) {
+            merge_converted_log(master, converted, &mut self.on_segment)?;
+            self.merged += 1;
+        }
+        Ok(true)
+    }
+}
+
+/// Converts `input_path` on a pool of threads and merges the results into `master` in file order.
+/// Jobs are cut at each BEGIN_LOG and at the end of the first fight after they reach [`JOB_BYTES`], so that they are converted
+/// the same as the whole log would be, and at most two jobs per thread are held in memory.
+/// `on_segment` is given the events of each fight when `by_fight` is set, or of each job otherwise, already pointing into `master`'s tables.
+fn convert_in_parallel<F, S>(master: &mut ESOLogProcessor, input_path: &Path, by_fight: bool, progress_callback: F, cancel_flag: &AtomicBool, on_segment: S) -> Result<(), String>
+where F: FnMut(u8), S: FnMut(&mut ESOLogProcessor, Vec<ESOLogsEvent>) -> Result<(), String> {
+    let input_file = File::open(input_path)
+        .map_err(|e| format!("Failed to open input file: {e}"))?;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let (job_sender, job_receiver) = mpsc::channel::<ConvertJob>();
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let job_receiver = &job_receiver;
+            let result_sender = result_sender.clone();
+            scope.spawn(move || loop {
+                let job = match job_receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => break,
+                };
+                let Ok(job) = job else {break};
+                let index = job.index;
+                let converted = if cancel_flag.load(Ordering::SeqCst) {
+                    Err("Conversion cancelled".to_string())
+                } else {
+                    std::panic::catch_unwind(|| convert_log(job, by_fight))
+                        .map_err(|_| format!("Conversion of part {} of the log panicked", index + 1))
+                };
+                if result_sender.send((index, converted)).is_err() {break}
+            });
+        }
+        drop(result_sender);
+
+        let merge = OrderedMerge {
+            results: result_receiver,
+            pending: BTreeMap::new(),
+            received: 0,
+            merged: 0,
+            max_in_flight: threads * 2,
+            on_segment,
+        };
+        // the job sender is dropped on return, which lets the worker threads finish
+        read_and_merge(master, input_file, by_fight, job_sender, merge, progress_callback, cancel_flag)
+    })?;
+
+    if !master.parse_report.is_clean() {
+        log::warn!("{} of {} lines could not be parsed and were skipped", master.parse_report.errors.len(), master.parse_report.lines);
+    }
+    Ok(())
+}
+
+/// Hands the next job to the worker threads, first merging finished jobs until fewer than [`OrderedMerge::max_in_flight`] are left in progress
+fn send_job<S>(master: &mut ESOLogProcessor, jobs: &mpsc::Sender<ConvertJob>, merge: &mut OrderedMerge<S>, job: ConvertJob) -> Result<(), String>
+where S: FnMut(&mut ESOLogProcessor, Vec<ESOLogsEvent>) -> Result<(), String> {
+    let sent = job.index + 1;
+    jobs.send(job).map_err(|_| "Conversion threads stopped unexpectedly".to_string())?;
+    while sent - merge.received >= merge.max_in_flight {
+        merge.receive(master, true)?;
+    }
+    while merge.receive(master, false)? {}
+    Ok(())
+}
+
+/// Reads the log into jobs for the worker threads. The log fixes are applied here rather than by each job,
+/// as the lines they add change the shields the jobs after carry on from
+fn read_and_merge<F, S>(master: &mut ESOLogProcessor, input_file: File, by_fight: bool, jobs: mpsc::Sender<ConvertJob>, mut merge: OrderedMerge<S>, mut progress_callback: F, cancel_flag: &AtomicBool) -> Result<(), String>
+where F: FnMut(u8), S: FnMut(&mut ESOLogProcessor, Vec<ESOLogsEvent>) -> Result<(), String> {
+    let total_bytes = input_file.metadata().map(|m| m.len()).unwrap_or(0).max(1);
+    let mut reader = BufReader::new(input_file);
+    let mut base_timestamp = master.base_timestamp;
+    let mut job = ConvertJob::new(0, base_timestamp);
+    let mut job_bytes = 0usize;
+    let mut context = JobContext::default();
+    let mut log_fixes = LogFixes::new();
+    let mut buffer = Vec::new();
+    let mut bytes_read = 0u64;
+    let mut line_number = 0usize;
+
+    loop {
+        let read = reader.read_until(b'\n', &mut buffer)
+            .map_err(|e| format!("Read error: {e}"))?;
+        if read == 0 {break}
+        bytes_read += read as u64;
+        line_number += 1;
+        if line_number.is_multiple_of(LINE_COUNT_FOR_PROGRESS) {
+            progress_callback(((bytes_read as f64 / total_bytes as f64) * 100.0).round() as u8);
             if cancel_flag.load(Ordering::SeqCst) {
                 return Err("Upload cancelled".to_string());
             }
         }
 
-        let line = line.map_err(|e| format!("Read error: {e}"))?;
+        while buffer.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            buffer.pop();
+        }
+        let line = match String::from_utf8(std::mem::take(&mut buffer)) {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn!("Skipping line {line_number} that is not valid UTF-8: {}", e.utf8_error());
+                continue;
+            }
+        };
+
         let mut split = line.splitn(4, ',');
-        let first = split.next();
-        let second = split.next();
-        let third = split.next();
-
-        if let Some("BEGIN_LOG") = second {
-            elp.eso_logs_log.new_log_reset();
-            elp.reset();
-            custom_state.reset();
-            if let Some(third_str) = third {
-                if let Ok(ts) = third_str.parse::<u64>() {
-                    if let Some(first_str) = first {
-                        if let Ok(ts2) = first_str.parse::<u64>() {
-                            if first_timestamp.is_none() {first_timestamp = Some(ts + ts2)};
-                        }
-                    }
-                }
+        let event_type = split.nth(1).map_or(EventType::Unknown, EventType::from);
+        let id = split.next().and_then(|id| id.parse::<u64>().ok()).unwrap_or(0);
+
+        if event_type == EventType::BeginLog {
+            if base_timestamp.is_none() {
+                base_timestamp = Some(id);
             }
+            if !job.lines.is_empty() {
+                let next = ConvertJob::new(job.index + 1, base_timestamp);
+                send_job(master, &jobs, &mut merge, std::mem::replace(&mut job, next))?;
+                job_bytes = 0;
+            }
+            log_fixes.reset();
         }
 
-        let is_end_combat = matches!(second, Some("END_COMBAT") | Some("END_LOG"));
-        for l in handle_line(line, &mut custom_state) {
-            elp.handle_line(l.to_string());
+        for fixed in handle_line(line, &mut log_fixes) {
+            let mut split = fixed.splitn(4, ',');
+            let event_type = split.nth(1).map_or(EventType::Unknown, EventType::from);
+            let id = split.next().and_then(|id| id.parse::<u64>().ok()).unwrap_or(0);
+            context.add(event_type, id, &fixed);
+            job.lines.push(fixed);
         }
+        job_bytes += read;
 
-        if is_end_combat {
-            elp.remove_overabundant_events();
-            let seg_zip = output_dir
-                .as_ref()
-                .join(format!("report_segment_{fight_index}.zip"));
-            let seg_data = build_report_segment(&elp);
-            write_zip_with_logtxt(seg_zip, seg_data.as_bytes())?;
-
-            let events = &elp.eso_logs_log.events;
-            if !events.is_empty() {
-                let mut last_ts = event_timestamp(&events[events.len()-1]);
-                if last_ts.is_some() && first_timestamp.is_some() {
-                    last_ts = Some(last_ts.ok_or_else(|| "Failed to unwrap last_timestamp".to_string())? + first_timestamp.ok_or_else(|| "Failed to unwrap first timestamp".to_string())?);
-                }
-                if let (Some(first), Some(last)) = (first_timestamp, last_ts) {
-                    use std::io::Write;
-                    let timestamps_path = output_dir.as_ref().join("timestamps");
-                    let mut file = std::fs::OpenOptions::new()
-                        .create(true)
-                        .append(true)
-                        .open(timestamps_path)
-                        .map_err(|e| format!("Failed to open timestamps file: {e}"))?;
-                    writeln!(file, "{first},{last}")
-                        .map_err(|e| format!("Failed to write timestamps: {e}"))?;
-                }
-            }
+        if by_fight && matches!(event_type, EventType::EndCombat | EventType::EndLog) {
+            log_fixes.reset();
+        }
+        if event_type == EventType::EndCombat && job_bytes >= JOB_BYTES {
+            let next = context.job(job.index + 1, base_timestamp);
+            send_job(master, &jobs, &mut merge, std::mem::replace(&mut job, next))?;
+            job_bytes = 0;
+        }
+    }
 
-            elp.eso_logs_log.events.clear();
-            custom_state.reset();
+    let mut sent = job.index;
+    if !job.lines.is_empty() {
+        jobs.send(job).map_err(|_| "Conversion threads stopped unexpectedly".to_string())?;
+        sent += 1;
+    }
+    while merge.received < sent {
+        merge.receive(master, true)?;
+    }
+    Ok(())
+}
 
-            fight_index += 1;
-        }
+pub fn split_and_zip_log_by_fight<InputPath, OutputDir, F>(input_path: InputPath, output_dir: OutputDir, progress_callback: F, cancel_flag: &AtomicBool) -> Result<(), String> where InputPath: AsRef<Path>, OutputDir: AsRef<Path>, F: FnMut(u8) {
+    if output_dir.as_ref().exists() {
+        fs::remove_dir_all(&output_dir)
+            .map_err(|e| format!("Failed to remove existing output dir: {e}"))?;
     }
+    fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output dir: {e}"))?;
+
+    let mut elp = ESOLogProcessor::new();
+    let mut fight_index: u16 = 1;
+    convert_in_parallel(&mut elp, input_path.as_ref(), true, progress_callback, cancel_flag, |elp, events| {
+        let seg_zip = output_dir
+            .as_ref()
+            .join(format!("report_segment_{fight_index}.zip"));
+        let seg_data = report_segment(&elp.megaserver, &events);
+        write_zip_with_logtxt(seg_zip, seg_data.as_bytes())?;
+
+        if let (Some(first), Some(last_event)) = (elp.start_timestamp, events.last())
+            && let Some(last) = event_timestamp(last_event) {
+            let timestamps_path = output_dir.as_ref().join("timestamps");
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(timestamps_path)
+                .map_err(|e| format!("Failed to open timestamps file: {e}"))?;
+            writeln!(file, "{first},{}", last + first)
+                .map_err(|e| format!("Failed to write timestamps: {e}"))?;
+        }
+
+        fight_index += 1;
+        Ok(())
+    })?;
 
     let tbl_zip = output_dir
         .as_ref()
@@ -1929,16 +2403,20 @@ pub fn write_zip_with_logtxt<P: AsRef<Path>>(zip_path: P, data: &[u8]) -> Result
 }
 
 pub fn build_report_segment(elp: &ESOLogProcessor) -> String {
-    let mut out = String::with_capacity(elp.eso_logs_log.events.len() * 64);
-    let server_id = match elp.megaserver.as_ref() {
+    report_segment(&elp.megaserver, &elp.eso_logs_log.events)
+}
+
+fn report_segment(megaserver: &str, events: &[ESOLogsEvent]) -> String {
+    let mut out = String::with_capacity(events.len() * 64);
+    let server_id = match megaserver {
         "NA Megaserver" | "XB1live-na" => {1}
         _ => {2}
     };
 
     out.push_str(&format!("15|{server_id}\n"));
-    out.push_str(&format!("{}\n", elp.eso_logs_log.events.len()));
+    out.push_str(&format!("{}\n", events.len()));
 
-    for e in &elp.eso_logs_log.events {
+    for e in events {
         out.push_str(&e.to_string());
         out.push('\n');
     }
@@ -32,6 +32,7 @@ pub struct ESOLogsLog {
     pub shields: HashMap<u32, HashMap<usize, ESOLogsBuffEventKey2>>,
     pub shield_values: HashMap<u32, u32>,
     pub current_health: HashMap<u32, u32>,
+    pub unit_operations: Vec<UnitOperation>,
 
     // Custom additions not required for parsing
     // pub esosim_characters: HashMap<u32, Character>,
@@ -95,6 +96,12 @@ impl ESOLogsLog {
     pub fn reserve_event_capacity(&mut self, events: usize) {self.events.reserve(events)}
 
     pub fn add_unit(&mut self, unit: ESOLogsUnit) -> usize {
+        let index = self.insert_unit(unit.clone());
+        self.unit_operations.push(UnitOperation::Added { unit, index });
+        index
+    }
+
+    fn insert_unit(&mut self, unit: ESOLogsUnit) -> usize {
         let mut id = unit.unit_id;
         if let Some(pd) = &unit.player_data {
             let char_id = pd.character_id;
@@ -153,9 +160,30 @@ impl ESOLogsLog {
     pub fn map_unit_id_to_monster_id(&mut self, unit_id: u32, unit: &ESOLogsUnit) {
         let session_id = unit.unit_id;
         self.unit_id_to_session_id.insert(unit_id, session_id);
+        self.unit_operations.push(UnitOperation::SessionMapped { unit_id, session_id });
+    }
+
+    /// Replaces the unit sharing `unit`'s session id if it was only known as "Offline"
+    pub fn replace_offline_unit(&mut self, unit: &ESOLogsUnit) {
+        self.unit_operations.push(UnitOperation::OfflineReplaced(unit.clone()));
+        self.insert_offline_unit(unit);
+    }
+
+    fn insert_offline_unit(&mut self, unit: &ESOLogsUnit) {
+        if let Some(unit_index) = self.session_id_to_units_index.get(&unit.unit_id)
+            && let Some(player) = self.units.get_mut(*unit_index)
+            && player.name == "Offline".into() {
+            *player = unit.clone();
+        }
     }
 
     pub fn add_object(&mut self, object: ESOLogsUnit) -> usize {
+        let index = self.insert_object(object.clone());
+        self.unit_operations.push(UnitOperation::ObjectAdded { object, index });
+        index
+    }
+
+    fn insert_object(&mut self, object: ESOLogsUnit) -> usize {
         if self.objects.contains_key(&object.name) {
             let session_id = self.objects.get(&object.name).unwrap();
             let index = self.session_id_to_units_index.get(session_id).unwrap();
@@ -260,6 +288,185 @@ impl ESOLogsLog {
         }
         None
     }
+
+    /// Merges the tables of `other`, a log converted on its own, into this one. `continues` is set when `other` carries on
+    /// the BEGIN_LOG of the log merged before it, whose units and effects it then shares rather than adding them anew.
+    /// Units are deduplicated by replaying the unit operations of `other` in order, so the result is the same as converting both logs one after another.
+    /// The replayed operations are not recorded here, as a merged table is never merged into another.
+    /// Events of `other` must be passed through the returned [`ESOLogsRemap`] before they are written next to this table.
+    pub fn merge(&mut self, other: &ESOLogsLog, continues: bool) -> ESOLogsRemap {
+        // these only live for a single BEGIN_LOG
+        if !continues {
+            self.unit_id_to_session_id.clear();
+            self.objects.clear();
+            self.effects_hashmap.clear();
+        }
+
+        let first_new_unit = self.units.len();
+        let mut units = vec![usize::MAX; other.units.len()];
+        for operation in &other.unit_operations {
+            let (local, index) = match operation {
+                UnitOperation::SessionMapped { unit_id, session_id } => {
+                    self.unit_id_to_session_id.insert(*unit_id, *session_id);
+                    continue;
+                }
+                UnitOperation::OfflineReplaced(unit) => {
+                    self.insert_offline_unit(unit);
+                    continue;
+                }
+                UnitOperation::Added { unit, index } => (*index, self.insert_unit(unit.clone())),
+                UnitOperation::ObjectAdded { object, index } => (*index, self.insert_object(object.clone())),
+            };
+            if let Some(entry) = units.get_mut(local) && *entry == usize::MAX {
+                *entry = index;
+            }
+        }
+
+        for (local, unit) in other.units.iter().enumerate() {
+            let index = units[local];
+            if index == usize::MAX {
+                units[local] = self.units.len();
+                self.units.push(unit.clone());
+            } else if index >= first_new_unit {
+                self.units[index] = unit.clone();
+            } else {
+                let existing = &mut self.units[index];
+                existing.unit_type = unit.unit_type;
+                // the fallback icon is only given to units without one
+                if unit.icon.is_some() && (existing.icon.is_none() || unit.icon.as_deref() != Some("death_recap_melee_basic")) {
+                    existing.icon = unit.icon.clone();
+                }
+            }
+        }
+
+        let mut buffs = Vec::with_capacity(other.buffs.len());
+        for buff in &other.buffs {
+            if let Some(index) = self.buff_index(buff.id) {
+                let existing = &mut self.buffs[index];
+                if buff.damage_type != DamageType::None {
+                    existing.damage_type = buff.damage_type;
+                }
+                if buff.status_type != StatusEffectType::None {
+                    existing.status_type = buff.status_type.clone();
+                }
+                if buff.caused_by_id != 0 {
+                    existing.caused_by_id = buff.caused_by_id;
+                }
+                buffs.push(index);
+            } else {
+                buffs.push(self.buffs.len());
+                self.add_buff(buff.clone());
+            }
+        }
+
+        let mut remap = ESOLogsRemap { units, buffs, effects: Vec::with_capacity(other.effects.len()) };
+
+        let first_new_effect = self.effects.len();
+        for effect in &other.effects {
+            let key = ESOLogsBuffEventKey {
+                source_unit_index: remap.unit(effect.source_unit_index),
+                target_unit_index: remap.unit(effect.target_unit_index),
+                buff_index: remap.buff(effect.buff_index),
+            };
+            let index = match self.effects_hashmap.get(&key) {
+                Some(index) if *index < first_new_effect => *index,
+                _ => {
+                    let index = self.effects.len();
+                    self.effects_hashmap.insert(key, index);
+                    self.effects.push(ESOLogsBuffEvent {
+                        unique_index: index,
+                        source_unit_index: key.source_unit_index,
+                        target_unit_index: key.target_unit_index,
+                        buff_index: key.buff_index,
+                    });
+                    index
+                }
+            };
+            remap.effects.push(index);
+        }
+
+        for relationship in &other.pets {
+            let relationship = ESOLogsPetRelationship {
+                owner_index: remap.unit(relationship.owner_index),
+                pet: ESOLogsPet { pet_type_index: remap.unit(relationship.pet.pet_type_index) },
+            };
+            if !self.pets.iter().any(|rel| rel.pet.pet_type_index == relationship.pet.pet_type_index) {
+                self.pets.push(relationship);
+            }
+        }
+
+        remap
+    }
+}
+
+/// A change to the unit table, recorded so that the same changes can be replayed when merging logs
+#[derive(Debug, Clone)]
+pub enum UnitOperation {
+    SessionMapped { unit_id: u32, session_id: u32 },
+    OfflineReplaced(ESOLogsUnit),
+    Added { unit: ESOLogsUnit, index: usize },
+    ObjectAdded { object: ESOLogsUnit, index: usize },
+}
+
+/// Maps the unit, buff and effect indices of a merged log onto the indices of the table it was merged into
+#[derive(Debug, Clone)]
+pub struct ESOLogsRemap {
+    units: Vec<usize>,
+    buffs: Vec<usize>,
+    effects: Vec<usize>,
+}
+
+impl ESOLogsRemap {
+    pub fn unit(&self, index: usize) -> usize {
+        self.units.get(index).copied().unwrap_or(index)
+    }
+
+    pub fn buff(&self, index: usize) -> usize {
+        self.buffs.get(index).copied().unwrap_or(index)
+    }
+
+    pub fn effect(&self, index: usize) -> usize {
+        self.effects.get(index).copied().unwrap_or(index)
+    }
+
+    fn buff_event(&self, buff_event: &mut ESOLogsBuffEvent) {
+        buff_event.unique_index = self.effect(buff_event.unique_index);
+        buff_event.source_unit_index = self.unit(buff_event.source_unit_index);
+        buff_event.target_unit_index = self.unit(buff_event.target_unit_index);
+        buff_event.buff_index = self.buff(buff_event.buff_index);
+    }
+
+    pub fn event(&self, event: &mut ESOLogsEvent) {
+        match event {
+            ESOLogsEvent::Buff(e) => self.buff_event(e),
+            ESOLogsEvent::BuffLine(e) => {
+                self.buff_event(&mut e.buff_event);
+                e.source_cast_index = e.source_cast_index.map(|i| self.buff(i));
+            }
+            ESOLogsEvent::CastLine(e) => self.buff_event(&mut e.buff_event),
+            ESOLogsEvent::PowerEnergize(e) => self.buff_event(&mut e.buff_event),
+            ESOLogsEvent::PlayerInfo(e) => e.unit_index = self.unit(e.unit_index),
+            ESOLogsEvent::HealthRecovery(e) => self.buff_event(&mut e.buff_event),
+            ESOLogsEvent::StackUpdate(e) => self.buff_event(&mut e.buff_event),
+            ESOLogsEvent::DamageShielded(e) => {
+                self.buff_event(&mut e.buff_event);
+                e.source_ability_cast_index = e.source_ability_cast_index.map(|i| self.buff(i));
+                e.damage_source_caster_index = e.damage_source_caster_index.map(|i| self.unit(i));
+                e.damage_source_unit_index = self.unit(e.damage_source_unit_index);
+            }
+            ESOLogsEvent::Interrupt(e) => {
+                self.buff_event(&mut e.buff_event);
+                e.interrupted_ability_index = self.buff(e.interrupted_ability_index);
+            }
+            ESOLogsEvent::InterruptionEnded(e) => self.buff_event(&mut e.buff_event),
+            ESOLogsEvent::CastEnded(e) => self.buff_event(&mut e.buff_event),
+            ESOLogsEvent::ZoneInfo(_)
+            | ESOLogsEvent::MapInfo(_)
+            | ESOLogsEvent::EndCombat(_)
+            | ESOLogsEvent::BeginCombat(_)
+            | ESOLogsEvent::EndTrial(_) => {}
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -391,7 +598,7 @@ pub struct ESOLogsBuffEventKey2 {
     pub source_cast_id: Option<u32>,
 }
 
-#[derive(Eq, Hash, PartialEq, Debug)]
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
 pub struct ESOLogsBuffEventKey {
     pub source_unit_index: usize,
     pub target_unit_index: usize,
@@ -778,6 +985,8 @@ pub struct ESOLogsDamageShielded { // purely for healing purposes. we copy the o
     pub shield_recipient_allegiance: u8,
     pub unit_instance_id: (usize, usize),
     pub orig_shield_instance_ids: (usize, usize),
+    /// The unit `unit_instance_id` belongs to
+    pub damage_source_unit_index: usize,
     pub hit_value: u32,
     pub source_ability_cast_index: Option<usize>,
     pub damage_source_caster_index: Option<usize>,
@@ -0,0 +1,72 @@
+use std::io::{BufRead, Write};
+use parser::line::{for_each_line, LogLine};
+use parser::session::Session;
+use parser::unit::UnitState;
+use serde_json::{Map, Value};
+
+/// Writes every line of `reader` to `writer` as one JSON object per line, returning the number of lines written.
+/// Each object keeps the line's fields under their parser names, with a `type` field holding the line type,
+/// and gains `ability_name`, `source_name`, `target_name` or `unit_name` where the ids they name are known.
+pub fn export_json_lines<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result<usize, String> {
+    let mut session = Session::new();
+    let mut written = 0;
+    let mut error = None;
+
+    for_each_line(reader, |line, _, _| {
+        if error.is_some() {return}
+        let result = line_to_json(line, &session)
+            .and_then(|value| serde_json::to_writer(&mut *writer, &value).map_err(|e| format!("Error serialising line: {e}")))
+            .and_then(|()| writer.write_all(b"\n").map_err(|e| format!("Error writing line: {e}")));
+        match result {
+            Ok(()) => written += 1,
+            Err(e) => error = Some(e),
+        }
+        session.apply(line);
+    }).map_err(|e| format!("Error reading log file: {e}"))?;
+
+    if let Some(e) = error {
+        return Err(e);
+    }
+    writer.flush().map_err(|e| format!("Error flushing writer: {e}"))?;
+    Ok(written)
+}
+
+/// Serialises a line and adds the names of the abilities and units it refers to, as known to `session` before the line is applied.
+pub fn line_to_json(line: &LogLine, session: &Session) -> Result<Value, String> {
+    let mut value = serde_json::to_value(line).map_err(|e| format!("Error serialising line: {e}"))?;
+    let Value::Object(object) = &mut value else {return Ok(value)};
+
+    match line {
+        LogLine::CombatEvent(event) => add_cast_names(object, session, event.ability_id, &event.source_unit_state, &event.target_unit_state),
+        LogLine::BeginCast(cast) => add_cast_names(object, session, cast.ability_id, &cast.source_unit_state, &cast.target_unit_state),
+        LogLine::EffectChanged(effect) => add_cast_names(object, session, effect.ability_id, &effect.source_unit_state, &effect.target_unit_state),
+        LogLine::EndCast(end_cast) => add_ability_name(object, session, end_cast.interrupted_ability_id),
+        LogLine::EffectInfo(info) => add_ability_name(object, session, info.ability_id),
+        LogLine::HealthRegen(regen) => add_unit_name(object, session, "unit_name", regen.unit_state.unit_id),
+        LogLine::PlayerInfo(info) => add_unit_name(object, session, "unit_name", info.unit_id),
+        LogLine::UnitChanged(unit) => add_unit_name(object, session, "unit_name", unit.unit_id),
+        LogLine::UnitRemoved { unit_id, .. } => add_unit_name(object, session, "unit_name", *unit_id),
+        _ => {}
+    }
+
+    Ok(value)
+}
+
+fn add_cast_names(object: &mut Map<String, Value>, session: &Session, ability_id: u32, source: &UnitState, target: &UnitState) {
+    add_ability_name(object, session, ability_id);
+    add_unit_name(object, session, "source_name", source.unit_id);
+    add_unit_name(object, session, "target_name", target.unit_id);
+}
+
+fn add_ability_name(object: &mut Map<String, Value>, session: &Session, ability_id: u32) {
+    if let Some(ability) = session.ability(ability_id) {
+        object.insert("ability_name".to_owned(), Value::from(&*ability.name));
+    }
+}
+
+fn add_unit_name(object: &mut Map<String, Value>, session: &Session, key: &str, unit_id: u32) {
+    if unit_id == 0 {return}
+    if let Some(unit) = session.unit(unit_id) {
+        object.insert(key.to_owned(), Value::from(unit.name()));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use parser::fights::find_fights;
+use parser::index::LogIndex;
+use parser::line::LogLine;
+use parser::EventType;
+
+/// The part of a log to extract.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extract {
+    /// Fight number, counting from 1 as in `find_fights`
+    Fight(usize),
+    /// Pull number `pull` of the boss named `name`, counting from 1, or the last pull when `None`
+    Boss { name: String, pull: Option<usize> },
+    /// Every line from one Unix time in milliseconds to another, both included, within a single BEGIN_LOG
+    Time { from: u64, to: u64 },
+}
+
+/// Lines of the log to extract: those in `start_offset..end_offset` with a time in `from..=to`.
+struct LineRange {
+    start_offset: u64,
+    end_offset: u64,
+    from: u64,
+    to: u64,
+}
+
+/// Unit and ability ids the extracted lines refer to.
+#[derive(Default)]
+struct References {
+    units: HashSet<u32>,
+    abilities: HashSet<u32>,
+}
+
+impl References {
+    fn add(&mut self, line: &LogLine) {
+        match line {
+            LogLine::CombatEvent(event) => {
+                self.abilities.insert(event.ability_id);
+                self.units.extend([event.source_unit_state.unit_id, event.target_unit_state.unit_id]);
+            }
+            LogLine::BeginCast(cast) => {
+                self.abilities.insert(cast.ability_id);
+                self.units.extend([cast.source_unit_state.unit_id, cast.target_unit_state.unit_id]);
+            }
+            LogLine::EffectChanged(effect) => {
+                self.abilities.insert(effect.ability_id);
+                self.units.extend([effect.source_unit_state.unit_id, effect.target_unit_state.unit_id]);
+            }
+            LogLine::EndCast(end_cast) => {
+                self.abilities.insert(end_cast.interrupted_ability_id);
+                self.abilities.extend(end_cast.interrupting_ability_id);
+                self.units.extend(end_cast.interrupting_unit_id);
+            }
+            LogLine::HealthRegen(regen) => {
+                self.units.insert(regen.unit_state.unit_id);
+            }
+            LogLine::UnitChanged(unit) => {
+                self.units.insert(unit.unit_id);
+            }
+            LogLine::UnitRemoved { unit_id, .. } => {
+                self.units.insert(*unit_id);
+            }
+            LogLine::PlayerInfo(info) => {
+                self.units.insert(info.unit_id);
+                self.abilities.extend(info.long_term_effects().map(|(id, _)| id));
+                self.abilities.extend(info.primary_ability_ids().chain(info.backup_ability_ids()));
+            }
+            LogLine::EffectInfo(info) => {
+                self.abilities.extend(info.synergy_ability_id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether a definition line from before the extract is needed to decode it.
+    fn needs(&self, line: &LogLine) -> bool {
+        match line {
+            LogLine::UnitAdded(unit) => self.units.contains(&unit.unit_id),
+            LogLine::UnitChanged(unit) => self.units.contains(&unit.unit_id),
+            LogLine::PlayerInfo(info) => self.units.contains(&info.unit_id),
+            LogLine::AbilityInfo(ability) => self.abilities.contains(&ability.ability_id),
+            LogLine::EffectInfo(effect) => self.abilities.contains(&effect.ability_id),
+            _ => true,
+        }
+    }
+}
+
+/// Writes part of a log to `output_path` as a log of its own that parses and uploads without the rest.
+///
+/// The new log starts with a BEGIN_LOG for the time of the first extracted line, followed by the zone, map and trial,
+/// and the units, players, abilities and effects the extracted lines refer to. An extract that starts with a fight also gets
+/// the effects active and the state of each player when the fight began. Times are shifted to count from the new BEGIN_LOG.
+/// A fight still open at the end of the extract is closed at its last line.
+/// Only a selection by boss reads the whole log; fights and times are found through the log's index.
+pub fn extract_log_to_file(log_path: &Path, extract: &Extract, output_path: &Path) -> Result<(), String> {
+    let index = LogIndex::open(log_path).map_err(|e| format!("Error indexing {}: {e}", log_path.display()))?;
+    let range = line_range(log_path, &index, extract)?;
+    let mut log = BufReader::new(File::open(log_path).map_err(|e| format!("Failed to open {}: {e}", log_path.display()))?);
+    let read_error = |e: std::io::Error| format!("Error reading log file: {e}");
+
+    // First pass: where the extract starts and which units and abilities it needs
+    let mut references = References::default();
+    let mut first: Option<(u64, u64)> = None;
+    let mut last_time = 0;
+    for_each_line_in(&mut log, &range, |offset, time, text| {
+        first.get_or_insert((offset, time));
+        last_time = time;
+        if let Ok(line) = LogLine::parse(text) {
+            references.add(&line);
+        }
+    }).map_err(read_error)?;
+    let Some((first_offset, base_time)) = first else {
+        return Err("Nothing to extract, no lines in the chosen range".to_owned());
+    };
+
+    let context = index.read_context(&mut log, first_offset).map_err(read_error)?;
+    let mut context: Vec<(String, LogLine)> = context.iter()
+        .filter_map(|text| LogLine::parse(text).ok().map(|line| (text.clone(), line)))
+        .collect();
+    let Some((begin_log, LogLine::BeginLog(begin))) = context.first() else {
+        return Err("The chosen range is not preceded by a BEGIN_LOG".to_owned());
+    };
+    let log_time = begin.log_time;
+    let begin_log = begin_log.clone();
+    context.remove(0);
+
+    // The effects and player states a fight began with bring their own units and abilities,
+    // unless they refer to a unit that has since been removed
+    let added: HashSet<u32> = context.iter()
+        .filter_map(|(_, line)| if let LogLine::UnitAdded(unit) = line {Some(unit.unit_id)} else {None})
+        .collect();
+    context.retain(|(_, line)| {
+        if !matches!(line, LogLine::CombatEvent(_) | LogLine::BeginCast(_) | LogLine::EffectChanged(_) | LogLine::HealthRegen(_)) {
+            return true;
+        }
+        let mut state = References::default();
+        state.add(line);
+        state.units.iter().all(|unit_id| *unit_id == 0 || added.contains(unit_id))
+    });
+    for (_, line) in &context {
+        if matches!(line, LogLine::CombatEvent(_) | LogLine::BeginCast(_) | LogLine::EffectChanged(_) | LogLine::HealthRegen(_)) {
+            references.add(line);
+        }
+    }
+    // Pets bring their owners, and players bring the abilities on their bars
+    references.units.remove(&0);
+    for (_, line) in &context {
+        if let LogLine::UnitAdded(unit) = line
+            && references.units.contains(&unit.unit_id) && unit.owner_unit_id != 0 {
+            references.units.insert(unit.owner_unit_id);
+        }
+    }
+    for (_, line) in &context {
+        if matches!(line, LogLine::PlayerInfo(_) | LogLine::EffectInfo(_)) && references.needs(line) {
+            references.add(line);
+        }
+    }
+
+    let in_combat = index.entries.iter()
+        .rfind(|e| e.offset < first_offset && matches!(e.event_type, EventType::BeginLog | EventType::BeginCombat | EventType::EndCombat))
+        .is_some_and(|e| e.event_type == EventType::BeginCombat);
+
+    let file = File::create(output_path).map_err(|e| format!("Error creating {}: {e}", output_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let write_error = |e: std::io::Error| format!("Error writing {}: {e}", output_path.display());
+
+    let begin_log_rest = begin_log.splitn(4, ',').nth(3).unwrap_or_default();
+    writeln!(writer, "0,BEGIN_LOG,{},{begin_log_rest}", log_time + base_time).map_err(write_error)?;
+    for (text, line) in &context {
+        if references.needs(line) {
+            writeln!(writer, "{}", with_time(text, 0)).map_err(write_error)?;
+        }
+    }
+    if in_combat {
+        writeln!(writer, "0,BEGIN_COMBAT").map_err(write_error)?;
+    }
+
+    // Second pass: the extracted lines themselves, keeping track of whether they leave a fight open
+    let mut in_combat = in_combat;
+    let mut result = Ok(());
+    for_each_line_in(&mut log, &range, |_, time, text| {
+        match text.split(',').nth(1).map(EventType::from) {
+            Some(EventType::BeginCombat) => in_combat = true,
+            Some(EventType::EndCombat) => in_combat = false,
+            _ => {}
+        }
+        if result.is_ok() {
+            result = writeln!(writer, "{}", with_time(text, time.saturating_sub(base_time)));
+        }
+    }).map_err(read_error)?;
+    result.map_err(write_error)?;
+    let end_time = last_time.saturating_sub(base_time);
+    if in_combat {
+        writeln!(writer, "{end_time},END_COMBAT").map_err(write_error)?;
+    }
+    writeln!(writer, "{end_time},END_LOG").map_err(write_error)?;
+    writer.flush().map_err(write_error)
+}
+
+/// Default name for an extract, next to the log: `<name>-fight-3.log`, `<name>-<boss>-2.log` or `<name>-<from>.log`.
+pub fn extracted_log_path(log_path: &Path, extract: &Extract) -> PathBuf {
+    let stem = log_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let suffix = match extract {
+        Extract::Fight(fight) => format!("fight-{fight}"),
+        Extract::Boss { name, pull } => {
+            let name: String = name.chars().map(|c| if c.is_alphanumeric() {c} else {'_'}).collect();
+            pull.map_or(name.clone(), |pull| format!("{name}-{pull}"))
+        }
+        Extract::Time { from, .. } => from.to_string(),
+    };
+    log_path.with_file_name(format!("{stem}-{suffix}.log"))
+}
+
+fn line_range(log_path: &Path, index: &LogIndex, extract: &Extract) -> Result<LineRange, String> {
+    let whole_lines = |start_offset, end_offset| LineRange { start_offset, end_offset, from: 0, to: u64::MAX };
+    match extract {
+        Extract::Fight(fight) => {
+            let fights = index.fights();
+            let count = fights.len();
+            let selected = fight.checked_sub(1)
+                .and_then(|i| fights.get(i))
+                .ok_or_else(|| format!("Fight {fight} does not exist, the log has {count} fights"))?;
+            Ok(whole_lines(selected.start_offset, selected.end_offset))
+        }
+        Extract::Boss { name, pull } => {
+            let file = File::open(log_path).map_err(|e| format!("Failed to open {}: {e}", log_path.display()))?;
+            let fights = find_fights(BufReader::new(file)).map_err(|e| format!("Error reading log file: {e}"))?;
+            let pulls: Vec<_> = fights.iter()
+                .filter(|f| f.bosses.iter().any(|b| b.name.eq_ignore_ascii_case(name)))
+                .collect();
+            let count = pulls.len();
+            let selected = match pull {
+                Some(pull) => pull.checked_sub(1).and_then(|i| pulls.get(i)),
+                None => pulls.last(),
+            };
+            let selected = selected.ok_or_else(|| match pull {
+                Some(pull) => format!("Pull {pull} of {name} does not exist, the log has {count} pulls"),
+                None => format!("No fight against {name} in the log"),
+            })?;
+            Ok(whole_lines(selected.start_offset, selected.end_offset))
+        }
+        Extract::Time { from, to } => {
+            let logs: Vec<_> = index.entries.iter().filter(|e| e.event_type == EventType::BeginLog).collect();
+            let log_number = logs.iter().rposition(|e| e.id <= *from)
+                .ok_or_else(|| format!("No log in the file covers {from}"))?;
+            if to < from {
+                return Err(format!("The range ends at {to}, before it starts at {from}"));
+            }
+            let begin_log = logs[log_number];
+            let end_offset = logs.get(log_number + 1).map_or(index.indexed_length, |e| e.offset);
+            let (from, to) = (from - begin_log.id, to.saturating_sub(begin_log.id));
+            // Start reading at the last indexed line before `from` rather than at the BEGIN_LOG, which is replaced anyway
+            let start_offset = index.entries.iter()
+                .filter(|e| e.offset > begin_log.offset && e.offset < end_offset && e.time < from)
+                .map(|e| e.offset)
+                .next_back()
+                .unwrap_or(begin_log.offset + begin_log.length);
+            Ok(LineRange { start_offset, end_offset, from, to })
+        }
+    }
+}
+
+/// Calls `f` with the offset, time and text of every line in `range`, stopping at the first line after `range.to`.
+fn for_each_line_in<R: BufRead + Seek>(log: &mut R, range: &LineRange, mut f: impl FnMut(u64, u64, &str)) -> std::io::Result<()> {
+    log.seek(SeekFrom::Start(range.start_offset))?;
+    let mut buffer = Vec::new();
+    let mut offset = range.start_offset;
+    while offset < range.end_offset {
+        buffer.clear();
+        let length = log.read_until(b'\n', &mut buffer)? as u64;
+        if length == 0 {break}
+        let text = String::from_utf8_lossy(&buffer);
+        let text = text.trim_end();
+        if let Some(time) = text.split(',').next().and_then(|t| t.parse::<u64>().ok()) {
+            if time > range.to {break}
+            if time >= range.from {
+                f(offset, time, text);
+            }
+        }
+        offset += length;
+    }
+    Ok(())
+}
+
+/// Replaces the time at the start of a line.
+fn with_time(line: &str, time: u64) -> String {
+    match line.find(',') {
+        Some(comma) => format!("{time}{}", &line[comma..]),
+        None => line.to_owned(),
+    }
+}
@@ -2,4 +2,14 @@ pub mod log_edit;
 pub mod split_log;
 pub mod esologs_format;
 pub mod esologs_convert;
+pub mod export;
+pub mod extract;
+#[cfg(feature = "parquet")]
+pub mod parquet_export;
+#[cfg(feature = "database")]
+pub mod database;
+pub mod repair;
+pub mod map_plot;
+pub mod trial_history;
+pub mod build_sheet;
 // pub mod rich_presence;
\ No newline at end of file
@@ -1,80 +1,99 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{BufReader, BufWriter, Write, BufRead};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use esosim::data::item_type::{GearSlot, ITEM_TYPES, ItemType};
-use esosim::data::skill::{SkillLine, ability_id_to_subclass};
+use esosim::data::skill::ability_id_to_subclass;
+use crate::esologs_format::LINE_COUNT_FOR_PROGRESS;
 use parser::effect::{is_zen_dot, MOULDERING_TAINT_ID, MOULDERING_TAINT_TIME, ZEN_DEBUFF_ID};
 use parser::event::{self, parse_event_result, EventResult};
+use parser::line::LogLine;
 use parser::parse::{self, gear_piece, unit_state_id_only};
 use parser::subclassing::{subclass_to_icon, subclass_to_name};
 use parser::unit::UnitState;
 use parser::{EffectChangedEventType, EventType, UnitAddedEventType};
 
-pub struct CustomLogData {
-    pub zen_stacks: HashMap<u32, ZenDebuffState>,
-    pub scribing_abilities: Vec<ScribingAbility>,
-    pub scribing_map: HashMap<u32, usize>,
-    pub scribing_unit_map: HashMap<(Arc<str>, u32), usize>,
-    pub taint_stacks: HashMap<u32, MoulderingTaintState>,
-    pub units: HashMap<u32, Arc<str>>,
-    pub last_combat_event_timestamp: u64,
-    pub subclassing_map: HashMap<String, Option<Vec<SkillLine>>>,
-    pub known_ids: HashMap<u32, bool>,
+/// What a [`LogFix`] does with a line
+#[derive(Debug, PartialEq)]
+pub enum FixOutput {
+    /// Leave the line as it is
+    Keep,
+    /// Keep the line and add these lines after it
+    Append(Vec<String>),
+    /// Replace the line with these lines, or remove it if there are none
+    Replace(Vec<String>),
 }
 
-impl Default for CustomLogData {
+/// A rewrite rule applied to every line of a log while it is modified.
+/// Each fix keeps its own state and is only shown lines of the event types it observes.
+pub trait LogFix: Send {
+    /// Identifier used to enable or disable the fix, such as `zen_stacks`
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn event_types(&self) -> &'static [EventType];
+    /// `parts` is the line split by [`parse::split_line`]
+    fn apply(&mut self, parts: &[&str]) -> FixOutput;
+    /// Called when a new log begins
+    fn reset(&mut self) {}
+}
+
+/// Every fix, in the order they are applied to a line
+pub fn all_log_fixes() -> Vec<Box<dyn LogFix>> {
+    vec![
+        Box::new(ZenStacks::default()),
+        Box::new(ArcanistBeams::default()),
+        Box::new(MoulderingTaint::default()),
+        Box::new(SoulGemResurrection),
+        Box::new(ClassMastery),
+        Box::new(Cryptcanon::default()),
+        Box::new(BlockadeVersions::default()),
+        Box::new(ScribingAbilities::default()),
+        Box::new(Subclassing::default()),
+    ]
+}
+
+/// The fixes applied while modifying a log, along with their state
+pub struct LogFixes {
+    fixes: Vec<Box<dyn LogFix>>,
+}
+
+impl Default for LogFixes {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl CustomLogData {
+impl LogFixes {
+    /// All fixes enabled
     pub fn new() -> Self {
-        Self {
-            zen_stacks: HashMap::new(),
-            scribing_abilities: Vec::new(),
-            scribing_map: HashMap::new(),
-            scribing_unit_map: HashMap::new(),
-            taint_stacks: HashMap::new(),
-            units: HashMap::new(),
-            last_combat_event_timestamp: 0,
-            subclassing_map: HashMap::new(),
-            known_ids: HashMap::new(),
-        }
+        Self { fixes: all_log_fixes() }
     }
 
-    pub fn reset(&mut self) {
-        self.zen_stacks.clear();
-        self.taint_stacks.clear();
-        self.subclassing_map.clear();
+    /// All fixes except those named in `disabled`
+    pub fn without<S: AsRef<str>>(disabled: &[S]) -> Result<Self, String> {
+        let fixes = all_log_fixes();
+        for name in disabled {
+            if !fixes.iter().any(|f| f.name() == name.as_ref()) {
+                return Err(format!("Unknown log fix: {}", name.as_ref()));
+            }
+        }
+        Ok(Self {
+            fixes: fixes.into_iter().filter(|f| !disabled.iter().any(|d| d.as_ref() == f.name())).collect(),
+        })
     }
-}
-
-pub struct ZenDebuffState {
-    active: bool,
-    source_id: u32,
-    contributing_ability_ids: Vec<u32>,
-}
-
-pub struct MoulderingTaintState {
-    stacks: u8,
-    last_timestamp: u64,
-    last_source_unit_state: UnitState,
-    last_target_unit_state: UnitState,
-    last_cast_id: u32,
-}
 
-const BEGIN_SCRIBING_ABILITIES: u32 = 1000;
+    pub fn enabled(&self) -> impl Iterator<Item = &dyn LogFix> {
+        self.fixes.iter().map(|f| f.as_ref())
+    }
 
-#[derive(Debug, Clone)]
-pub struct ScribingAbility {
-    pub id: u32,
-    pub name: Arc<str>,
-    pub icon: Arc<str>,
-    pub scribing: Option<Vec<String>>,
+    pub fn reset(&mut self) {
+        for fix in &mut self.fixes {
+            fix.reset();
+        }
+    }
 }
 
 fn torf_from_bool(b: bool) -> String {
@@ -86,123 +105,195 @@ fn torf_from_bool(b: bool) -> String {
 }
 
 pub fn modify_log_file(file_path: &Path) -> Result<(), Box<dyn Error>> {
-    let file: File = File::open(file_path)?;
-    let reader = BufReader::new(file);
+    modify_log_file_to(file_path, &modified_log_path(file_path)?)
+}
 
-    let mut modified_lines: Vec<String> = Vec::new();
-    let mut custom_log_data = CustomLogData::new();
+/// `Encounter.log` -> `Encounter-MODIFIED.log` in the same folder
+pub fn modified_log_path(file_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let stem = file_path.file_stem().ok_or("Failed to get file stem")?;
+    let mut new_file_name = stem.to_os_string();
+    new_file_name.push("-MODIFIED.log");
+    Ok(file_path.with_file_name(new_file_name))
+}
 
-    for line_result in reader.lines() {
-        let line = match line_result {
-            Ok(l) => l,
-            Err(e) => {
-                log::warn!("Error reading line: {e}");
-                continue;
-            }
-        };
-        modified_lines.extend(handle_line(line, &mut custom_log_data));
-    }
+pub fn modify_log_file_to(file_path: &Path, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let noop = |_progress: u8| {};
+    let dummy_cancel = AtomicBool::new(false);
+    modify_log_file_with_progress(file_path, output_path, LogFixes::new(), noop, &dummy_cancel)?;
+    Ok(())
+}
 
-    let mut new_path = file_path.with_extension("");
-    if let Some(stem) = new_path.file_stem() {
-        let mut new_file_name = stem.to_os_string();
-        new_file_name.push("-MODIFIED.log");
-        new_path.set_file_name(new_file_name);
-    } else {
-        return Err("Failed to get file stem".into());
+/// Streams `input_path` through `log_fixes` into `output_path` one line at a time, so memory use does not grow with the size of the log.
+/// `progress_callback` receives the percentage of the input read so far. Setting `cancel_flag` stops the modification and removes the partial output.
+pub fn modify_log_file_with_progress<InputPath, OutputPath, F>(input_path: InputPath, output_path: OutputPath, log_fixes: LogFixes, mut progress_callback: F, cancel_flag: &AtomicBool) -> Result<(), String> where InputPath: AsRef<Path>, OutputPath: AsRef<Path>, F: FnMut(u8) {
+    let input_path = input_path.as_ref();
+    let output_path = output_path.as_ref();
+    if let (Ok(input), Ok(output)) = (input_path.canonicalize(), output_path.canonicalize())
+        && input == output {
+        return Err("Output path must differ from the input log".to_string());
     }
-    
-    let file = OpenOptions::new()
+
+    let input_file = File::open(input_path)
+        .map_err(|e| format!("Failed to open input file: {e}"))?;
+    let total_bytes = input_file.metadata().map(|m| m.len()).unwrap_or(0).max(1);
+    let reader = BufReader::new(input_file);
+
+    let output_file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(&new_path)?;
-    let mut writer = BufWriter::new(file);
+        .open(output_path)
+        .map_err(|e| format!("Failed to create output file: {e}"))?;
+    let writer = BufWriter::new(output_file);
 
-    for line in &modified_lines {
-        writeln!(writer, "{line}")
-            .map_err(|e| format!("Failed to write line: {e}"))?;
+    let result = modify_log_stream(reader, writer, log_fixes, |bytes_read| {
+        progress_callback(((bytes_read as f64 / total_bytes as f64) * 100.0).round().min(100.0) as u8);
+        !cancel_flag.load(Ordering::SeqCst)
+    });
+    if result.is_err() && let Err(e) = fs::remove_file(output_path) {
+        log::warn!("Failed to remove partial output file: {e}");
     }
+    result?;
+    progress_callback(100);
     Ok(())
 }
 
-pub fn handle_line(line: String, custom_log_data: &mut CustomLogData) -> Vec<String> {
-    let parts: Vec<String> = parser::parse::handle_line(&line);
-    
-    if parts.get(1).map(|s| s.as_str()) == Some("BEGIN_COMBAT") {
-        custom_log_data.zen_stacks.clear();
-    }
-
-    let new_addition = check_line_for_edits(&parts, custom_log_data);
+/// Reads lines from `reader`, applies `log_fixes` and writes the result to `writer`.
+/// `on_progress` is called with the number of bytes read every [`LINE_COUNT_FOR_PROGRESS`] lines; returning `false` cancels.
+pub fn modify_log_stream<R: BufRead, W: Write>(mut reader: R, mut writer: W, mut log_fixes: LogFixes, mut on_progress: impl FnMut(u64) -> bool) -> Result<(), String> {
+    let mut buffer = Vec::new();
+    let mut bytes_read: u64 = 0;
+    let mut current_line: usize = 0;
+
+    loop {
+        buffer.clear();
+        let length = reader.read_until(b'\n', &mut buffer)
+            .map_err(|e| format!("Read error: {e}"))?;
+        if length == 0 {break}
+        bytes_read += length as u64;
+        current_line += 1;
+        if current_line.is_multiple_of(LINE_COUNT_FOR_PROGRESS) && !on_progress(bytes_read) {
+            return Err("Modification cancelled".to_string());
+        }
 
-    let mut modified_lines = Vec::new();
+        while buffer.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            buffer.pop();
+        }
+        let line = match String::from_utf8(std::mem::take(&mut buffer)) {
+            Ok(l) => l,
+            Err(e) => {
+                log::warn!("Error reading line {current_line}: {e}");
+                continue;
+            }
+        };
+        for entry in handle_line(line, &mut log_fixes) {
+            writeln!(writer, "{entry}")
+                .map_err(|e| format!("Failed to write line: {e}"))?;
+        }
+    }
 
-    if let Some(new_lines) = new_addition {
-        let is_ability = parts.get(1).map(|s| s.as_str()) == Some("ABILITY_INFO");
-        let is_effect  = parts.get(1).map(|s| s.as_str()) == Some("EFFECT_INFO");
-        let is_player  = parts.get(1).map(|s| s.as_str()) == Some("PLAYER_INFO");
-        // let is_resurrect = parts.get(2).map(|s| s.as_str()) == Some("SOUL_GEM_RESURRECTION_ACCEPTED");
+    writer.flush().map_err(|e| format!("Failed to flush output: {e}"))?;
+    Ok(())
+}
 
-        let is_zen_debuff = parts
-            .get(5)
-            .and_then(|s| s.parse::<u32>().ok())
-            == Some(*ZEN_DEBUFF_ID);
+/// Applies every enabled fix to `line`, returning the lines that replace it.
+/// Lines that fail to parse are left unmodified.
+pub fn handle_line(line: String, log_fixes: &mut LogFixes) -> Vec<String> {
+    let mut output = Vec::new();
+    apply_fixes(&mut log_fixes.fixes, line, &mut output, true);
+    output
+}
 
-        if !is_ability && !is_effect && !is_player && !is_zen_debuff {
-            modified_lines.push(line);
+/// Runs `line` through `fixes` in order. Lines a fix adds are passed through the fixes after it.
+fn apply_fixes(fixes: &mut [Box<dyn LogFix>], line: String, output: &mut Vec<String>, validate: bool) {
+    let mut appended = Vec::new();
+    let replaced = {
+        let parts = parse::split_line(&line);
+        let event_type = parts.get(1).map(|s| EventType::from(*s)).unwrap_or(EventType::Unknown);
+        let mut replaced = None;
+        if validate && let Err(e) = LogLine::from_parts(&parts) {
+            log::debug!("Leaving line unmodified: {e}");
+        } else {
+            for i in 0..fixes.len() {
+                if !fixes[i].event_types().contains(&event_type) {continue}
+                match fixes[i].apply(&parts) {
+                    FixOutput::Keep => {}
+                    FixOutput::Append(lines) => appended.push((i + 1, lines)),
+                    FixOutput::Replace(lines) => {
+                        replaced = Some((i + 1, lines));
+                        break;
+                    }
+                }
+            }
         }
+        replaced
+    };
 
-        modified_lines.extend(new_lines);
-    } else {
-        let is_resurrect = parts.get(1).map(|s| s.as_str()) == Some("BEGIN_CAST") && parts[5].parse::<u32>() == Ok(26770);
-        if is_resurrect {return modified_lines}
-        modified_lines.push(line);
+    match replaced {
+        Some((next, lines)) => {
+            for new_line in lines {
+                apply_fixes(&mut fixes[next..], new_line, output, false);
+            }
+        }
+        None => output.push(line),
     }
-
-    modified_lines
-}
-
-fn check_line_for_edits(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
-    let event = parts.get(1).map(|s| EventType::from(s.as_str())).unwrap_or(EventType::Unknown);
-    match event {
-        EventType::EffectChanged => check_effect_changed(parts, &mut custom_log_data.zen_stacks),
-        EventType::AbilityInfo => check_ability_info(parts, custom_log_data),
-        EventType::EffectInfo => add_arcanist_beam_effect_information(parts, custom_log_data),
-        EventType::PlayerInfo => modify_player_data(parts, custom_log_data),
-        EventType::CombatEvent => modify_combat_event(parts, custom_log_data),
-        EventType::UnitAdded => handle_unit_added(parts, custom_log_data),
-        _ => None,
+    for (next, lines) in appended {
+        for new_line in lines {
+            apply_fixes(&mut fixes[next..], new_line, output, false);
+        }
     }
 }
 
 const PRAGMATIC: &u32 = &186369;
 const EXHAUSTING: &u32 = &186780;
 
-fn check_effect_changed(parts: &[String], zen_hashmap: &mut HashMap<u32, ZenDebuffState>) -> Option<Vec<String>> {
-    if parts.len() < 17 {
-        return None;
-    }
-    match &parts[5] {
-        id if *id == PRAGMATIC.to_string() => add_arcanist_beam_cast(parts),
-        id if *id == EXHAUSTING.to_string() => add_arcanist_beam_cast(parts),
-        id if *id == ZEN_DEBUFF_ID.to_string() => add_zen_stacks(parts, zen_hashmap),
-        id if is_zen_dot(id.parse::<u32>().unwrap_or(0)) => add_zen_stacks(parts, zen_hashmap),
-        _ => None,
-    }
+struct ZenDebuffState {
+    active: bool,
+    source_id: u32,
+    contributing_ability_ids: Vec<u32>,
 }
 
 const MAX_ZEN_STACKS: u8 = 5;
 
-fn add_zen_stacks(parts: &[String], zen_status: &mut HashMap<u32, ZenDebuffState>) -> Option<Vec<String>> {
+/// Z'en's Redress does not log its stack count, so count the damage over time effects contributing to it
+#[derive(Default)]
+struct ZenStacks {
+    stacks: HashMap<u32, ZenDebuffState>,
+}
+
+impl LogFix for ZenStacks {
+    fn name(&self) -> &'static str {"zen_stacks"}
+    fn description(&self) -> &'static str {"Adds stack counts to Z'en's Redress"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::BeginCombat, EventType::EffectChanged]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        if parts.get(1) == Some(&"BEGIN_COMBAT") {
+            self.stacks.clear();
+            return FixOutput::Keep;
+        }
+        if parts.len() < 17 {return FixOutput::Keep}
+        let ability_id = parts[5].parse::<u32>().unwrap_or(0);
+        if ability_id == *ZEN_DEBUFF_ID {
+            add_zen_stacks(parts, &mut self.stacks).map_or(FixOutput::Keep, FixOutput::Replace)
+        } else if is_zen_dot(ability_id) {
+            add_zen_stacks(parts, &mut self.stacks).map_or(FixOutput::Keep, FixOutput::Append)
+        } else {
+            FixOutput::Keep
+        }
+    }
+
+    fn reset(&mut self) {
+        self.stacks.clear();
+    }
+}
+
+fn add_zen_stacks(parts: &[&str], zen_status: &mut HashMap<u32, ZenDebuffState>) -> Option<Vec<String>> {
     let is_zen_debuff = parts[5] == ZEN_DEBUFF_ID.to_string();
-    let source_unit_state = unit_state_id_only(parts, 6);
-    let source_unit_id = source_unit_state?;
-    let target_unit_id = if parts[16] == "*" {
+    let source_unit_id = unit_state_id_only(parts, 6).ok()?;
+    let target_unit_id = if parts.get(16) == Some(&"*") {
         source_unit_id
     } else {
-        let t = unit_state_id_only(parts, 16);
-        t?;
-        t.unwrap()
+        unit_state_id_only(parts, 16).ok()?
     };
 
     let entry = zen_status.entry(target_unit_id).or_insert_with(|| ZenDebuffState {
@@ -215,7 +306,7 @@ fn add_zen_stacks(parts: &[String], zen_status: &mut HashMap<u32, ZenDebuffState
         return None
     }
 
-    let event_type = parts.get(2).map(|s| EffectChangedEventType::from(s.as_str())).unwrap_or(EffectChangedEventType::Unknown);
+    let event_type = parts.get(2).map(|s| EffectChangedEventType::from(*s)).unwrap_or(EffectChangedEventType::Unknown);
     let ability_id = parts[5].parse::<u32>().unwrap_or(0);
 
     if is_zen_debuff {
@@ -283,7 +374,28 @@ fn add_zen_stacks(parts: &[String], zen_status: &mut HashMap<u32, ZenDebuffState
     None
 }
 
-fn add_arcanist_beam_cast(parts: &[String]) -> Option<Vec<String>> {
+/// The arcanist beams are only logged as buffs, so add casts for them and give them proper icons
+#[derive(Default)]
+struct ArcanistBeams {
+    known_ids: HashSet<u32>,
+}
+
+impl LogFix for ArcanistBeams {
+    fn name(&self) -> &'static str {"arcanist_beams"}
+    fn description(&self) -> &'static str {"Adds casts and icons for Pragmatic and Exhausting Fatecarver"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::EffectChanged, EventType::AbilityInfo, EventType::EffectInfo]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        match parts.get(1) {
+            Some(&"EFFECT_CHANGED") if parts.len() >= 17 => add_arcanist_beam_cast(parts).map_or(FixOutput::Keep, FixOutput::Append),
+            Some(&"ABILITY_INFO") => add_arcanist_beam_information(parts, &self.known_ids).map_or(FixOutput::Keep, FixOutput::Replace),
+            Some(&"EFFECT_INFO") => add_arcanist_beam_effect_information(parts, &mut self.known_ids).map_or(FixOutput::Keep, FixOutput::Replace),
+            _ => FixOutput::Keep,
+        }
+    }
+}
+
+fn add_arcanist_beam_cast(parts: &[&str]) -> Option<Vec<String>> {
     if parts[5] == PRAGMATIC.to_string() || parts[5] == EXHAUSTING.to_string() {
         if parts[2] == "GAINED" {
             let duration = 4500 + if parts[5] == EXHAUSTING.to_string() { 1000 } else { 0 };
@@ -303,270 +415,61 @@ fn add_arcanist_beam_cast(parts: &[String]) -> Option<Vec<String>> {
     None
 }
 
-fn check_ability_info(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
-    let ability = parse::ability(parts);
-    if let Some(ref scribing) = ability.scribing {
-        if let Some((existing_index, existing_ability)) = custom_log_data.scribing_abilities
-            .iter()
-            .enumerate()
-            .find(|(_, a)| a.name == ability.name && a.scribing.as_ref() == Some(scribing))
-        {
-            custom_log_data.scribing_map.insert(ability.id, existing_index);
-            let focus_script = &existing_ability.scribing.as_ref().unwrap()[0];
-            let signature_script = &existing_ability.scribing.as_ref().unwrap()[1];
-            let affix_script = &existing_ability.scribing.as_ref().unwrap()[2];
-            let new_name = format!("{} ({} / {})", &existing_ability.name, signature_script, affix_script);
-            return Some(vec![
-                format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
-                    parts[0], "ABILITY_INFO", existing_ability.id, new_name, existing_ability.icon, "F", "T", focus_script, signature_script, affix_script),
-                format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
-                    parts[0], "ABILITY_INFO", ability.id, ability.name, existing_ability.icon, "F", "T", focus_script, signature_script, affix_script)
-            ]);
-        }
-        let ability_name_clone = ability.name.clone();
-        let ability_id_clone = ability.id;
-        let scribing_ability = ScribingAbility {
-            id: BEGIN_SCRIBING_ABILITIES + custom_log_data.scribing_abilities.len() as u32,
-            name: ability.name,
-            icon: ability.icon,
-            scribing: ability.scribing,
-        };
-        custom_log_data.scribing_abilities.push(scribing_ability);
-        let index = custom_log_data.scribing_abilities.len() - 1;
-        let scribing_ability = &custom_log_data.scribing_abilities[index];
-        custom_log_data.scribing_map.insert(ability.id, index);
-        let focus_script = &scribing_ability.scribing.as_ref().unwrap()[0];
-        let signature_script = &scribing_ability.scribing.as_ref().unwrap()[1];
-        let affix_script = &scribing_ability.scribing.as_ref().unwrap()[2];
-        let new_name = format!("{} ({} / {})", &scribing_ability.name, signature_script, affix_script);
-        Some(vec![
-            format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
-                parts[0], "ABILITY_INFO", scribing_ability.id, new_name, scribing_ability.icon, "F", "T", focus_script, signature_script, affix_script),
-            format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
-                parts[0], "ABILITY_INFO", ability_id_clone, ability_name_clone, scribing_ability.icon, "F", "T", focus_script, signature_script, affix_script)
-        ])
-    } else if (parts[2] == PRAGMATIC.to_string() && !custom_log_data.known_ids.contains_key(PRAGMATIC)) || (parts[2] == EXHAUSTING.to_string() && !custom_log_data.known_ids.contains_key(EXHAUSTING)) {
-        add_arcanist_beam_information(parts, custom_log_data)
-    } else if parts[2].parse::<u32>().ok() == Some(BLOCKADE_DEFAULT) && !custom_log_data.known_ids.contains_key(&BLOCKADE_DEFAULT) {
-        add_blockade_versions(parts, custom_log_data)
-    } else {
-        custom_log_data.known_ids.insert(ability.id, true);
-        let icon = if ability.id == 263672 {Some("/esoui/art/icons/achievement_u25_dun2_meta.dds")} else {None};
-        if matches!(ability.id,
-            266179 | // templar
-            263411 | 263416 | 263419 | 263369 | 268372 | // arcanist
-            238232 | 263208 | // dragonknight
-            263604 | 263605 | // nightblade
-            263873 | 263878 | 263874 | // sorc
-            263462 | 263549 | // necro
-            263522 // warden
-        ) {
-            let name = format!("Class Mastery: {}", ability.name.clone());
-            return Some(vec!(format!("{},{},{},\"{}\",\"{}\",{},{}",
-                parts[0], "ABILITY_INFO", ability.id, name, icon.unwrap_or(&ability.icon), torf_from_bool(ability.blockable), torf_from_bool(ability.interruptible))));
-        }
-        return None
-    }
-}
-
-fn add_arcanist_beam_information(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
+fn add_arcanist_beam_information(parts: &[&str], known_ids: &HashSet<u32>) -> Option<Vec<String>> {
     let mut lines = Vec::new();
-    if parts[2] == PRAGMATIC.to_string() && !custom_log_data.known_ids.contains_key(PRAGMATIC) {
+    if parts[2] == PRAGMATIC.to_string() && !known_ids.contains(PRAGMATIC) {
         lines.push(format!("{},{},{},{},{},{},{}", parts[0], parts[1], PRAGMATIC, parts[3], "\"/esoui/art/icons/ability_arcanist_002_b.dds\"", "F", "T"));
         return Some(lines);
-    } else if parts[2] == EXHAUSTING.to_string() && !custom_log_data.known_ids.contains_key(EXHAUSTING) {
+    } else if parts[2] == EXHAUSTING.to_string() && !known_ids.contains(EXHAUSTING) {
         lines.push(format!("{},{},{},{},{},{},{}", parts[0], parts[1], EXHAUSTING, parts[3], "\"/esoui/art/icons/ability_arcanist_002_a.dds\"", "F", "T"));
         return Some(lines);
     }
     None
 }
 
-fn add_arcanist_beam_effect_information(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
+fn add_arcanist_beam_effect_information(parts: &[&str], known_ids: &mut HashSet<u32>) -> Option<Vec<String>> {
     let mut lines = Vec::new();
-    if parts[2] == PRAGMATIC.to_string() && !custom_log_data.known_ids.contains_key(PRAGMATIC) {
+    if parts[2] == PRAGMATIC.to_string() && !known_ids.contains(PRAGMATIC) {
         lines.push(format!("{},{},{},{},{},{}", parts[0], "EFFECT_INFO", PRAGMATIC, "BUFF", "NONE", "NEVER"));
-        custom_log_data.known_ids.insert(*PRAGMATIC, true);
+        known_ids.insert(*PRAGMATIC);
         return Some(lines);
-    } else if parts[2] == EXHAUSTING.to_string() && !custom_log_data.known_ids.contains_key(EXHAUSTING) {
+    } else if parts[2] == EXHAUSTING.to_string() && !known_ids.contains(EXHAUSTING) {
         lines.push(format!("{},{},{},{},{},{}", parts[0], "EFFECT_INFO", EXHAUSTING, "BUFF", "NONE", "NEVER"));
-        custom_log_data.known_ids.insert(*EXHAUSTING, true);
+        known_ids.insert(*EXHAUSTING);
         return Some(lines);
     }
     None
 }
 
-const BLOCKADE_FIRE: u32 = 39012;
-const BLOCKADE_STORMS: u32 = 39018;
-const BLOCKADE_FROST: u32 = 39028;
-const BLOCKADE_DEFAULT: u32 = 39011;
+struct MoulderingTaintState {
+    stacks: u8,
+    last_timestamp: u64,
+    last_source_unit_state: UnitState,
+    last_target_unit_state: UnitState,
+    last_cast_id: u32,
+}
 
-fn add_blockade_versions(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
-    let mut lines = Vec::new();
-    // ABILITY_INFO,39011,"Elemental Blockade","/esoui/art/icons/ability_destructionstaff_002a.dds",T,T
-    // ABILITY_INFO,39028,"Blockade of Frost","/esoui/art/icons/ability_destructionstaff_002b.dds",F,T
-	// ABILITY_INFO,39012,"Blockade of Fire","/esoui/art/icons/ability_destructionstaff_004_b.dds",F,T
-    // ABILITY_INFO,39018,"Blockade of Storms","/esoui/art/icons/ability_destructionstaff_003_b.dds",F,T
-	// ABILITY_INFO,62951,"Blockade of Frost","/esoui/art/icons/ability_destructionstaff_002b.dds",F,F
-	// ABILITY_INFO,62912,"Blockade of Fire","/esoui/art/icons/ability_destructionstaff_004_b.dds",F,F
-	// ABILITY_INFO,62990,"Blockade of Storms","/esoui/art/icons/ability_destructionstaff_003_b.dds",F,F
-    lines.push(format!("{},{},{},\"{}\",\"{}\",{},{}", parts[0], "ABILITY_INFO", BLOCKADE_FIRE, "Blockade of Fire", "/esoui/art/icons/ability_destructionstaff_004_b.dds", "F", "T"));
-    lines.push(format!("{},{},{},\"{}\",\"{}\",{},{}", parts[0], "ABILITY_INFO", BLOCKADE_STORMS, "Blockade of Storms", "/esoui/art/icons/ability_destructionstaff_003_b.dds", "F", "T"));
-    lines.push(format!("{},{},{},\"{}\",\"{}\",{},{}", parts[0], "ABILITY_INFO", BLOCKADE_FROST, "Blockade of Frost", "/esoui/art/icons/ability_destructionstaff_002b.dds", "F", "T"));
-    custom_log_data.known_ids.insert(BLOCKADE_FIRE, true);
-    custom_log_data.known_ids.insert(BLOCKADE_STORMS, true);
-    custom_log_data.known_ids.insert(BLOCKADE_FROST, true);
-    custom_log_data.known_ids.insert(BLOCKADE_DEFAULT, true);
-    Some(lines)
-}
-
-fn modify_player_data(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
-    
-    // log::trace!("Modifying player data: {:?}", parts);
-
-    if parts.len() < 7 { // this should never occur
-        return None;
-    }
-
-    let mut primary_ability_id_list: Vec<u32> = parts[parts.len() - 2].split(',').map(|x| x.parse::<u32>().unwrap_or_default()).collect();
-    let mut backup_ability_id_list: Vec<u32> = parts[parts.len() - 1].split(',').map(|x| x.parse::<u32>().unwrap_or_default()).collect();
-
-    let mut frontbar_type = ItemType::Unknown;
-    let mut backbar_type = ItemType::Unknown;
-    let gear_parts: Vec<&str> = parts[5..parts.len()-2].iter().map(|s| s.as_str()).collect();
-
-    let mut processed_gear: Vec<String> = Vec::new();
-    let mut cryptcanon = false;
-    for i in gear_parts {
-        let gear = gear_piece(i);
-        if let Some((gear_piece, slot)) = gear {
-            if gear_piece.item_id == 194509 {cryptcanon = true}
-            // let is_mythic = is_mythic_set(gear_piece_obj.set_id);
-            let gear_str = i.to_string();
-            // if is_mythic { // save for the rainy day where esologs adds functionality for mythic items.. https://discord.com/channels/503331371159257089/714906580646232135/878731437807902760
-            //     if let Some(pos) = gear_str.find("LEGENDARY") {
-            //         gear_str.replace_range(pos..pos + "LEGENDARY".len(), "MYTHIC_OVERRIDE");
-            //     }
-            // }
-            processed_gear.push(format!("[{gear_str}]"));
-            let item_slot = slot;
-            let item_type = ITEM_TYPES.get(&gear_piece.item_id);
-            if let Some(item) = item_type {
-                if item_slot == GearSlot::MainHand {
-                    frontbar_type = *item;
-                } else if item_slot == GearSlot::MainHandBackup {
-                    backbar_type = *item;
-                }
-            }
-        }
-    }
+/// Mouldering Taint is only logged as damage, so add a stacking debuff that fades when it stops ticking
+#[derive(Default)]
+struct MoulderingTaint {
+    stacks: HashMap<u32, MoulderingTaintState>,
+}
 
-    if cryptcanon {
-        if primary_ability_id_list.contains(&195031) || backup_ability_id_list.contains(&195031) {cryptcanon = false} else if primary_ability_id_list.len() == 6 && backup_ability_id_list.len() == 6 {
-            if let Some(last) = primary_ability_id_list.last_mut() {
-                *last = 195031;
-            }
-            if let Some(last) = backup_ability_id_list.last_mut() {
-                *last = 195031;
-            }
-        }
-    }
+impl LogFix for MoulderingTaint {
+    fn name(&self) -> &'static str {"mouldering_taint"}
+    fn description(&self) -> &'static str {"Adds a stacking debuff for Mouldering Taint"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::CombatEvent]}
 
-    let player_id = parts[2].parse::<u32>().unwrap();
-    let player_name = custom_log_data.units.get(&player_id).cloned().unwrap_or_else(|| player_id.to_string().into());
-
-    for id in &mut primary_ability_id_list {
-        if matches!(*id, BLOCKADE_DEFAULT | BLOCKADE_FIRE | BLOCKADE_FROST | BLOCKADE_STORMS) {
-            *id = match frontbar_type {
-                ItemType::FrostStaff => BLOCKADE_FROST,
-                ItemType::FireStaff => BLOCKADE_FIRE,
-                ItemType::LightningStaff => BLOCKADE_STORMS,
-                _ => BLOCKADE_DEFAULT,
-            };
-        } else if let Some(index) = custom_log_data.scribing_unit_map.get(&(player_name.clone(), *id)) {
-            // log::trace!("Setting {} to scribing {:?} for {}", player_name, custom_log_data.scribing_abilities[*index].scribing, id);
-            *id = BEGIN_SCRIBING_ABILITIES + *index as u32;
-        } else if custom_log_data.scribing_map.contains_key(id) {
-            if let Some(index) = custom_log_data.scribing_map.get(id) {
-                custom_log_data.scribing_unit_map.insert((player_name.clone(), *id), *index);
-                *id = BEGIN_SCRIBING_ABILITIES + *index as u32;
-            }
-        }
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        add_mouldering_taint_stacks(parts, &mut self.stacks).map_or(FixOutput::Keep, FixOutput::Append)
     }
 
-    for id in &mut backup_ability_id_list {
-        if matches!(*id, BLOCKADE_DEFAULT | BLOCKADE_FIRE | BLOCKADE_FROST | BLOCKADE_STORMS) {
-            *id = match backbar_type {
-                ItemType::FrostStaff => BLOCKADE_FROST,
-                ItemType::FireStaff => BLOCKADE_FIRE,
-                ItemType::LightningStaff => BLOCKADE_STORMS,
-                _ => BLOCKADE_DEFAULT,
-            };
-        } else if let Some(index) = custom_log_data.scribing_unit_map.get(&(player_name.clone(), *id)) {
-            // log::trace!("Setting {} to scribing {:?} for {}", player_name, custom_log_data.scribing_abilities[*index].scribing, id);
-            *id = BEGIN_SCRIBING_ABILITIES + *index as u32;
-        } else if custom_log_data.scribing_map.contains_key(id) {
-            if let Some(index) = custom_log_data.scribing_map.get(id) {
-                custom_log_data.scribing_unit_map.insert((player_name.clone(), *id), *index);
-                *id = BEGIN_SCRIBING_ABILITIES + *index as u32;
-            }
-        }
+    fn reset(&mut self) {
+        self.stacks.clear();
     }
+}
 
-    let mut result = Vec::new();
-
-    let mut long_term_buffs: Vec<u32> = parts[3].split(',').map(|x| x.parse::<u32>().unwrap_or_default()).collect();
-    let mut long_term_buff_stacks: Vec<u8> = parts[4].split(',').map(|x| x.parse::<u8>().unwrap_or_default()).collect();
-    let mut subclasses_to_append = Vec::new();
-    for ability_id in long_term_buffs.iter().chain(primary_ability_id_list.iter()).chain(backup_ability_id_list.iter()) {
-        if let Some(subclass) = ability_id_to_subclass(ability_id) {
-            if !custom_log_data.known_ids.contains_key(&(subclass as u32)) {
-                let subclass_definition = format!(
-                    "{},ABILITY_INFO,{},\"Subclass: {}\",\"{}\",F,T",
-                    parts[0],
-                    subclass as u32,
-                    subclass_to_name(subclass),
-                    subclass_to_icon(subclass)
-                );
-                let subclass_effect_info = format!(
-                    "{},EFFECT_INFO,{},BUFF,NONE,DEFAULT",
-                    parts[0],
-                    subclass as u32
-                );
-                result.push(subclass_definition);
-                result.push(subclass_effect_info);
-                custom_log_data.known_ids.insert(subclass as u32, true);
-            }
-            subclasses_to_append.push(subclass);
-        }
-    }
-    subclasses_to_append.sort_by(|a, b| (*a as u32).cmp(&(*b as u32)));
-    subclasses_to_append.dedup();
-    for subclass in &subclasses_to_append {
-        long_term_buffs.push((*subclass as u32).clone());
-        long_term_buff_stacks.push(1);
-    }
-    custom_log_data.subclassing_map.insert(player_name.to_string(), Some(subclasses_to_append));
-    
-    let mut new_parts: Vec<String> = vec![
-        format!("{}", parts[0]),
-        format!("{}", parts[1]),
-        format!("{}", parts[2]),
-        format!("[{}]", long_term_buffs.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")),
-        format!("[{}]", long_term_buff_stacks.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")),
-    ];
-    new_parts.push(format!("[{}]", processed_gear.join(",")));
-    new_parts.push(format!("[{}]", primary_ability_id_list.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")));
-    new_parts.push(format!("[{}]", backup_ability_id_list.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")));
-
-    if cryptcanon && !custom_log_data.known_ids.contains_key(&195031) {
-        result.push(format!("{},ABILITY_INFO,195031,\"Crypt Transfer\",\"/esoui/art/icons/u38_ability_armor_ultimatetransfer.dds\",F,T", parts[0]));
-        custom_log_data.known_ids.insert(195031, true);
-    }
-    result.push(new_parts.join(","));
-    Some(result)
-}
-
-fn modify_combat_event(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
+fn add_mouldering_taint_stacks(parts: &[&str], taint_stacks: &mut HashMap<u32, MoulderingTaintState>) -> Option<Vec<String>> {
     let ability_id = match parts[8].parse::<u32>() {
         Ok(id) => id,
         Err(_) => return None,
@@ -576,26 +479,15 @@ fn modify_combat_event(parts: &[String], custom_log_data: &mut CustomLogData) ->
         Err(_) => return None,
     };
 
-    let event_type = parse_event_result(&parts[2]);
-
-    if event_type == Some(EventResult::SoulGemResurrectionAccepted) {
-        let mut lines = Vec::new();
-        let mut line = format!("{},BEGIN_CAST,0,F,0,26770,", parts[0]);
-        line.push_str(&parts[9..].join(","));
-        lines.push(line);
-        lines.push(format!("{},END_CAST,COMPLETED,0,26770", parts[0]));
-        return Some(lines);
-    }
-
     let mo_taint_time = *MOULDERING_TAINT_TIME as u64;
 
     match ability_id {
         id if id == *MOULDERING_TAINT_ID => {
-            let source = parse::unit_state(parts, 9);
-            let target = parse::unit_state(parts, 19);
+            let source = parse::unit_state(parts, 9).ok()?;
+            let target = parse::unit_state(parts, 19).ok()?;
             let cast_track_id = parts[7].parse::<u32>().unwrap();
 
-            let entry = custom_log_data.taint_stacks.entry(target.unit_id)
+            let entry = taint_stacks.entry(target.unit_id)
                 .or_insert_with(|| MoulderingTaintState {
                     stacks: 0,
                     last_timestamp: time,
@@ -631,14 +523,14 @@ fn modify_combat_event(parts: &[String], custom_log_data: &mut CustomLogData) ->
         },
         _ => {
             let mut removed = Vec::new();
-            if custom_log_data.taint_stacks.is_empty() {
+            if taint_stacks.is_empty() {
                 return None;
             }
 
-            for (id, entry) in custom_log_data.taint_stacks.iter_mut() {
-                if parts[19] != "*" && parse::unit_state_id_only(parts, 19) == Some(*id) {
-                    let target = parse::unit_state(parts, 19);
-                    if (time > entry.last_timestamp + mo_taint_time || (event::parse_event_result(&parts[2]).unwrap() == EventResult::Died || target.health == 0)) && entry.stacks > 0 {
+            for (id, entry) in taint_stacks.iter_mut() {
+                if parts[19] != "*" && parse::unit_state_id_only(parts, 19).ok() == Some(*id) {
+                    let Ok(target) = parse::unit_state(parts, 19) else {continue};
+                    if (time > entry.last_timestamp + mo_taint_time || (event::parse_event_result(parts[2]).unwrap() == EventResult::Died || target.health == 0)) && entry.stacks > 0 {
                         entry.last_timestamp = time;
                         entry.stacks = 0;
                         let e = entry.last_source_unit_state;
@@ -669,7 +561,7 @@ fn modify_combat_event(parts: &[String], custom_log_data: &mut CustomLogData) ->
                 }
             }
             for k in removed {
-                custom_log_data.taint_stacks.remove_entry(&k);
+                taint_stacks.remove_entry(&k);
             }
         }
     }
@@ -677,28 +569,402 @@ fn modify_combat_event(parts: &[String], custom_log_data: &mut CustomLogData) ->
     None
 }
 
-fn handle_unit_added(parts: &[String], custom_log_data: &mut CustomLogData) -> Option<Vec<String>> {
-    let event = UnitAddedEventType::from(parts.get(3).unwrap().as_str());
+const SOUL_GEM_RESURRECTION: &str = "26770";
+
+/// Logs the resurrection when a soul gem is accepted instead of when it is offered
+struct SoulGemResurrection;
+
+impl LogFix for SoulGemResurrection {
+    fn name(&self) -> &'static str {"soul_gem_resurrection"}
+    fn description(&self) -> &'static str {"Logs soul gem resurrections as completed casts"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::CombatEvent, EventType::BeginCast]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        if parts[1] == "BEGIN_CAST" {
+            return if parts.get(5) == Some(&SOUL_GEM_RESURRECTION) {FixOutput::Replace(Vec::new())} else {FixOutput::Keep};
+        }
+        if parse_event_result(parts[2]) != Some(EventResult::SoulGemResurrectionAccepted) {return FixOutput::Keep}
+        let mut line = format!("{},BEGIN_CAST,0,F,0,{SOUL_GEM_RESURRECTION},", parts[0]);
+        line.push_str(&parts[9..].join(","));
+        FixOutput::Append(vec![line, format!("{},END_CAST,COMPLETED,0,{SOUL_GEM_RESURRECTION}", parts[0])])
+    }
+}
+
+/// Prefixes the class mastery passives so they are recognisable among other buffs
+struct ClassMastery;
+
+impl LogFix for ClassMastery {
+    fn name(&self) -> &'static str {"class_mastery"}
+    fn description(&self) -> &'static str {"Names the class mastery passives"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::AbilityInfo]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        let Ok(ability) = parse::ability(parts) else {return FixOutput::Keep};
+        if ability.scribing.is_some() {return FixOutput::Keep}
+        let icon = if ability.id == 263672 {Some("/esoui/art/icons/achievement_u25_dun2_meta.dds")} else {None};
+        if matches!(ability.id,
+            266179 | // templar
+            263411 | 263416 | 263419 | 263369 | 268372 | // arcanist
+            238232 | 263208 | // dragonknight
+            263604 | 263605 | // nightblade
+            263873 | 263878 | 263874 | // sorc
+            263462 | 263549 | // necro
+            263522 // warden
+        ) {
+            let name = format!("Class Mastery: {}", ability.name.clone());
+            return FixOutput::Replace(vec!(format!("{},{},{},\"{}\",\"{}\",{},{}",
+                parts[0], "ABILITY_INFO", ability.id, name, icon.unwrap_or(&ability.icon), torf_from_bool(ability.blockable), torf_from_bool(ability.interruptible))));
+        }
+        FixOutput::Keep
+    }
+}
+
+/// A PLAYER_INFO line split into the lists fixes rewrite
+struct PlayerInfoLine<'a> {
+    parts: &'a [&'a str],
+    long_term_buffs: Vec<u32>,
+    long_term_buff_stacks: Vec<u8>,
+    gear: Vec<&'a str>,
+    item_ids: Vec<u32>,
+    frontbar_type: ItemType,
+    backbar_type: ItemType,
+    primary_ability_id_list: Vec<u32>,
+    backup_ability_id_list: Vec<u32>,
+}
+
+impl<'a> PlayerInfoLine<'a> {
+    fn parse(parts: &'a [&'a str]) -> Option<Self> {
+        if parts.len() < 7 { // this should never occur
+            return None;
+        }
+
+        let mut player_info = Self {
+            parts,
+            long_term_buffs: parts[3].split(',').map(|x| x.parse::<u32>().unwrap_or_default()).collect(),
+            long_term_buff_stacks: parts[4].split(',').map(|x| x.parse::<u8>().unwrap_or_default()).collect(),
+            gear: Vec::new(),
+            item_ids: Vec::new(),
+            frontbar_type: ItemType::Unknown,
+            backbar_type: ItemType::Unknown,
+            primary_ability_id_list: parts[parts.len() - 2].split(',').map(|x| x.parse::<u32>().unwrap_or_default()).collect(),
+            backup_ability_id_list: parts[parts.len() - 1].split(',').map(|x| x.parse::<u32>().unwrap_or_default()).collect(),
+        };
+
+        for i in parts[5..parts.len()-2].iter().filter(|i| !i.is_empty()) {
+            player_info.gear.push(i);
+            let gear = gear_piece(i).unwrap_or_else(|e| {log::debug!("{e}"); None});
+            if let Some((gear_piece, slot)) = gear {
+                // if is_mythic_set(gear_piece.set_id) { // save for the rainy day where esologs adds functionality for mythic items.. https://discord.com/channels/503331371159257089/714906580646232135/878731437807902760
+                //     replace LEGENDARY with MYTHIC_OVERRIDE
+                // }
+                player_info.item_ids.push(gear_piece.item_id);
+                if let Some(item) = ITEM_TYPES.get(&gear_piece.item_id) {
+                    if slot == GearSlot::MainHand {
+                        player_info.frontbar_type = *item;
+                    } else if slot == GearSlot::MainHandBackup {
+                        player_info.backbar_type = *item;
+                    }
+                }
+            }
+        }
+        Some(player_info)
+    }
+
+    fn player_id(&self) -> u32 {
+        self.parts[2].parse::<u32>().unwrap_or_default()
+    }
+
+    fn to_line(&self) -> String {
+        let new_parts: Vec<String> = vec![
+            self.parts[0].to_string(),
+            self.parts[1].to_string(),
+            self.parts[2].to_string(),
+            format!("[{}]", self.long_term_buffs.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")),
+            format!("[{}]", self.long_term_buff_stacks.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")),
+            format!("[{}]", self.gear.iter().map(|g| format!("[{g}]")).collect::<Vec<_>>().join(",")),
+            format!("[{}]", self.primary_ability_id_list.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")),
+            format!("[{}]", self.backup_ability_id_list.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")),
+        ];
+        new_parts.join(",")
+    }
+}
+
+const CRYPTCANON: u32 = 194509;
+const CRYPT_TRANSFER: u32 = 195031;
+
+/// The Cryptcanon Vestments ultimate is not shown on the bars, so put Crypt Transfer in the ultimate slots
+#[derive(Default)]
+struct Cryptcanon {
+    crypt_transfer_known: bool,
+}
+
+impl LogFix for Cryptcanon {
+    fn name(&self) -> &'static str {"cryptcanon"}
+    fn description(&self) -> &'static str {"Shows Crypt Transfer on the bars of players wearing Cryptcanon Vestments"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::AbilityInfo, EventType::PlayerInfo]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        if parts[1] == "ABILITY_INFO" {
+            if parts[2] == CRYPT_TRANSFER.to_string() {self.crypt_transfer_known = true}
+            return FixOutput::Keep;
+        }
+        let Some(mut player_info) = PlayerInfoLine::parse(parts) else {return FixOutput::Keep};
+        if !player_info.item_ids.contains(&CRYPTCANON) {return FixOutput::Keep}
+        let primary = &mut player_info.primary_ability_id_list;
+        let backup = &mut player_info.backup_ability_id_list;
+        if primary.contains(&CRYPT_TRANSFER) || backup.contains(&CRYPT_TRANSFER) {return FixOutput::Keep}
+        if primary.len() == 6 && backup.len() == 6 {
+            if let Some(last) = primary.last_mut() {
+                *last = CRYPT_TRANSFER;
+            }
+            if let Some(last) = backup.last_mut() {
+                *last = CRYPT_TRANSFER;
+            }
+        }
+
+        let mut result = Vec::new();
+        if !self.crypt_transfer_known {
+            result.push(format!("{},ABILITY_INFO,{CRYPT_TRANSFER},\"Crypt Transfer\",\"/esoui/art/icons/u38_ability_armor_ultimatetransfer.dds\",F,T", parts[0]));
+            self.crypt_transfer_known = true;
+        }
+        result.push(player_info.to_line());
+        FixOutput::Replace(result)
+    }
+}
+
+const BLOCKADE_FIRE: u32 = 39012;
+const BLOCKADE_STORMS: u32 = 39018;
+const BLOCKADE_FROST: u32 = 39028;
+const BLOCKADE_DEFAULT: u32 = 39011;
+
+/// Elemental Blockade is logged with the same id for every staff, so use the version matching the staff on each bar
+#[derive(Default)]
+struct BlockadeVersions {
+    versions_added: bool,
+}
+
+impl LogFix for BlockadeVersions {
+    fn name(&self) -> &'static str {"blockade_versions"}
+    fn description(&self) -> &'static str {"Shows the fire, frost or storms version of Elemental Blockade"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::AbilityInfo, EventType::PlayerInfo]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        if parts[1] == "ABILITY_INFO" {
+            if parts[2].parse::<u32>().ok() == Some(BLOCKADE_DEFAULT) && !self.versions_added {
+                self.versions_added = true;
+                return FixOutput::Replace(add_blockade_versions(parts));
+            }
+            return FixOutput::Keep;
+        }
+
+        let Some(mut player_info) = PlayerInfoLine::parse(parts) else {return FixOutput::Keep};
+        let mut changed = false;
+        for (bar, weapon_type) in [
+            (&mut player_info.primary_ability_id_list, player_info.frontbar_type),
+            (&mut player_info.backup_ability_id_list, player_info.backbar_type),
+        ] {
+            for id in bar.iter_mut() {
+                if !matches!(*id, BLOCKADE_DEFAULT | BLOCKADE_FIRE | BLOCKADE_FROST | BLOCKADE_STORMS) {continue}
+                let version = match weapon_type {
+                    ItemType::FrostStaff => BLOCKADE_FROST,
+                    ItemType::FireStaff => BLOCKADE_FIRE,
+                    ItemType::LightningStaff => BLOCKADE_STORMS,
+                    _ => BLOCKADE_DEFAULT,
+                };
+                changed |= *id != version;
+                *id = version;
+            }
+        }
+        if changed {FixOutput::Replace(vec![player_info.to_line()])} else {FixOutput::Keep}
+    }
+}
+
+fn add_blockade_versions(parts: &[&str]) -> Vec<String> {
+    let mut lines = Vec::new();
+    // ABILITY_INFO,39011,"Elemental Blockade","/esoui/art/icons/ability_destructionstaff_002a.dds",T,T
+    // ABILITY_INFO,39028,"Blockade of Frost","/esoui/art/icons/ability_destructionstaff_002b.dds",F,T
+	// ABILITY_INFO,39012,"Blockade of Fire","/esoui/art/icons/ability_destructionstaff_004_b.dds",F,T
+    // ABILITY_INFO,39018,"Blockade of Storms","/esoui/art/icons/ability_destructionstaff_003_b.dds",F,T
+	// ABILITY_INFO,62951,"Blockade of Frost","/esoui/art/icons/ability_destructionstaff_002b.dds",F,F
+	// ABILITY_INFO,62912,"Blockade of Fire","/esoui/art/icons/ability_destructionstaff_004_b.dds",F,F
+	// ABILITY_INFO,62990,"Blockade of Storms","/esoui/art/icons/ability_destructionstaff_003_b.dds",F,F
+    lines.push(format!("{},{},{},\"{}\",\"{}\",{},{}", parts[0], "ABILITY_INFO", BLOCKADE_FIRE, "Blockade of Fire", "/esoui/art/icons/ability_destructionstaff_004_b.dds", "F", "T"));
+    lines.push(format!("{},{},{},\"{}\",\"{}\",{},{}", parts[0], "ABILITY_INFO", BLOCKADE_STORMS, "Blockade of Storms", "/esoui/art/icons/ability_destructionstaff_003_b.dds", "F", "T"));
+    lines.push(format!("{},{},{},\"{}\",\"{}\",{},{}", parts[0], "ABILITY_INFO", BLOCKADE_FROST, "Blockade of Frost", "/esoui/art/icons/ability_destructionstaff_002b.dds", "F", "T"));
+    lines
+}
+
+const BEGIN_SCRIBING_ABILITIES: u32 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct ScribingAbility {
+    pub id: u32,
+    pub name: Arc<str>,
+    pub icon: Arc<str>,
+    pub scribing: Option<Vec<String>>,
+}
+
+/// Every scribed skill shares one ability id, so give each combination of scripts its own id and name
+#[derive(Default)]
+struct ScribingAbilities {
+    scribing_abilities: Vec<ScribingAbility>,
+    scribing_map: HashMap<u32, usize>,
+    scribing_unit_map: HashMap<(Arc<str>, u32), usize>,
+    units: HashMap<u32, Arc<str>>,
+}
+
+impl LogFix for ScribingAbilities {
+    fn name(&self) -> &'static str {"scribing"}
+    fn description(&self) -> &'static str {"Separates scribed skills by their scripts"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::AbilityInfo, EventType::PlayerInfo, EventType::UnitAdded]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        let output = match parts[1] {
+            "ABILITY_INFO" => self.check_ability_info(parts),
+            "PLAYER_INFO" => self.modify_player_data(parts),
+            _ => {
+                self.handle_unit_added(parts);
+                None
+            }
+        };
+        output.map_or(FixOutput::Keep, FixOutput::Replace)
+    }
+}
+
+impl ScribingAbilities {
+    fn check_ability_info(&mut self, parts: &[&str]) -> Option<Vec<String>> {
+        let ability = parse::ability(parts).ok()?;
+        let scribing = ability.scribing.as_ref()?;
+        if let Some((existing_index, existing_ability)) = self.scribing_abilities
+            .iter()
+            .enumerate()
+            .find(|(_, a)| a.name == ability.name && a.scribing.as_ref() == Some(scribing))
+        {
+            self.scribing_map.insert(ability.id, existing_index);
+            let focus_script = &existing_ability.scribing.as_ref().unwrap()[0];
+            let signature_script = &existing_ability.scribing.as_ref().unwrap()[1];
+            let affix_script = &existing_ability.scribing.as_ref().unwrap()[2];
+            let new_name = format!("{} ({} / {})", &existing_ability.name, signature_script, affix_script);
+            return Some(vec![
+                format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
+                    parts[0], "ABILITY_INFO", existing_ability.id, new_name, existing_ability.icon, "F", "T", focus_script, signature_script, affix_script),
+                format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
+                    parts[0], "ABILITY_INFO", ability.id, ability.name, existing_ability.icon, "F", "T", focus_script, signature_script, affix_script)
+            ]);
+        }
+        let ability_name_clone = ability.name.clone();
+        let ability_id_clone = ability.id;
+        let scribing_ability = ScribingAbility {
+            id: BEGIN_SCRIBING_ABILITIES + self.scribing_abilities.len() as u32,
+            name: ability.name,
+            icon: ability.icon,
+            scribing: ability.scribing,
+        };
+        self.scribing_abilities.push(scribing_ability);
+        let index = self.scribing_abilities.len() - 1;
+        let scribing_ability = &self.scribing_abilities[index];
+        self.scribing_map.insert(ability.id, index);
+        let focus_script = &scribing_ability.scribing.as_ref().unwrap()[0];
+        let signature_script = &scribing_ability.scribing.as_ref().unwrap()[1];
+        let affix_script = &scribing_ability.scribing.as_ref().unwrap()[2];
+        let new_name = format!("{} ({} / {})", &scribing_ability.name, signature_script, affix_script);
+        Some(vec![
+            format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
+                parts[0], "ABILITY_INFO", scribing_ability.id, new_name, scribing_ability.icon, "F", "T", focus_script, signature_script, affix_script),
+            format!("{},{},{},\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\"",
+                parts[0], "ABILITY_INFO", ability_id_clone, ability_name_clone, scribing_ability.icon, "F", "T", focus_script, signature_script, affix_script)
+        ])
+    }
+
+    fn modify_player_data(&mut self, parts: &[&str]) -> Option<Vec<String>> {
+        let mut player_info = PlayerInfoLine::parse(parts)?;
+        let player_id = player_info.player_id();
+        let player_name = self.units.get(&player_id).cloned().unwrap_or_else(|| player_id.to_string().into());
+
+        let mut changed = false;
+        for id in player_info.primary_ability_id_list.iter_mut().chain(player_info.backup_ability_id_list.iter_mut()) {
+            if let Some(index) = self.scribing_unit_map.get(&(player_name.clone(), *id)) {
+                *id = BEGIN_SCRIBING_ABILITIES + *index as u32;
+                changed = true;
+            } else if let Some(index) = self.scribing_map.get(id) {
+                self.scribing_unit_map.insert((player_name.clone(), *id), *index);
+                *id = BEGIN_SCRIBING_ABILITIES + *index as u32;
+                changed = true;
+            }
+        }
+        changed.then(|| vec![player_info.to_line()])
+    }
+
+    fn handle_unit_added(&mut self, parts: &[&str]) {
+        let Some(event) = parts.get(3).map(|s| UnitAddedEventType::from(*s)) else {return};
         match event {
             UnitAddedEventType::Player => {
-                let player = parse::player(parts);
+                let Ok(player) = parse::player(parts) else {return};
                 let name: Arc<str> = player.name.into();
-                if name == "Offline".into() || name.len() < 3 {return None;}
-                custom_log_data.units.insert(player.unit_id, name.clone());
-                custom_log_data.units.insert(player.player_per_session_id, name);
-            },
-            UnitAddedEventType::Monster => {
-                // let monster = parse::monster(parts);
-                // custom_log_data.units.insert(monster.unit_id, monster.name.clone());
-                // custom_log_data.units.insert(monster.unit_id, monster.name);
-            },
-            UnitAddedEventType::Object | UnitAddedEventType::SiegeWeapon => {
-                // let object = parse::object(parts);
+                if name == "Offline".into() || name.len() < 3 {return}
+                self.units.insert(player.unit_id, name.clone());
+                self.units.insert(player.player_per_session_id, name);
             },
+            UnitAddedEventType::Monster | UnitAddedEventType::Object | UnitAddedEventType::SiegeWeapon => {},
             UnitAddedEventType::Unknown => {
                 log::error!("Unknown unit added unit type");
             },
         }
+    }
+}
 
-    None
-}
\ No newline at end of file
+/// Adds each player's subclassed skill lines as buffs, so they show on the player's summary
+#[derive(Default)]
+struct Subclassing {
+    known_ids: HashSet<u32>,
+}
+
+impl LogFix for Subclassing {
+    fn name(&self) -> &'static str {"subclassing"}
+    fn description(&self) -> &'static str {"Adds each player's subclassed skill lines as buffs"}
+    fn event_types(&self) -> &'static [EventType] {&[EventType::AbilityInfo, EventType::PlayerInfo]}
+
+    fn apply(&mut self, parts: &[&str]) -> FixOutput {
+        if parts[1] == "ABILITY_INFO" {
+            if let Ok(id) = parts[2].parse::<u32>() {
+                self.known_ids.insert(id);
+            }
+            return FixOutput::Keep;
+        }
+
+        let Some(mut player_info) = PlayerInfoLine::parse(parts) else {return FixOutput::Keep};
+        let mut result = Vec::new();
+        let mut subclasses_to_append = Vec::new();
+        for ability_id in player_info.long_term_buffs.iter().chain(player_info.primary_ability_id_list.iter()).chain(player_info.backup_ability_id_list.iter()) {
+            if let Some(subclass) = ability_id_to_subclass(ability_id) {
+                if !self.known_ids.contains(&(subclass as u32)) {
+                    let subclass_definition = format!(
+                        "{},ABILITY_INFO,{},\"Subclass: {}\",\"{}\",F,T",
+                        parts[0],
+                        subclass as u32,
+                        subclass_to_name(subclass),
+                        subclass_to_icon(subclass)
+                    );
+                    let subclass_effect_info = format!(
+                        "{},EFFECT_INFO,{},BUFF,NONE,DEFAULT",
+                        parts[0],
+                        subclass as u32
+                    );
+                    result.push(subclass_definition);
+                    result.push(subclass_effect_info);
+                    self.known_ids.insert(subclass as u32);
+                }
+                subclasses_to_append.push(subclass);
+            }
+        }
+        if subclasses_to_append.is_empty() {return FixOutput::Keep}
+        subclasses_to_append.sort_by(|a, b| (*a as u32).cmp(&(*b as u32)));
+        subclasses_to_append.dedup();
+        for subclass in &subclasses_to_append {
+            player_info.long_term_buffs.push(*subclass as u32);
+            player_info.long_term_buff_stacks.push(1);
+        }
+        result.push(player_info.to_line());
+        FixOutput::Replace(result)
+    }
+}
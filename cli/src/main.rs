@@ -1,252 +1,1054 @@
 use std::collections::{HashMap, HashSet};
-use std::{env, fs};
+use std::fs;
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "database")]
+use cli::database::{ingest_log_file, open_database};
 use cli::esologs_convert::{build_master_table, split_and_zip_log_by_fight, ESOLogProcessor};
 use cli::esologs_format::{ESOLogsEvent, ESOLogsLineType};
-use cli::log_edit::modify_log_file;
-use cli::split_log::split_encounter_file_into_log_files;
+use cli::export::export_json_lines;
+use cli::extract::{extract_log_to_file, extracted_log_path, Extract};
+#[cfg(feature = "parquet")]
+use cli::parquet_export::{export_parquet, parquet_output_path};
+use cli::log_edit::{modified_log_path, modify_log_file_with_progress, LogFixes};
+use cli::repair::{repair_log_file, repaired_log_path};
+use cli::split_log::{combine_encounter_log_files_with_progress, split_encounter_file_into_directory};
 use ftail::Ftail;
+use parser::index::LogIndex;
 use log::LevelFilter;
 
-fn main() {
-    let result = Ftail::new()
-        .console(LevelFilter::Trace)
-        .init();
-    match result {
-        Ok(_) => log::info!("Logging initialised"),
-        Err(e) => println!("Error initialising logging: {e}"),
+/// Tools for working with Elder Scrolls Online Encounter.log files
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Print debug logging
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply fixes to a log and write <name>-MODIFIED.log
+    Modify {
+        file: PathBuf,
+        /// Path of the modified log
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Fixes to leave out, as listed by `fixes`
+        #[arg(short, long, value_delimiter = ',')]
+        disable: Vec<String>,
+    },
+    /// List the fixes `modify` applies
+    Fixes,
+    /// Salvage a log left broken by a crash and write <name>-REPAIRED.log, with a list of the changes in <name>-REPAIRED.txt
+    Repair {
+        file: PathBuf,
+        /// Path of the repaired log
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Split a log into one file per BEGIN_LOG
+    Split {
+        file: PathBuf,
+        /// Folder for the split logs, defaults to the folder of the input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Join several logs into one, ordered by start time and without duplicated sessions
+    Combine {
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+        /// Path of the combined log
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write one fight, boss pull or time range of a log as a log of its own
+    #[command(group = ArgGroup::new("part").required(true).args(["fight", "boss", "from"]))]
+    Extract {
+        file: PathBuf,
+        /// Fight to extract, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        /// Extract a pull of the boss with this name
+        #[arg(long)]
+        boss: Option<String>,
+        /// Which pull of --boss to extract, counting from 1. Defaults to the last
+        #[arg(long, requires = "boss")]
+        pull: Option<usize>,
+        /// Start of a time range to extract, as a Unix time in milliseconds
+        #[arg(long, requires = "to")]
+        from: Option<u64>,
+        /// End of the time range to extract, as a Unix time in milliseconds
+        #[arg(long, requires = "from")]
+        to: Option<u64>,
+        /// Path of the extracted log, defaults to a name describing the extract next to the log
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert a log to the esologs.com format as plain text
+    Convert {
+        file: PathBuf,
+        /// Folder for master_table.txt and report_segments.txt
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Convert a log to zipped esologs.com report segments, one per fight
+    Zip {
+        file: PathBuf,
+        /// Folder for the zip files, replaced on each run. Defaults to a folder in the system temp directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Index a log next to it as <name>.idx, or bring the index up to date, so single fights can be read without reading the whole log
+    Index {
+        file: PathBuf,
+    },
+    /// Check a log for structural problems, such as missing BEGIN_LOGs, times going backwards or malformed lines
+    Validate {
+        file: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// List the fights in a log
+    Fights {
+        file: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Damage and healing per player and ability for each fight
+    #[command(alias = "meter")]
+    Stats {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Buff uptime on players and debuff uptime on bosses for each fight
+    Uptime {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Damage and healing each player took before each of their deaths, and whether they were resurrected
+    Deaths {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        /// Seconds before each death to show
+        #[arg(short, long, default_value_t = 10)]
+        window: u64,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Cast timeline of each player, with gaps between casts, cancelled casts, weapon swaps and time on each bar
+    Rotation {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        /// Only show the player with this character or account name
+        #[arg(short, long)]
+        player: Option<String>,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Magicka, stamina and ultimate of each player: averages, sustain, time low on resources and time sitting on a full ultimate
+    Resources {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        /// Only show the player with this character or account name
+        #[arg(short, long)]
+        player: Option<String>,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Split each boss fight into phases by boss health and stretches where the boss could not be damaged, with damage and deaths in each.
+    /// Built-in rules follow the phases of some trial bosses; rules for other bosses, including phases begun by a boss's own abilities, are given with --definitions
+    Phases {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        /// Only show the player with this character or account name
+        #[arg(short, long)]
+        player: Option<String>,
+        /// A file of phase rules in the format of the built-in boss_phase_data.csv, by monster id, boss name or 0 for every boss, replacing the built-in rules of the bosses it covers
+        #[arg(short, long)]
+        definitions: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Trial runs from entering the trial to END_TRIAL, with boss kill times, wipes, deaths, vitality and score
+    Trials {
+        file: PathBuf,
+        /// Append completed runs not already recorded to this CSV file, created if it does not exist
+        #[arg(long)]
+        history: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Build sheet of each player: sets, traits, enchants, weapons, both bars with scribed scripts and skill lines, as Markdown or JSON
+    Builds {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`
+        #[arg(long)]
+        fight: Option<usize>,
+        /// Only show the player with this character or account name
+        #[arg(short, long)]
+        player: Option<String>,
+        /// Write the build sheets to this file instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// `table` writes Markdown
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Where players and bosses moved during each fight and where they died, with SVG path plots and heatmaps and the distance between units
+    Positions {
+        file: PathBuf,
+        /// Only show this fight, numbered as in `fights`. Required to write an SVG
+        #[arg(long)]
+        fight: Option<usize>,
+        /// Only show units with this character or account name, defaults to every player and boss. Can be given more than once
+        #[arg(short, long)]
+        unit: Vec<String>,
+        /// Show the distance from each unit shown to the unit with this name every second
+        #[arg(short, long)]
+        distance_to: Option<String>,
+        /// Write an SVG of the path each unit took to this file
+        #[arg(long)]
+        paths: Option<PathBuf>,
+        /// Write an SVG heatmap of where the units stood to this file
+        #[arg(long)]
+        heatmap: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Write every line of a log as JSON Lines, with ability and unit names resolved
+    Export {
+        file: PathBuf,
+        /// Path of the JSON Lines file, defaults to standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write the events, casts, units, abilities and fights of a log to Parquet files
+    #[cfg(feature = "parquet")]
+    Parquet {
+        file: PathBuf,
+        /// Folder for the Parquet files, defaults to <name>-parquet next to the log
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Add logs to a SQLite database for querying with SQL, replacing any that have changed since they were added
+    #[cfg(feature = "database")]
+    Ingest {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Path of the database, created if it does not exist
+        #[arg(short, long, default_value = "logs.sqlite")]
+        database: PathBuf,
+    },
+    /// Find abilities that hit several targets at once and append them to a csv
+    Aoe {
+        file: PathBuf,
+        #[arg(short, long, default_value = "aoe.csv")]
+        output: PathBuf,
+    },
+    /// Print an esologs.com query matching the abilities in an aoe csv
+    Aoesql {
+        #[arg(default_value = "aoe.csv")]
+        file: PathBuf,
+    },
+    /// Print the parent of every zone
+    Parentzones,
+    /// Print every dungeon zone
+    Dungeons,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let level = if cli.verbose { LevelFilter::Trace } else { LevelFilter::Warn };
+    if let Err(e) = Ftail::new().console(level).init() {
+        eprintln!("Error initialising logging: {e}");
     }
 
-    let args: Vec<String> = env::args().collect();
-    let (file_path, query) = parse_config(&args);
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    match query {
-        "modify" => {
-            if let Err(e) = modify_log_file(Path::new(file_path)) {
-                log::error!("Error modifying log file: {e}");
-            }
+fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Modify { file, output, disable } => {
+            let log_fixes = LogFixes::without(&disable)?;
+            let output = match output {
+                Some(o) => o,
+                None => modified_log_path(&file).map_err(|e| format!("Error modifying log file: {e}"))?,
+            };
+            let progress = |progress: u8| log::info!("Modifying: {progress}%");
+            let dummy_cancel = std::sync::atomic::AtomicBool::new(false);
+            modify_log_file_with_progress(&file, &output, log_fixes, progress, &dummy_cancel).map_err(|e| format!("Error modifying log file: {e}"))
         }
-        "split" => {
-            if let Err(e) = split_encounter_file_into_log_files(Path::new(file_path)) {
-                log::error!("Error splitting log file: {e}");
+        Command::Fixes => {
+            for fix in LogFixes::new().enabled() {
+                println!("{:<24} {}", fix.name(), fix.description());
             }
+            Ok(())
         }
-        "esolog" => {
-            let mut eso_log_processor = ESOLogProcessor::new();
-            if let Err(e) = eso_log_processor.convert_log_file_to_esolog_format(Path::new(file_path)) {
-                log::error!("Error splitting log file: {e}");
-            }
-            eso_log_processor.remove_overabundant_events();
-            if let Ok(file) = File::create("C:/Users/H/Downloads/master_table.txt") {
-                let mut writer = BufWriter::new(file);
-                let master_table = build_master_table(&mut eso_log_processor);
-                if let Err(e) = write!(writer, "{master_table}") {
-                    log::error!("Error writing master_table: {e}");
+        Command::Repair { file, output } => {
+            let output = output.unwrap_or_else(|| repaired_log_path(&file));
+            let report = repair_log_file(&file, &output).map_err(|e| format!("Error repairing log file: {e}"))?;
+            let report_path = output.with_extension("txt");
+            fs::write(&report_path, format!("{report}\n")).map_err(|e| format!("Error writing {}: {e}", report_path.display()))?;
+            println!("{} lines read, {} lines written, {} changes listed in {}", report.lines_read, report.lines_written, report.changes.len(), report_path.display());
+            Ok(())
+        }
+        Command::Split { file, output } => {
+            let output = output.unwrap_or_else(|| file.parent().unwrap_or(Path::new(".")).to_path_buf());
+            split_encounter_file_into_directory(&file, &output).map_err(|e| format!("Error splitting log file: {e}"))
+        }
+        Command::Combine { files, output } => {
+            let progress = |progress: u8| log::info!("Combining: {progress}%");
+            let output = combine_encounter_log_files_with_progress(&files, output.as_deref(), progress).map_err(|e| format!("Error combining log files: {e}"))?;
+            println!("{}", output.display());
+            Ok(())
+        }
+        Command::Extract { file, fight, boss, pull, from, to, output } => {
+            let extract = match (fight, boss, from, to) {
+                (Some(fight), _, _, _) => Extract::Fight(fight),
+                (_, Some(name), _, _) => Extract::Boss { name, pull },
+                (_, _, Some(from), Some(to)) => Extract::Time { from, to },
+                _ => return Err("Choose a fight, boss or time range to extract".to_owned()),
+            };
+            let output = output.unwrap_or_else(|| extracted_log_path(&file, &extract));
+            extract_log_to_file(&file, &extract, &output)?;
+            println!("{}", output.display());
+            Ok(())
+        }
+        Command::Convert { file, output } => convert(&file, &output),
+        Command::Zip { file, output } => {
+            let output = output.unwrap_or_else(|| std::env::temp_dir().join("esologtool_temporary"));
+            // The output folder is cleared before writing, so refuse to touch one holding anything else
+            if let Ok(entries) = fs::read_dir(&output) {
+                let foreign = entries.flatten().any(|entry| {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    !(name.ends_with(".zip") || name == "timestamps")
+                });
+                if foreign {
+                    return Err(format!("{} contains files that are not report segments, choose an empty folder", output.display()));
                 }
-            } else {
-                log::error!("Error creating output file: master_table.txt");
-                return;
             }
+            let noop = |_progress: u8| {};
+            let dummy_cancel = std::sync::atomic::AtomicBool::new(false);
+            split_and_zip_log_by_fight(&file, &output, noop, &dummy_cancel)?;
+            println!("{}", output.display());
+            Ok(())
+        }
+        Command::Index { file } => {
+            let index = LogIndex::open(&file).map_err(|e| format!("Error indexing {}: {e}", file.display()))?;
+            println!("{}: {} lines indexed, {} fights", LogIndex::path_for(&file).display(), index.entries.len(), index.fights().len());
+            Ok(())
+        }
+        Command::Validate { file, format } => validate(&file, format),
+        Command::Fights { file, format } => fights(&file, format),
+        Command::Stats { file, fight, format } => stats(&file, fight, format),
+        Command::Uptime { file, fight, format } => uptime(&file, fight, format),
+        Command::Deaths { file, fight, window, format } => deaths(&file, fight, window, format),
+        Command::Rotation { file, fight, player, format } => rotation(&file, fight, player.as_deref(), format),
+        Command::Resources { file, fight, player, format } => resources(&file, fight, player.as_deref(), format),
+        Command::Phases { file, fight, player, definitions, format } => phases(&file, fight, player.as_deref(), definitions.as_deref(), format),
+        Command::Trials { file, history, format } => trials(&file, history.as_deref(), format),
+        Command::Builds { file, fight, player, output, format } => builds(&file, fight, player.as_deref(), output.as_deref(), format),
+        Command::Positions { file, fight, unit, distance_to, paths, heatmap, format } => {
+            positions(&file, fight, &unit, distance_to.as_deref(), paths.as_deref(), heatmap.as_deref(), format)
+        }
+        Command::Export { file, output } => export(&file, output.as_deref()),
+        #[cfg(feature = "parquet")]
+        Command::Parquet { file, output } => {
+            let output = match output {
+                Some(o) => o,
+                None => parquet_output_path(&file)?,
+            };
+            export_parquet(open_log(&file)?, &output)?;
+            println!("{}", output.display());
+            Ok(())
+        }
+        #[cfg(feature = "database")]
+        Command::Ingest { files, database } => ingest(&files, &database),
+        Command::Aoe { file, output } => aoe(&file, &output),
+        Command::Aoesql { file } => {
+            let content = fs::read_to_string(&file).map_err(|e| format!("Failed to read {}: {e}", file.display()))?;
+            let ids: Vec<&str> = content
+                .lines()
+                .filter_map(|line| line.split(',').next())
+                .collect();
+            let id_list = ids.join(",");
+            println!("ability.id IN ({id_list})");
+            Ok(())
+        }
+        Command::Parentzones => {
+            parser::zone::print_parent_zones();
+            Ok(())
+        }
+        Command::Dungeons => {
+            parser::zone::print_dungeon_zones();
+            Ok(())
+        }
+    }
+}
 
-            log::info!("master table written");
-            if let Ok(file) = File::create("C:/Users/H/Downloads/report_segments.txt") {
-                let mut writer = BufWriter::new(file);
+fn open_log(file: &Path) -> Result<BufReader<File>, String> {
+    File::open(file).map(BufReader::new).map_err(|e| format!("Failed to open {}: {e}", file.display()))
+}
 
-                for line in &eso_log_processor.eso_logs_log.events {
-                    if let Err(e) = writeln!(writer, "{line}") {
-                        log::warn!("Error writing events: {e}");
-                        break;
-                    }
-                }
+fn print_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| format!("Error serialising output: {e}"))?;
+    println!("{json}");
+    Ok(())
+}
 
-                if let Err(e) = writer.flush() {
-                    log::warn!("Error flushing writer: {e}");
-                }
-            } else {
-                log::error!("Error creating output file: report_segments.txt");
-            }
-            let mut id_hashmap: HashMap<u32, u32> = HashMap::new();
-            for event in &eso_log_processor.eso_logs_log.events {
-                match event {
-                    ESOLogsEvent::BuffLine(e) => {
-                        let id_index = e.buff_event.buff_index;
-                        if id_index > eso_log_processor.eso_logs_log.buffs.len() {continue;}
-                        let ability_id = eso_log_processor.eso_logs_log.buffs[id_index].id;
-                        let current = id_hashmap.get(&ability_id);
-                        let new_value = if let Some(c) = current {
-                            c + 1
-                        } else {
-                            1
-                        };
-                        id_hashmap.insert(ability_id, new_value);
-                    },
-                    ESOLogsEvent::CastLine(e) => {
-                        let id_index = e.buff_event.buff_index;
-                        if id_index > eso_log_processor.eso_logs_log.buffs.len() {continue;}
-                        let ability_id = eso_log_processor.eso_logs_log.buffs[id_index].id;
-                        let current = id_hashmap.get(&ability_id);
-                        let new_value = if let Some(c) = current {
-                            c + 1
-                        } else {
-                            1
-                        };
-                        id_hashmap.insert(ability_id, new_value);
-                    },
-                    _ => {},
-                }
-            }
-            let vec: Vec<(u32, u32)> = id_hashmap.into_iter().collect();
-            let mut sum = 0;
-            for entry in vec {
-                sum += entry.1;
-                if entry.1 < 10 {continue;}
-                println!("{},{}", entry.0, entry.1);
+/// Runs `analyse` over the log and numbers its results as in `fights`. When `fight` is given, only that fight is read, using the log's index.
+fn analyse_fights<T>(file: &Path, fight: Option<usize>, analyse: impl FnOnce(&mut dyn BufRead) -> io::Result<Vec<T>>) -> Result<Vec<(usize, T)>, String> {
+    match fight {
+        Some(n) => {
+            // the fight is the last one read
+            let items = analyse(&mut read_indexed_fight(file, n)?).map_err(|e| format!("Error reading log file: {e}"))?;
+            Ok(items.into_iter().last().map(|item| (n, item)).into_iter().collect())
+        }
+        None => {
+            let items = analyse(&mut open_log(file)?).map_err(|e| format!("Error reading log file: {e}"))?;
+            Ok(items.into_iter().enumerate().map(|(i, item)| (i + 1, item)).collect())
+        }
+    }
+}
+
+/// Reads fight number `fight` (counting from 1) from its BEGIN_COMBAT up to the next BEGIN_COMBAT or BEGIN_LOG,
+/// preceded by the lines that give the state it began in, using the log's index to skip the rest of the log.
+fn read_indexed_fight(file: &Path, fight: usize) -> Result<impl BufRead, String> {
+    let index = LogIndex::open(file).map_err(|e| format!("Error indexing {}: {e}", file.display()))?;
+    let fights = index.fights();
+    let count = fights.len();
+    let selected = fight.checked_sub(1)
+        .and_then(|i| fights.get(i))
+        .ok_or_else(|| format!("Fight {fight} does not exist, the log has {count} fights"))?;
+    let mut log = File::open(file).map_err(|e| format!("Failed to open {}: {e}", file.display()))?;
+    let mut context = index.read_context(&mut log, selected.start_offset).map_err(|e| format!("Error reading log file: {e}"))?.join("\n");
+    context.push('\n');
+    log.seek(SeekFrom::Start(selected.start_offset)).map_err(|e| format!("Error reading log file: {e}"))?;
+    Ok(io::Cursor::new(context).chain(BufReader::new(log.take(index.aftermath_end(selected) - selected.start_offset))))
+}
+
+fn convert(file: &Path, output: &Path) -> Result<(), String> {
+    let mut eso_log_processor = ESOLogProcessor::new();
+    eso_log_processor.convert_log_file_to_esolog_format(file).map_err(|e| format!("Error converting log file: {e}"))?;
+    eso_log_processor.remove_overabundant_events();
+
+    let master_table_path = output.join("master_table.txt");
+    let file = File::create(&master_table_path).map_err(|e| format!("Error creating {}: {e}", master_table_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let master_table = build_master_table(&mut eso_log_processor);
+    write!(writer, "{master_table}").map_err(|e| format!("Error writing master_table: {e}"))?;
+    log::info!("master table written");
+
+    let segments_path = output.join("report_segments.txt");
+    let file = File::create(&segments_path).map_err(|e| format!("Error creating {}: {e}", segments_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    for line in &eso_log_processor.eso_logs_log.events {
+        writeln!(writer, "{line}").map_err(|e| format!("Error writing events: {e}"))?;
+    }
+    writer.flush().map_err(|e| format!("Error flushing writer: {e}"))?;
+
+    let mut id_hashmap: HashMap<u32, u32> = HashMap::new();
+    for event in &eso_log_processor.eso_logs_log.events {
+        let id_index = match event {
+            ESOLogsEvent::BuffLine(e) => e.buff_event.buff_index,
+            ESOLogsEvent::CastLine(e) => e.buff_event.buff_index,
+            _ => continue,
+        };
+        if let Some(buff) = eso_log_processor.eso_logs_log.buffs.get(id_index) {
+            *id_hashmap.entry(buff.id).or_insert(0) += 1;
+        }
+    }
+    let mut sum = 0;
+    for (id, count) in id_hashmap {
+        sum += count;
+        if count < 10 {continue;}
+        log::debug!("{id},{count}");
+    }
+    log::info!("{sum} buff and cast events");
+    Ok(())
+}
+
+fn validate(file: &Path, format: Format) -> Result<(), String> {
+    let report = parser::validate::validate_log(open_log(file)?).map_err(|e| format!("Error reading log file: {e}"))?;
+    if format == Format::Json {
+        print_json(&report)?;
+    } else {
+        println!("{report}");
+    }
+    if report.is_valid() { Ok(()) } else { Err(format!("{} has structural problems", file.display())) }
+}
+
+fn fights(file: &Path, format: Format) -> Result<(), String> {
+    let fights = parser::fights::find_fights(open_log(file)?).map_err(|e| format!("Error reading log file: {e}"))?;
+    if format == Format::Json {
+        let summaries: Vec<serde_json::Value> = fights.iter().enumerate().map(|(i, fight)| serde_json::json!({
+            "fight": i + 1,
+            "start_time": fight.unix_start_time(),
+            "end_time": fight.unix_end_time(),
+            "duration": fight.duration(),
+            "start_offset": fight.start_offset,
+            "end_offset": fight.end_offset,
+            "zone_id": fight.zone_id,
+            "zone_name": fight.zone_name,
+            "bosses": fight.bosses.iter().map(|b| &b.name).collect::<Vec<_>>(),
+            "players": fight.players.iter().map(|p| &p.display_name).collect::<Vec<_>>(),
+            "outcome": format!("{:?}", fight.outcome),
+            "trial_score": fight.trial_score,
+        })).collect();
+        return print_json(&summaries);
+    }
+    for (i, fight) in fights.iter().enumerate() {
+        let score = fight.trial_score.map(|s| format!(" score {s}")).unwrap_or_default();
+        println!(
+            "{:>3} {:<28} {:<32} {:>4}s {:<7} {} players{score}",
+            i + 1,
+            fight.zone_name.as_deref().unwrap_or("Unknown zone"),
+            fight.boss_name().unwrap_or("Trash"),
+            fight.duration() / 1000,
+            format!("{:?}", fight.outcome),
+            fight.players.len(),
+        );
+    }
+    Ok(())
+}
+
+fn stats(file: &Path, fight: Option<usize>, format: Format) -> Result<(), String> {
+    let meters = analyse_fights(file, fight, |reader| parser::meter::fight_meters(reader))?;
+    if format == Format::Json {
+        return print_json(&meters.iter().map(|(_, m)| m).collect::<Vec<_>>());
+    }
+    for (i, meter) in &meters {
+        if meter.players.is_empty() && fight.is_none() {continue}
+        println!("{} ({}s)", meter.fight.title(*i), meter.fight.duration() / 1000);
+        println!("  {:<26} {:>12} {:>9} {:>12} {:>9} {:>12}", "Player", "Damage", "DPS", "Healing", "HPS", "Taken");
+        for player in &meter.players {
+            println!(
+                "  {:<26} {:>12} {:>9.0} {:>12} {:>9.0} {:>12}",
+                player.name, player.damage_done, player.dps, player.healing_done, player.hps, player.damage_taken,
+            );
+            for ability in player.abilities.iter().take(5).filter(|a| a.damage > 0) {
+                println!("    {:<24} {:>12}", ability.name, ability.damage);
             }
-            println!("{}", sum);
         }
-        "esologzip" => {
-            let noop = |_progress: u8| {};
-            let dummy_cancel = std::sync::atomic::AtomicBool::new(false);
-            split_and_zip_log_by_fight(file_path, r#"C:\Users\H\AppData\Local\Temp\esologtool_temporary"#, noop, &dummy_cancel).expect("esologzip shouldn't error");
+    }
+    Ok(())
+}
+
+fn uptime(file: &Path, fight: Option<usize>, format: Format) -> Result<(), String> {
+    let uptimes = analyse_fights(file, fight, |reader| parser::uptime::fight_uptimes(reader))?;
+    if format == Format::Json {
+        return print_json(&uptimes.iter().map(|(_, u)| u).collect::<Vec<_>>());
+    }
+    for (i, fight_uptimes) in &uptimes {
+        if fight_uptimes.targets.is_empty() && fight.is_none() {continue}
+        println!("{} ({}s)", fight_uptimes.fight.title(*i), fight_uptimes.fight.duration() / 1000);
+        for target in &fight_uptimes.targets {
+            println!("  {}{}", target.name, if target.is_boss {" (boss)"} else {""});
+            for effect in &target.effects {
+                println!("    {:<40} {:>6.1}% {:>5.2} stacks", effect.name, effect.uptime * 100.0, effect.average_stacks);
+            }
         }
-        "aoe" => {
-            let mut eso_log_processor = ESOLogProcessor::new();
+    }
+    Ok(())
+}
 
-            if let Err(e) = eso_log_processor.convert_log_file_to_esolog_format(Path::new(file_path)) {
-                log::error!("Error converting log file: {e}");
-                return;
+fn deaths(file: &Path, fight: Option<usize>, window: u64, format: Format) -> Result<(), String> {
+    let deaths = analyse_fights(file, fight, |reader| parser::death::fight_deaths(reader, window * 1000))?;
+    if format == Format::Json {
+        return print_json(&deaths.iter().map(|(_, d)| d).collect::<Vec<_>>());
+    }
+    for (i, fight_deaths) in &deaths {
+        if fight_deaths.deaths.is_empty() && fight.is_none() {continue}
+        println!("{} ({}s)", fight_deaths.fight.title(*i), fight_deaths.fight.duration() / 1000);
+        let seconds = |time: u64| time.saturating_sub(fight_deaths.fight.start_time) as f64 / 1000.0;
+        for death in &fight_deaths.deaths {
+            let killed_by = death.killing_blow.as_ref()
+                .map(|e| format!(" to {} from {}", e.ability_name, e.source_name))
+                .unwrap_or_default();
+            let resurrected = match (death.resurrected_at, &death.resurrected_by) {
+                (Some(time), Some(by)) => format!(", resurrected by {by} at {:.1}s", seconds(time)),
+                (Some(time), None) => format!(", resurrected at {:.1}s", seconds(time)),
+                (None, _) => String::new(),
+            };
+            println!("  {} ({}) died at {:.1}s{killed_by}{resurrected}", death.name, death.display_name, seconds(death.time));
+            for event in death.events.iter().chain(&death.killing_blow) {
+                println!(
+                    "    {:>6.1}s {:<28} {:<24} {:>9} {:<7} {:>7}/{:<7} shield {}",
+                    (event.time as f64 - death.time as f64) / 1000.0,
+                    event.ability_name,
+                    event.source_name,
+                    event.amount,
+                    if event.is_heal {"healing"} else {"damage"},
+                    event.health,
+                    event.max_health,
+                    event.shield,
+                );
+            }
+            if !death.debuffs.is_empty() {
+                let debuffs: Vec<String> = death.debuffs.iter()
+                    .map(|d| if d.stack_count > 1 { format!("{} x{}", d.name, d.stack_count) } else { d.name.clone() })
+                    .collect();
+                println!("    Debuffs: {}", debuffs.join(", "));
             }
+        }
+    }
+    Ok(())
+}
 
-            log::info!("Finished parsing lines, now looking for aoe abilities...");
-
-            let mut already_found: HashSet<u32> = HashSet::new();
-            let output_file = "C:/Users/H/Downloads/aoe.csv";
-            if let Ok(file) = File::open(output_file) {
-                let reader = BufReader::new(file);
-                for line in reader.lines().flatten() {
-                    if let Some((id_str, _name)) = line.split_once(',') {
-                        if let Ok(id) = id_str.parse::<u32>() {
-                            already_found.insert(id);
-                        }
-                    }
+fn rotation(file: &Path, fight: Option<usize>, player: Option<&str>, format: Format) -> Result<(), String> {
+    let mut rotations = analyse_fights(file, fight, |reader| parser::rotation::fight_rotations(reader))?;
+    if let Some(player) = player {
+        for (_, fight_rotations) in &mut rotations {
+            fight_rotations.players.retain(|p| p.name.eq_ignore_ascii_case(player) || p.display_name.eq_ignore_ascii_case(player));
+        }
+    }
+    if format == Format::Json {
+        return print_json(&rotations.iter().map(|(_, r)| r).collect::<Vec<_>>());
+    }
+    for (i, fight_rotations) in &rotations {
+        if fight_rotations.players.is_empty() && fight.is_none() {continue}
+        println!("{} ({}s)", fight_rotations.fight.title(*i), fight_rotations.fight.duration() / 1000);
+        let seconds = |time: u64| time.saturating_sub(fight_rotations.fight.start_time) as f64 / 1000.0;
+        for rotation in &fight_rotations.players {
+            let bars = match (rotation.front_bar_ms, rotation.back_bar_ms) {
+                (Some(front), Some(back)) if front + back > 0 => {
+                    format!(", {:.0}% front bar", front as f64 * 100.0 / (front + back) as f64)
                 }
+                _ => String::new(),
+            };
+            println!(
+                "  {} ({}) {} casts, {:.1} per minute, {:.2}s average gap, {} cancelled, {} interrupted, {} failed, {} weapon swaps{bars}",
+                rotation.name, rotation.display_name, rotation.casts, rotation.casts_per_minute, rotation.average_gap / 1000.0,
+                rotation.cancelled_casts, rotation.interrupted_casts, rotation.failed_casts, rotation.weapon_swaps,
+            );
+            for cast in &rotation.timeline {
+                let bar = match cast.bar {
+                    Some(parser::rotation::Bar::Front) => "F",
+                    Some(parser::rotation::Bar::Back) => "B",
+                    None => " ",
+                };
+                let gap = cast.gap.map(|gap| format!("+{:.2}s", gap as f64 / 1000.0)).unwrap_or_default();
+                let ended = match cast.end_reason {
+                    Some(parser::event::CastEndReason::Completed) | None => String::new(),
+                    Some(reason) => format!(" {reason:?}"),
+                };
+                println!("    {:>7.2}s {bar} {:<32} {gap:>7}{ended}", seconds(cast.time), cast.name);
             }
+        }
+    }
+    Ok(())
+}
 
-            let non_aoe_ids: HashSet<u32> = vec![
-                41839,
-                41838,
-                243742,
-                190179,
-                98438,
-                187843,
-                61945,
-                17895,
-                220863,
-                183430,
-                107203,
-                93307,
-                147743,
-                79707,
-                79025,
-                17899,
-                46743,
-                17902,
-                18084,21929,148801,21925,215779,148797,148800,21487,21481 // status effects
-            ].into_iter().collect();
-
-            let mut aoe_candidates: HashMap<u32, Vec<(u64, usize, usize)>> = HashMap::new();
-
-            for event in &eso_log_processor.eso_logs_log.events {
-                if let ESOLogsEvent::CastLine(cast_line) = event {
-                    if matches!(cast_line.line_type, ESOLogsLineType::Damage | ESOLogsLineType::DotTick)
-                        && cast_line.cast.source_allegiance == 16 && cast_line.cast.target_allegiance == 64 {
-                            if let Some(cast_info) = &cast_line.cast_information {
-                                if cast_info.hit_value > 0 {
-                                    let cast_id = cast_line.cast.cast_id_origin;
-                                    let ts = cast_line.timestamp;
-                                    let target = cast_line.buff_event.target_unit_index;
-                                    let buff_index = cast_line.buff_event.buff_index;
-                                    aoe_candidates.entry(cast_id).or_default().push((ts, target, buff_index));
-                                }
-                            }
-                        }
-                }
+fn resources(file: &Path, fight: Option<usize>, player: Option<&str>, format: Format) -> Result<(), String> {
+    let mut resources = analyse_fights(file, fight, |reader| parser::resource::fight_resources(reader))?;
+    if let Some(player) = player {
+        for (_, fight_resources) in &mut resources {
+            fight_resources.players.retain(|p| p.name.eq_ignore_ascii_case(player) || p.display_name.eq_ignore_ascii_case(player));
+        }
+    }
+    if format == Format::Json {
+        return print_json(&resources.iter().map(|(_, r)| r).collect::<Vec<_>>());
+    }
+    for (i, fight_resources) in &resources {
+        if fight_resources.players.is_empty() && fight.is_none() {continue}
+        println!("{} ({}s)", fight_resources.fight.title(*i), fight_resources.fight.duration() / 1000);
+        let seconds = |time: u64| time.saturating_sub(fight_resources.fight.start_time) as f64 / 1000.0;
+        println!(
+            "  {:<26} {:>8} {:>8} {:>9} {:>9} {:>8} {:>8} {:>9} {:>6}",
+            "Player", "Magicka", "Stamina", "Mag/s", "Stam/s", "Low", "Max ult", "Ult gen", "Ults",
+        );
+        for player in &fight_resources.players {
+            println!(
+                "  {:<26} {:>7.0}% {:>7.0}% {:>9.0} {:>9.0} {:>7.1}s {:>7.1}s {:>9} {:>6}",
+                player.name,
+                player.average_magicka * 100.0,
+                player.average_stamina * 100.0,
+                player.magicka_restored_per_second,
+                player.stamina_restored_per_second,
+                player.low_resource_ms as f64 / 1000.0,
+                player.max_ultimate_ms as f64 / 1000.0,
+                player.ultimate_generated,
+                player.ultimate_casts,
+            );
+            for period in &player.low_resource_periods {
+                println!("    {:?} below 10% from {:.1}s to {:.1}s", player.primary_resource, seconds(period.start), seconds(period.end));
             }
+        }
+    }
+    Ok(())
+}
 
-            let mut aoe_buff_indexes: HashSet<usize> = HashSet::new();
-
-            for (_cast_id, hits) in aoe_candidates {
-                let mut hits_sorted = hits.clone();
-                hits_sorted.sort_by_key(|(ts, _, _)| *ts);
-
-                'outer: for i in 0..hits_sorted.len() {
-                    let (ts_i, target_i, buff_idx_i) = hits_sorted[i];
-                    let mut unique_targets = HashSet::new();
-                    unique_targets.insert(target_i);
-
-                    for j in i + 1..hits_sorted.len() {
-                        let (ts_j, target_j, buff_idx_j) = hits_sorted[j];
-                        if ts_j.saturating_sub(ts_i) <= 2 {
-                            unique_targets.insert(target_j);
-                            if unique_targets.len() > 1 {
-                                aoe_buff_indexes.insert(buff_idx_i);
-                                aoe_buff_indexes.insert(buff_idx_j);
-                                break 'outer;
-                            }
-                        } else {
-                            break;
-                        }
-                    }
-                }
+fn phases(file: &Path, fight: Option<usize>, player: Option<&str>, definitions: Option<&Path>, format: Format) -> Result<(), String> {
+    let mut phase_definitions = parser::phase::BOSS_PHASES.clone();
+    if let Some(path) = definitions {
+        let data = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        phase_definitions.extend(parser::phase::PhaseDefinitions::parse(&data).map_err(|e| format!("Error in {}: {e}", path.display()))?);
+    }
+    let mut phases = analyse_fights(file, fight, |reader| parser::phase::fight_phases(reader, &phase_definitions))?;
+    if let Some(player) = player {
+        for (_, fight_phases) in &mut phases {
+            for phase in &mut fight_phases.phases {
+                phase.players.retain(|p| p.name.eq_ignore_ascii_case(player) || p.display_name.eq_ignore_ascii_case(player));
+                phase.deaths.retain(|d| phase.players.iter().any(|p| p.unit_id == d.unit_id) || d.name.eq_ignore_ascii_case(player));
+            }
+        }
+    }
+    if format == Format::Json {
+        return print_json(&phases.iter().map(|(_, p)| p).collect::<Vec<_>>());
+    }
+    for (i, fight_phases) in &phases {
+        if fight_phases.phases.is_empty() && fight.is_none() {continue}
+        println!("{} ({}s)", fight_phases.fight.title(*i), fight_phases.fight.duration() / 1000);
+        let seconds = |time: u64| time.saturating_sub(fight_phases.fight.start_time) as f64 / 1000.0;
+        for phase in &fight_phases.phases {
+            let trigger = match phase.trigger {
+                parser::phase::PhaseTrigger::Start => String::new(),
+                parser::phase::PhaseTrigger::Health { percent } => format!(", boss at {percent}%"),
+                parser::phase::PhaseTrigger::Ability { ability_id } => format!(", ability {ability_id}"),
+                parser::phase::PhaseTrigger::Invulnerable { .. } => ", boss not taking damage".to_string(),
+                parser::phase::PhaseTrigger::Resumed => ", boss taking damage again".to_string(),
+            };
+            println!(
+                "  {:<20} {:>7.1}s - {:>7.1}s ({:.1}s{trigger}) {:>12} damage, {:>9.0} DPS, {} deaths",
+                phase.name, seconds(phase.start_time), seconds(phase.end_time),
+                phase.end_time.saturating_sub(phase.start_time) as f64 / 1000.0,
+                phase.damage_done, phase.dps, phase.deaths.len(),
+            );
+            for player in &phase.players {
+                println!("    {:<26} {:>12} {:>9.0} DPS", player.name, player.damage_done, player.dps);
             }
+            for death in &phase.deaths {
+                println!("    {} died at {:.1}s", death.name, seconds(death.time));
+            }
+        }
+    }
+    Ok(())
+}
 
-            let mut file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(output_file)
-                .expect("Unable to open or create output file");
+fn trials(file: &Path, history: Option<&Path>, format: Format) -> Result<(), String> {
+    let runs = parser::trial::trial_runs(open_log(file)?).map_err(|e| format!("Error reading log file: {e}"))?;
+    if let Some(path) = history {
+        let added = cli::trial_history::append_trial_history(path, &runs)?;
+        log::info!("{added} runs added to {}", path.display());
+    }
+    if format == Format::Json {
+        return print_json(&runs);
+    }
+    let minutes = |ms: u64| format!("{}:{:04.1}", ms / 60_000, (ms % 60_000) as f64 / 1000.0);
+    for (i, run) in runs.iter().enumerate() {
+        let result = match (run.completed, run.success) {
+            (true, true) => "Completed".to_string(),
+            (true, false) => "Failed".to_string(),
+            (false, _) => "Not finished".to_string(),
+        };
+        println!(
+            "Run {} - {}{} (trial {}) - {result}",
+            i + 1,
+            run.difficulty.filter(|d| *d != parser::zone::DungeonDifficulty::None).map(|d| format!("{d:?} ")).unwrap_or_default(),
+            run.zone_name.as_deref().unwrap_or("Unknown zone"),
+            run.trial_id,
+        );
+        println!(
+            "  {} fights, {} wipes, {} deaths, {} players",
+            run.fights, run.wipes, run.deaths, run.players.len(),
+        );
+        if let (Some(duration), Some(score)) = (run.duration, run.final_score) {
+            println!("  Time {}, score {score}, vitality bonus {}", minutes(duration), run.vitality_bonus.unwrap_or(0));
+        }
+        for boss in &run.bosses {
+            let kill = match (boss.kill_time, boss.kill_duration) {
+                (Some(time), Some(duration)) => format!("killed at {} in {}", minutes(time), minutes(duration)),
+                _ => "not killed".to_string(),
+            };
+            println!("  {:<32} {kill}, {} pulls, {} wipes", boss.name, boss.pulls, boss.wipes);
+        }
+    }
+    Ok(())
+}
 
-            for idx in aoe_buff_indexes {
-                if let Some(buff) = eso_log_processor.eso_logs_log.buffs.get(idx) {
-                    if non_aoe_ids.contains(&buff.id) || already_found.contains(&buff.id) {
-                        continue;
-                    }
-                    log::info!("{},{}", buff.id, buff.name);
-                    writeln!(file, "{},{}", buff.id, buff.name)
-                        .expect("Unable to write to file");
-                    already_found.insert(buff.id);
-                }
+fn builds(file: &Path, fight: Option<usize>, player: Option<&str>, output: Option<&Path>, format: Format) -> Result<(), String> {
+    let mut builds = analyse_fights(file, fight, |reader| parser::build::fight_builds(reader))?;
+    if let Some(player) = player {
+        for (_, fight_builds) in &mut builds {
+            fight_builds.players.retain(|p| p.name.eq_ignore_ascii_case(player) || p.display_name.eq_ignore_ascii_case(player));
+        }
+    }
+    let text = if format == Format::Json {
+        let json = serde_json::to_string_pretty(&builds.iter().map(|(_, b)| b).collect::<Vec<_>>()).map_err(|e| format!("Error serialising output: {e}"))?;
+        json + "\n"
+    } else {
+        builds.iter()
+            .filter(|(_, b)| !b.players.is_empty() || fight.is_some())
+            .map(|(i, b)| cli::build_sheet::build_markdown(*i, b))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    match output {
+        Some(path) => fs::write(path, text).map_err(|e| format!("Failed to write {}: {e}", path.display())),
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+/// Distance from one unit to another every second of a fight, in map coordinates.
+#[derive(serde::Serialize)]
+struct UnitDistances<'a> {
+    unit_id: u32,
+    name: &'a str,
+    to: &'a str,
+    distances: Vec<parser::position::DistanceSample>,
+}
+
+fn positions(file: &Path, fight: Option<usize>, units: &[String], distance_to: Option<&str>, paths: Option<&Path>, heatmap: Option<&Path>, format: Format) -> Result<(), String> {
+    if fight.is_none() && (paths.is_some() || heatmap.is_some()) {
+        return Err("Choose a fight with --fight to write an SVG".to_string());
+    }
+    let positions = analyse_fights(file, fight, |reader| parser::position::fight_positions(reader))?;
+
+    let mut json = Vec::new();
+    for (i, fight_positions) in &positions {
+        let tracks: Vec<&parser::position::UnitTrack> = if units.is_empty() {
+            fight_positions.tracks.iter().filter(|t| t.is_player || t.is_boss).collect()
+        } else {
+            units.iter().flat_map(|name| fight_positions.tracks_named(name)).collect()
+        };
+        let target = distance_to.map(|name| {
+            fight_positions.tracks_named(name).next().ok_or_else(|| format!("Fight {i} has no unit named {name}"))
+        });
+        let target = match target {
+            Some(Err(e)) if fight.is_some() => return Err(e),
+            Some(Err(_)) => continue,
+            Some(Ok(target)) => Some(target),
+            None => None,
+        };
+        let distances: Vec<UnitDistances> = target.iter()
+            .flat_map(|target| tracks.iter().filter(|t| t.unit_id != target.unit_id).map(move |track| UnitDistances {
+                unit_id: track.unit_id,
+                name: &track.name,
+                to: &target.name,
+                distances: fight_positions.distances(track, target, 1000),
+            }))
+            .collect();
+
+        let title = fight_positions.fight.title(*i);
+        if let Some(path) = paths {
+            let svg = cli::map_plot::paths_svg(fight_positions, &tracks, &title)?;
+            fs::write(path, svg).map_err(|e| format!("Error writing {}: {e}", path.display()))?;
+            log::info!("Paths written to {}", path.display());
+        }
+        if let Some(path) = heatmap {
+            let svg = cli::map_plot::heatmap_svg(fight_positions, &tracks, &title)?;
+            fs::write(path, svg).map_err(|e| format!("Error writing {}: {e}", path.display()))?;
+            log::info!("Heatmap written to {}", path.display());
+        }
+
+        if format == Format::Json {
+            json.push(serde_json::json!({
+                "start_time": fight_positions.fight.start_time,
+                "end_time": fight_positions.fight.end_time,
+                "zone_name": fight_positions.fight.zone_name,
+                "boss_name": fight_positions.fight.boss_name,
+                "map": fight_positions.map,
+                "tracks": tracks,
+                "deaths": fight_positions.deaths.iter().filter(|d| tracks.iter().any(|t| t.unit_id == d.unit_id)).collect::<Vec<_>>(),
+                "distances": distances,
+            }));
+            continue;
+        }
+        if tracks.is_empty() && fight.is_none() {continue}
+        println!("{title} ({}s)", fight_positions.fight.duration() / 1000);
+        if let Some(map) = &fight_positions.map {
+            println!("  Map: {} ({})", map.name, map.texture_path);
+        }
+        let seconds = |time: u64| time.saturating_sub(fight_positions.fight.start_time) as f64 / 1000.0;
+        for track in &tracks {
+            let (Some(first), Some(last)) = (track.samples.first(), track.samples.last()) else {continue};
+            println!(
+                "  {:<26} {:>6} positions, moved {:.4}, from ({:.4}, {:.4}) to ({:.4}, {:.4})",
+                track.name, track.samples.len(), track.distance_travelled(), first.x, first.y, last.x, last.y,
+            );
+            if let Some(target) = target {
+                let Some(samples) = distances.iter().find(|d| d.unit_id == track.unit_id).map(|d| &d.distances) else {continue};
+                if samples.is_empty() {continue}
+                let closest = samples.iter().min_by(|a, b| a.distance.total_cmp(&b.distance)).map_or(0.0, |s| s.distance);
+                let furthest = samples.iter().max_by(|a, b| a.distance.total_cmp(&b.distance)).map_or(0.0, |s| s.distance);
+                let average = samples.iter().map(|s| s.distance).sum::<f64>() / samples.len() as f64;
+                println!("    Distance to {}: {closest:.4} closest, {average:.4} average, {furthest:.4} furthest", target.name);
             }
         }
-        "aoesql" => {
-            let file_path = "C:/Users/H/Downloads/aoe.csv";
-            let content = fs::read_to_string(file_path).expect("should always be able to read aoe.csv");
-            let ids: Vec<&str> = content
-                .lines()
-                .filter_map(|line| line.split(',').next())
-                .collect();
-            let id_list = ids.join(",");
-            let sql = format!("ability.id IN ({id_list})");
-            log::info!("{sql}");
+        for death in fight_positions.deaths.iter().filter(|d| tracks.iter().any(|t| t.unit_id == d.unit_id)) {
+            println!("  {} died at {:.1}s at ({:.4}, {:.4})", death.name, seconds(death.time), death.x, death.y);
         }
-        "parentzones" => {
-            parser::zone::print_parent_zones();
+    }
+    if format == Format::Json {
+        return print_json(&json);
+    }
+    Ok(())
+}
+
+fn export(file: &Path, output: Option<&Path>) -> Result<(), String> {
+    let reader = open_log(file)?;
+    let written = match output {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("Error creating {}: {e}", path.display()))?;
+            export_json_lines(reader, &mut BufWriter::new(file))?
         }
-        "dungeons" => {
-            parser::zone::print_dungeon_zones();
+        None => export_json_lines(reader, &mut BufWriter::new(std::io::stdout().lock()))?,
+    };
+    log::info!("{written} lines exported");
+    Ok(())
+}
+
+#[cfg(feature = "database")]
+fn ingest(files: &[PathBuf], database: &Path) -> Result<(), String> {
+    let mut connection = open_database(database)?;
+    for file in files {
+        match ingest_log_file(&mut connection, file)? {
+            Some(summary) => println!(
+                "{}: {} logs, {} fights, {} units, {} combat events, {} effect changes",
+                file.display(), summary.sessions, summary.fights, summary.units, summary.combat_events, summary.effect_changes,
+            ),
+            None => println!("{}: unchanged, skipped", file.display()),
         }
-        _ => {}
     }
+    Ok(())
 }
 
-fn parse_config(args: &[String]) -> (&str, &str) {
-    let mut query = "";
-    let file_path = &args[1];
-    if args.len() > 2 {
-        query = &args[2];
+fn aoe(file: &Path, output_file: &Path) -> Result<(), String> {
+    let mut eso_log_processor = ESOLogProcessor::new();
+    eso_log_processor.convert_log_file_to_esolog_format(file).map_err(|e| format!("Error converting log file: {e}"))?;
+
+    log::info!("Finished parsing lines, now looking for aoe abilities...");
+
+    let mut already_found: HashSet<u32> = HashSet::new();
+    if let Ok(file) = File::open(output_file) {
+        let reader = BufReader::new(file);
+        for line in reader.lines().map_while(Result::ok) {
+            if let Some((id_str, _name)) = line.split_once(',')
+                && let Ok(id) = id_str.parse::<u32>() {
+                already_found.insert(id);
+            }
+        }
+    }
+
+    let non_aoe_ids: HashSet<u32> = [
+        41839,
+        41838,
+        243742,
+        190179,
+        98438,
+        187843,
+        61945,
+        17895,
+        220863,
+        183430,
+        107203,
+        93307,
+        147743,
+        79707,
+        79025,
+        17899,
+        46743,
+        17902,
+        18084,21929,148801,21925,215779,148797,148800,21487,21481 // status effects
+    ].into_iter().collect();
+
+    let mut aoe_candidates: HashMap<u32, Vec<(u64, usize, usize)>> = HashMap::new();
+
+    for event in &eso_log_processor.eso_logs_log.events {
+        if let ESOLogsEvent::CastLine(cast_line) = event
+            && matches!(cast_line.line_type, ESOLogsLineType::Damage | ESOLogsLineType::DotTick)
+            && cast_line.cast.source_allegiance == 16 && cast_line.cast.target_allegiance == 64
+            && let Some(cast_info) = &cast_line.cast_information
+            && cast_info.hit_value > 0 {
+            let cast_id = cast_line.cast.cast_id_origin;
+            let ts = cast_line.timestamp;
+            let target = cast_line.buff_event.target_unit_index;
+            let buff_index = cast_line.buff_event.buff_index;
+            aoe_candidates.entry(cast_id).or_default().push((ts, target, buff_index));
+        }
+    }
+
+    let mut aoe_buff_indexes: HashSet<usize> = HashSet::new();
+
+    for (_cast_id, mut hits_sorted) in aoe_candidates {
+        hits_sorted.sort_by_key(|(ts, _, _)| *ts);
+
+        'outer: for i in 0..hits_sorted.len() {
+            let (ts_i, target_i, buff_idx_i) = hits_sorted[i];
+            let mut unique_targets = HashSet::new();
+            unique_targets.insert(target_i);
+
+            for &(ts_j, target_j, buff_idx_j) in &hits_sorted[i + 1..] {
+                if ts_j.saturating_sub(ts_i) <= 2 {
+                    unique_targets.insert(target_j);
+                    if unique_targets.len() > 1 {
+                        aoe_buff_indexes.insert(buff_idx_i);
+                        aoe_buff_indexes.insert(buff_idx_j);
+                        break 'outer;
+                    }
+                } else {
+                    break;
+                }
+            }
+        }
     }
 
-    (file_path, query)
-}
\ No newline at end of file
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output_file)
+        .map_err(|e| format!("Unable to open or create {}: {e}", output_file.display()))?;
+
+    for idx in aoe_buff_indexes {
+        if let Some(buff) = eso_log_processor.eso_logs_log.buffs.get(idx) {
+            if non_aoe_ids.contains(&buff.id) || already_found.contains(&buff.id) {
+                continue;
+            }
+            println!("{},{}", buff.id, buff.name);
+            writeln!(file, "{},{}", buff.id, buff.name)
+                .map_err(|e| format!("Unable to write to {}: {e}", output_file.display()))?;
+            already_found.insert(buff.id);
+        }
+    }
+    Ok(())
+}
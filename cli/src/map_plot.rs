@@ -0,0 +1,139 @@
+use std::fmt::Write;
+use parser::position::{DeathPosition, FightPositions, UnitTrack};
+
+/// Width and height of the plots in pixels
+const SIZE: f64 = 800.0;
+/// Space left around the area the units moved in, as a fraction of it
+const MARGIN: f64 = 0.05;
+/// Number of cells along each side of a heatmap
+const HEATMAP_CELLS: usize = 64;
+const COLOURS: [&str; 12] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#9467bd", "#8c564b", "#e377c2",
+    "#7f7f7f", "#bcbd22", "#17becf", "#393b79", "#637939", "#843c39",
+];
+
+/// Maps map coordinates onto the plot, keeping distances in proportion.
+struct Bounds {
+    min_x: f64,
+    min_y: f64,
+    scale: f64,
+}
+
+impl Bounds {
+    /// Fits every sample of `tracks` and every death among them, or `None` if there are no samples.
+    fn fit(tracks: &[&UnitTrack], deaths: &[&DeathPosition]) -> Option<Self> {
+        let points = tracks.iter().flat_map(|t| t.samples.iter().map(|s| (s.x as f64, s.y as f64)))
+            .chain(deaths.iter().map(|d| (d.x as f64, d.y as f64)));
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for (x, y) in points {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        if min_x > max_x {return None}
+        // A unit that never moved still gets a small area around it
+        let extent = (max_x - min_x).max(max_y - min_y).max(1e-4);
+        let margin = extent * MARGIN;
+        let centre = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+        let half = extent / 2.0 + margin;
+        Some(Self { min_x: centre.0 - half, min_y: centre.1 - half, scale: SIZE / (2.0 * half) })
+    }
+
+    fn point(&self, x: f32, y: f32) -> (f64, f64) {
+        ((x as f64 - self.min_x) * self.scale, (y as f64 - self.min_y) * self.scale)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn seconds(fight: &FightPositions, time: u64) -> f64 {
+    time.saturating_sub(fight.fight.start_time) as f64 / 1000.0
+}
+
+/// Opens the document, naming the fight and the map it was on, which the game gives as a texture path rather than an image that can be linked.
+fn header(svg: &mut String, fight: &FightPositions, title: &str) {
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{SIZE}" height="{SIZE}" viewBox="0 0 {SIZE} {SIZE}">"#);
+    let _ = writeln!(svg, "<title>{}</title>", escape(title));
+    if let Some(map) = &fight.map {
+        let _ = writeln!(svg, "<desc>{} ({})</desc>", escape(&map.name), escape(&map.texture_path));
+    }
+    let _ = writeln!(svg, r##"<rect width="100%" height="100%" fill="#ffffff"/>"##);
+}
+
+fn death_markers(svg: &mut String, fight: &FightPositions, bounds: &Bounds, deaths: &[&DeathPosition]) {
+    for death in deaths {
+        let (x, y) = bounds.point(death.x, death.y);
+        let _ = writeln!(
+            svg,
+            r##"<g stroke="#d62728" stroke-width="3"><title>{} died at {:.1}s</title><line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/><line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/></g>"##,
+            escape(&death.name), seconds(fight, death.time),
+            x - 6.0, y - 6.0, x + 6.0, y + 6.0, x - 6.0, y + 6.0, x + 6.0, y - 6.0,
+        );
+    }
+}
+
+fn deaths_of<'a>(fight: &'a FightPositions, tracks: &[&UnitTrack]) -> Vec<&'a DeathPosition> {
+    fight.deaths.iter().filter(|d| tracks.iter().any(|t| t.unit_id == d.unit_id)).collect()
+}
+
+/// Draws the path each of `tracks` took through the fight, from a hollow circle where it started to a filled one where it ended, with a cross where each of them died.
+pub fn paths_svg(fight: &FightPositions, tracks: &[&UnitTrack], title: &str) -> Result<String, String> {
+    let deaths = deaths_of(fight, tracks);
+    let bounds = Bounds::fit(tracks, &deaths).ok_or("None of the units have a position to plot")?;
+    let mut svg = String::new();
+    header(&mut svg, fight, title);
+
+    for (i, track) in tracks.iter().enumerate() {
+        let colour = COLOURS[i % COLOURS.len()];
+        let points: Vec<(f64, f64)> = track.samples.iter().map(|s| bounds.point(s.x, s.y)).collect();
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {continue};
+        let _ = writeln!(svg, "<g><title>{}</title>", escape(&track.name));
+        let line: Vec<String> = points.iter().map(|(x, y)| format!("{x:.1},{y:.1}")).collect();
+        let _ = writeln!(svg, r#"<polyline fill="none" stroke="{colour}" stroke-width="1.5" stroke-opacity="0.8" points="{}"/>"#, line.join(" "));
+        let _ = writeln!(svg, r##"<circle cx="{:.1}" cy="{:.1}" r="4" fill="#ffffff" stroke="{colour}" stroke-width="2"/>"##, first.0, first.1);
+        let _ = writeln!(svg, r#"<circle cx="{:.1}" cy="{:.1}" r="4" fill="{colour}"/>"#, last.0, last.1);
+        let _ = writeln!(svg, "</g>");
+        let _ = writeln!(svg, r#"<text x="10" y="{}" font-family="sans-serif" font-size="13" fill="{colour}">{}</text>"#, 20 + i * 16, escape(&track.name));
+    }
+
+    death_markers(&mut svg, fight, &bounds, &deaths);
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// Shades the ground `tracks` stood on by the total time spent there, from blue for the least to red for the most, with a cross where each of them died.
+pub fn heatmap_svg(fight: &FightPositions, tracks: &[&UnitTrack], title: &str) -> Result<String, String> {
+    let deaths = deaths_of(fight, tracks);
+    let bounds = Bounds::fit(tracks, &deaths).ok_or("None of the units have a position to plot")?;
+    let cell_size = SIZE / HEATMAP_CELLS as f64;
+    let mut cells = vec![0u64; HEATMAP_CELLS * HEATMAP_CELLS];
+    for track in tracks {
+        for (i, sample) in track.samples.iter().enumerate() {
+            let until = track.samples.get(i + 1).map_or(fight.fight.end_time, |next| next.time).max(sample.time);
+            let (x, y) = bounds.point(sample.x, sample.y);
+            let column = ((x / cell_size) as usize).min(HEATMAP_CELLS - 1);
+            let row = ((y / cell_size) as usize).min(HEATMAP_CELLS - 1);
+            cells[row * HEATMAP_CELLS + column] += until - sample.time;
+        }
+    }
+    let most = cells.iter().copied().max().unwrap_or(0).max(1) as f64;
+
+    let mut svg = String::new();
+    header(&mut svg, fight, title);
+    for (i, &ms) in cells.iter().enumerate() {
+        if ms == 0 {continue}
+        let heat = ms as f64 / most;
+        let (row, column) = (i / HEATMAP_CELLS, i % HEATMAP_CELLS);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{cell_size:.1}" height="{cell_size:.1}" fill="hsl({:.0},100%,50%)" fill-opacity="{:.2}"><title>{:.1}s</title></rect>"#,
+            column as f64 * cell_size, row as f64 * cell_size, 240.0 * (1.0 - heat), 0.2 + 0.8 * heat, ms as f64 / 1000.0,
+        );
+    }
+    death_markers(&mut svg, fight, &bounds, &deaths);
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
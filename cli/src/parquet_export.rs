@@ -0,0 +1,748 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use arrow_array::builder::{ArrayBuilder, BooleanBuilder, Float32Builder, StringBuilder, StringDictionaryBuilder, TimestampMillisecondBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder};
+use arrow_array::types::Int32Type;
+use arrow_array::{ArrayRef, RecordBatch};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use parser::effect::EffectEvent;
+use parser::event::{Cast, Event};
+use parser::fights::{Fight, FightTracker};
+use parser::line::{for_each_line, AbilityInfo, EndCast, LogLine, UnitAdded};
+use parser::session::Session;
+use parser::unit::UnitState;
+
+/// The Parquet files written by [`export_parquet`], without the `.parquet` extension
+pub const TABLES: [&str; 6] = ["combat_events", "effect_events", "casts", "units", "abilities", "fights"];
+
+/// Rows held in memory per table before they are written out as a row group
+const ROWS_PER_GROUP: usize = 64 * 1024;
+/// Milliseconds a cast waits for its END_CAST before it is written without one
+const CAST_END_WAIT: u64 = 60_000;
+
+type DictionaryBuilder = StringDictionaryBuilder<Int32Type>;
+
+/// Writes the combat events, effect changes, casts, units, abilities and fights of a log to one Parquet file each in `output_dir`.
+///
+/// Every event row carries `log`, the BEGIN_LOG it belongs to counting from 0, and `fight`, numbered from 1 in the order `find_fights` returns them,
+/// so rows from several logs can be told apart once loaded together. Unit states are flattened into `source_` and `target_` columns,
+/// enums are written as dictionary encoded strings and `timestamp` is the Unix time of the line.
+pub fn export_parquet<R: BufRead>(reader: R, output_dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(output_dir).map_err(|e| format!("Error creating {}: {e}", output_dir.display()))?;
+    let mut exporter = ParquetExporter::new(output_dir);
+    let mut error = None;
+
+    for_each_line(reader, |line, offset, length| {
+        if error.is_some() {return}
+        if let Err(e) = exporter.handle_line(line, offset, length) {
+            error = Some(e);
+        }
+    }).map_err(|e| format!("Error reading log file: {e}"))?;
+
+    if let Some(e) = error {
+        return Err(e);
+    }
+    exporter.finish()
+}
+
+/// Folder [`export_parquet`] writes to by default, `<name>-parquet` next to the log.
+pub fn parquet_output_path(file_path: &Path) -> Result<PathBuf, String> {
+    let stem = file_path.file_stem().ok_or("Failed to get file stem")?;
+    let mut folder_name = stem.to_os_string();
+    folder_name.push("-parquet");
+    Ok(file_path.with_file_name(folder_name))
+}
+
+struct ParquetExporter {
+    tracker: FightTracker,
+    logs: u32,
+    fight_logs: Vec<u32>,
+    abilities_seen: HashSet<u32>,
+    combat_events: TableFile<CombatEventTable>,
+    effect_events: TableFile<EffectEventTable>,
+    casts: TableFile<CastTable>,
+    units: TableFile<UnitTable>,
+    abilities: TableFile<AbilityTable>,
+    fights: TableFile<FightTable>,
+}
+
+impl ParquetExporter {
+    fn new(output_dir: &Path) -> Self {
+        Self {
+            tracker: FightTracker::new(),
+            logs: 0,
+            fight_logs: Vec::new(),
+            abilities_seen: HashSet::new(),
+            combat_events: TableFile::new(output_dir, "combat_events"),
+            effect_events: TableFile::new(output_dir, "effect_events"),
+            casts: TableFile::new(output_dir, "casts"),
+            units: TableFile::new(output_dir, "units"),
+            abilities: TableFile::new(output_dir, "abilities"),
+            fights: TableFile::new(output_dir, "fights"),
+        }
+    }
+
+    fn handle_line(&mut self, line: &LogLine, offset: u64, length: u64) -> Result<(), String> {
+        let log_before = self.log();
+        let fights_before = self.tracker.fights.len();
+        if matches!(line, LogLine::BeginLog(_)) {
+            // Cast track ids start again in a new log, so nothing older can be ended
+            self.casts.table.release(u64::MAX);
+            self.logs += 1;
+        }
+        self.tracker.handle_line(line, offset, length);
+        self.fight_logs.resize(self.fight_logs.len() + self.tracker.fights.len() - fights_before, log_before);
+
+        let position = Position {
+            log: self.log(),
+            fight: self.tracker.current_fight().map(|_| self.tracker.fights.len() as u32 + 1),
+            log_start_time: self.tracker.session.log_start_time,
+        };
+        let session = &self.tracker.session;
+
+        match line {
+            LogLine::CombatEvent(event) => {
+                self.combat_events.table.append(&position, event, session);
+                self.combat_events.flush_if_full()?;
+            }
+            LogLine::EffectChanged(effect) => {
+                self.effect_events.table.append(&position, effect, session);
+                self.effect_events.flush_if_full()?;
+            }
+            LogLine::BeginCast(cast) => self.casts.table.begin(&position, cast, session),
+            LogLine::EndCast(end_cast) => self.casts.table.end(end_cast),
+            LogLine::UnitAdded(unit) => {
+                self.units.table.append(&position, unit);
+                self.units.flush_if_full()?;
+            }
+            LogLine::AbilityInfo(ability) if self.abilities_seen.insert(ability.ability_id) => {
+                self.abilities.table.append(ability);
+                self.abilities.flush_if_full()?;
+            }
+            _ => {}
+        }
+
+        self.casts.table.release(session.time.saturating_sub(CAST_END_WAIT));
+        self.casts.flush_if_full()
+    }
+
+    fn log(&self) -> u32 {
+        self.logs.saturating_sub(1)
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        self.casts.table.release(u64::MAX);
+        let log = self.log();
+        let fights = self.tracker.finish();
+        self.fight_logs.resize(fights.len(), log);
+        for (i, (fight, log)) in fights.iter().zip(&self.fight_logs).enumerate() {
+            self.fights.table.append(i as u32 + 1, *log, fight);
+        }
+
+        self.combat_events.close()?;
+        self.effect_events.close()?;
+        self.casts.close()?;
+        self.units.close()?;
+        self.abilities.close()?;
+        self.fights.close()
+    }
+}
+
+/// A table being filled with rows, handed to its [`TableFile`] one row group at a time.
+trait Table: Default {
+    fn len(&self) -> usize;
+    /// Takes every row appended so far as named columns.
+    fn columns(&mut self) -> Vec<(String, ArrayRef)>;
+}
+
+struct TableFile<T: Table> {
+    path: PathBuf,
+    table: T,
+    writer: Option<ArrowWriter<File>>,
+}
+
+impl<T: Table> TableFile<T> {
+    fn new(output_dir: &Path, name: &str) -> Self {
+        Self {
+            path: output_dir.join(format!("{name}.parquet")),
+            table: T::default(),
+            writer: None,
+        }
+    }
+
+    fn flush_if_full(&mut self) -> Result<(), String> {
+        if self.table.len() >= ROWS_PER_GROUP {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        let batch = RecordBatch::try_from_iter(self.table.columns()).map_err(|e| format!("Error building rows for {}: {e}", self.path.display()))?;
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let file = File::create(&self.path).map_err(|e| format!("Error creating {}: {e}", self.path.display()))?;
+                let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+                let writer = ArrowWriter::try_new(file, batch.schema(), Some(properties)).map_err(|e| format!("Error creating {}: {e}", self.path.display()))?;
+                self.writer.insert(writer)
+            }
+        };
+        writer.write(&batch).map_err(|e| format!("Error writing {}: {e}", self.path.display()))
+    }
+
+    /// Writes the remaining rows, or an empty file with the table's columns if there never were any.
+    fn close(mut self) -> Result<(), String> {
+        if self.table.len() > 0 || self.writer.is_none() {
+            self.flush()?;
+        }
+        if let Some(writer) = self.writer {
+            writer.close().map_err(|e| format!("Error writing {}: {e}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+fn column(name: &str, builder: &mut dyn ArrayBuilder) -> (String, ArrayRef) {
+    (name.to_owned(), builder.finish())
+}
+
+fn ability_name(session: &Session, ability_id: u32) -> Option<Arc<str>> {
+    session.ability(ability_id).map(|a| a.name.clone())
+}
+
+/// Where an event row sits within the file.
+#[derive(Clone, Copy)]
+struct Position {
+    log: u32,
+    fight: Option<u32>,
+    log_start_time: Option<u64>,
+}
+
+/// The columns every event table starts with.
+struct EventColumns {
+    log: UInt32Builder,
+    fight: UInt32Builder,
+    timestamp: TimestampMillisecondBuilder,
+    time: UInt64Builder,
+}
+
+impl Default for EventColumns {
+    fn default() -> Self {
+        Self {
+            log: UInt32Builder::new(),
+            fight: UInt32Builder::new(),
+            timestamp: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+            time: UInt64Builder::new(),
+        }
+    }
+}
+
+impl EventColumns {
+    fn append(&mut self, position: &Position, time: u64) {
+        self.log.append_value(position.log);
+        self.fight.append_option(position.fight);
+        self.timestamp.append_option(position.log_start_time.map(|start| (start + time) as i64));
+        self.time.append_value(time);
+    }
+
+    fn columns(&mut self) -> Vec<(String, ArrayRef)> {
+        vec![
+            column("log", &mut self.log),
+            column("fight", &mut self.fight),
+            column("timestamp", &mut self.timestamp),
+            column("time", &mut self.time),
+        ]
+    }
+}
+
+const UNIT_STATE_COUNTERS: [&str; 12] = [
+    "unit_id", "health", "max_health", "magicka", "max_magicka", "stamina", "max_stamina",
+    "ultimate", "max_ultimate", "werewolf", "werewolf_max", "shield",
+];
+
+/// A unit state flattened into one column per field, each named with a prefix such as `source_`.
+#[derive(Default)]
+struct UnitStateColumns {
+    counters: [UInt32Builder; 12],
+    map_x: Float32Builder,
+    map_y: Float32Builder,
+    heading: Float32Builder,
+}
+
+impl UnitStateColumns {
+    fn append(&mut self, state: &UnitState) {
+        let counters = [
+            state.unit_id, state.health, state.max_health, state.magicka, state.max_magicka, state.stamina, state.max_stamina,
+            state.ultimate, state.max_ultimate, state.werewolf, state.werewolf_max, state.shield,
+        ];
+        for (builder, value) in self.counters.iter_mut().zip(counters) {
+            builder.append_value(value);
+        }
+        self.map_x.append_value(state.map_x);
+        self.map_y.append_value(state.map_y);
+        self.heading.append_value(state.heading);
+    }
+
+    fn columns(&mut self, prefix: &str, columns: &mut Vec<(String, ArrayRef)>) {
+        for (name, builder) in UNIT_STATE_COUNTERS.iter().zip(&mut self.counters) {
+            columns.push(column(&format!("{prefix}{name}"), builder));
+        }
+        columns.push(column(&format!("{prefix}map_x"), &mut self.map_x));
+        columns.push(column(&format!("{prefix}map_y"), &mut self.map_y));
+        columns.push(column(&format!("{prefix}heading"), &mut self.heading));
+    }
+}
+
+#[derive(Default)]
+struct CombatEventTable {
+    event: EventColumns,
+    result: DictionaryBuilder,
+    damage_type: DictionaryBuilder,
+    power_type: UInt32Builder,
+    hit_value: UInt32Builder,
+    overflow: UInt32Builder,
+    cast_track_id: UInt32Builder,
+    ability_id: UInt32Builder,
+    ability_name: DictionaryBuilder,
+    source: UnitStateColumns,
+    target: UnitStateColumns,
+}
+
+impl CombatEventTable {
+    fn append(&mut self, position: &Position, event: &Event, session: &Session) {
+        self.event.append(position, event.time);
+        self.result.append_value(format!("{:?}", event.result));
+        self.damage_type.append_value(format!("{:?}", event.damage_type));
+        self.power_type.append_value(event.power_type);
+        self.hit_value.append_value(event.hit_value);
+        self.overflow.append_value(event.overflow);
+        self.cast_track_id.append_value(event.cast_track_id);
+        self.ability_id.append_value(event.ability_id);
+        self.ability_name.append_option(ability_name(session, event.ability_id));
+        self.source.append(&event.source_unit_state);
+        self.target.append(&event.target_unit_state);
+    }
+}
+
+impl Table for CombatEventTable {
+    fn len(&self) -> usize {
+        self.event.time.len()
+    }
+
+    fn columns(&mut self) -> Vec<(String, ArrayRef)> {
+        let mut columns = self.event.columns();
+        columns.extend([
+            column("result", &mut self.result),
+            column("damage_type", &mut self.damage_type),
+            column("power_type", &mut self.power_type),
+            column("hit_value", &mut self.hit_value),
+            column("overflow", &mut self.overflow),
+            column("cast_track_id", &mut self.cast_track_id),
+            column("ability_id", &mut self.ability_id),
+            column("ability_name", &mut self.ability_name),
+        ]);
+        self.source.columns("source_", &mut columns);
+        self.target.columns("target_", &mut columns);
+        columns
+    }
+}
+
+#[derive(Default)]
+struct EffectEventTable {
+    event: EventColumns,
+    change_type: DictionaryBuilder,
+    stack_count: UInt16Builder,
+    cast_track_id: UInt32Builder,
+    ability_id: UInt32Builder,
+    ability_name: DictionaryBuilder,
+    player_initiated_remove_cast_track_id: BooleanBuilder,
+    source: UnitStateColumns,
+    target: UnitStateColumns,
+}
+
+impl EffectEventTable {
+    fn append(&mut self, position: &Position, effect: &EffectEvent, session: &Session) {
+        self.event.append(position, effect.time);
+        self.change_type.append_value(format!("{:?}", effect.change_type));
+        self.stack_count.append_value(effect.stack_count);
+        self.cast_track_id.append_value(effect.cast_track_id);
+        self.ability_id.append_value(effect.ability_id);
+        self.ability_name.append_option(ability_name(session, effect.ability_id));
+        self.player_initiated_remove_cast_track_id.append_value(effect.player_initiated_remove_cast_track_id);
+        self.source.append(&effect.source_unit_state);
+        self.target.append(&effect.target_unit_state);
+    }
+}
+
+impl Table for EffectEventTable {
+    fn len(&self) -> usize {
+        self.event.time.len()
+    }
+
+    fn columns(&mut self) -> Vec<(String, ArrayRef)> {
+        let mut columns = self.event.columns();
+        columns.extend([
+            column("change_type", &mut self.change_type),
+            column("stack_count", &mut self.stack_count),
+            column("cast_track_id", &mut self.cast_track_id),
+            column("ability_id", &mut self.ability_id),
+            column("ability_name", &mut self.ability_name),
+            column("player_initiated_remove_cast_track_id", &mut self.player_initiated_remove_cast_track_id),
+        ]);
+        self.source.columns("source_", &mut columns);
+        self.target.columns("target_", &mut columns);
+        columns
+    }
+}
+
+/// A BEGIN_CAST waiting for its END_CAST.
+struct PendingCast {
+    position: Position,
+    time: u64,
+    duration: u32,
+    channeled: bool,
+    cast_track_id: u32,
+    ability_id: u32,
+    ability_name: Option<Arc<str>>,
+    source: UnitState,
+    target: UnitState,
+    end: Option<CastEnd>,
+}
+
+struct CastEnd {
+    time: u64,
+    reason: Option<String>,
+    interrupting_ability_id: Option<u32>,
+    interrupting_unit_id: Option<u32>,
+}
+
+/// One row per BEGIN_CAST, joined with its END_CAST when there is one.
+/// Casts are kept in order until they end or [`CAST_END_WAIT`] passes, so rows stay sorted by time.
+#[derive(Default)]
+struct CastTable {
+    pending: VecDeque<PendingCast>,
+    /// Sequence number of the first pending cast, counting every cast begun
+    first_sequence: u64,
+    sequence_by_track_id: HashMap<u32, u64>,
+    event: EventColumns,
+    duration: UInt32Builder,
+    channeled: BooleanBuilder,
+    cast_track_id: UInt32Builder,
+    ability_id: UInt32Builder,
+    ability_name: DictionaryBuilder,
+    end_time: UInt64Builder,
+    end_reason: DictionaryBuilder,
+    interrupting_ability_id: UInt32Builder,
+    interrupting_unit_id: UInt32Builder,
+    source: UnitStateColumns,
+    target: UnitStateColumns,
+}
+
+impl CastTable {
+    fn begin(&mut self, position: &Position, cast: &Cast, session: &Session) {
+        let sequence = self.first_sequence + self.pending.len() as u64;
+        self.sequence_by_track_id.insert(cast.cast_track_id, sequence);
+        self.pending.push_back(PendingCast {
+            position: *position,
+            time: cast.time,
+            duration: cast.duration,
+            channeled: cast.channeled,
+            cast_track_id: cast.cast_track_id,
+            ability_id: cast.ability_id,
+            ability_name: ability_name(session, cast.ability_id),
+            source: cast.source_unit_state,
+            target: cast.target_unit_state,
+            end: None,
+        });
+    }
+
+    fn end(&mut self, end_cast: &EndCast) {
+        let Some(sequence) = self.sequence_by_track_id.remove(&end_cast.cast_track_id) else {return};
+        if let Some(cast) = self.pending.get_mut((sequence - self.first_sequence) as usize) {
+            cast.end = Some(CastEnd {
+                time: end_cast.time,
+                reason: end_cast.end_reason.as_ref().map(|r| format!("{r:?}")),
+                interrupting_ability_id: end_cast.interrupting_ability_id,
+                interrupting_unit_id: end_cast.interrupting_unit_id,
+            });
+        }
+    }
+
+    /// Moves casts into the table from the front of the queue while they have ended or began before `give_up_before`.
+    fn release(&mut self, give_up_before: u64) {
+        while let Some(cast) = self.pending.front()
+            && (cast.end.is_some() || cast.time < give_up_before) {
+            let cast = self.pending.pop_front().expect("front was just checked");
+            if self.sequence_by_track_id.get(&cast.cast_track_id) == Some(&self.first_sequence) {
+                self.sequence_by_track_id.remove(&cast.cast_track_id);
+            }
+            self.first_sequence += 1;
+            self.append(cast);
+        }
+    }
+
+    fn append(&mut self, cast: PendingCast) {
+        self.event.append(&cast.position, cast.time);
+        self.duration.append_value(cast.duration);
+        self.channeled.append_value(cast.channeled);
+        self.cast_track_id.append_value(cast.cast_track_id);
+        self.ability_id.append_value(cast.ability_id);
+        self.ability_name.append_option(cast.ability_name);
+        self.source.append(&cast.source);
+        self.target.append(&cast.target);
+        match cast.end {
+            Some(end) => {
+                self.end_time.append_value(end.time);
+                self.end_reason.append_option(end.reason);
+                self.interrupting_ability_id.append_option(end.interrupting_ability_id);
+                self.interrupting_unit_id.append_option(end.interrupting_unit_id);
+            }
+            None => {
+                self.end_time.append_null();
+                self.end_reason.append_null();
+                self.interrupting_ability_id.append_null();
+                self.interrupting_unit_id.append_null();
+            }
+        }
+    }
+}
+
+impl Table for CastTable {
+    fn len(&self) -> usize {
+        self.event.time.len()
+    }
+
+    fn columns(&mut self) -> Vec<(String, ArrayRef)> {
+        let mut columns = self.event.columns();
+        columns.extend([
+            column("duration", &mut self.duration),
+            column("channeled", &mut self.channeled),
+            column("cast_track_id", &mut self.cast_track_id),
+            column("ability_id", &mut self.ability_id),
+            column("ability_name", &mut self.ability_name),
+            column("end_time", &mut self.end_time),
+            column("end_reason", &mut self.end_reason),
+            column("interrupting_ability_id", &mut self.interrupting_ability_id),
+            column("interrupting_unit_id", &mut self.interrupting_unit_id),
+        ]);
+        self.source.columns("source_", &mut columns);
+        self.target.columns("target_", &mut columns);
+        columns
+    }
+}
+
+#[derive(Default)]
+struct UnitTable {
+    event: EventColumns,
+    unit_id: UInt32Builder,
+    unit_type: DictionaryBuilder,
+    monster_id: UInt32Builder,
+    is_boss: BooleanBuilder,
+    is_local_player: BooleanBuilder,
+    is_grouped_with_local_player: BooleanBuilder,
+    player_per_session_id: UInt32Builder,
+    name: StringBuilder,
+    display_name: StringBuilder,
+    character_id: UInt64Builder,
+    class: DictionaryBuilder,
+    race: DictionaryBuilder,
+    level: UInt8Builder,
+    champion_points: UInt16Builder,
+    owner_unit_id: UInt32Builder,
+    reaction: DictionaryBuilder,
+}
+
+impl UnitTable {
+    fn append(&mut self, position: &Position, unit: &UnitAdded) {
+        self.event.append(position, unit.time);
+        self.unit_id.append_value(unit.unit_id);
+        self.unit_type.append_value(format!("{:?}", unit.unit_type));
+        self.monster_id.append_value(unit.monster_id);
+        self.is_boss.append_value(unit.is_boss);
+        self.is_local_player.append_value(unit.is_local_player);
+        self.is_grouped_with_local_player.append_value(unit.is_grouped_with_local_player);
+        self.player_per_session_id.append_value(unit.player_per_session_id);
+        self.name.append_value(unit.name);
+        self.display_name.append_option(Some(unit.display_name).filter(|n| !n.is_empty()));
+        self.character_id.append_value(unit.character_id);
+        self.class.append_value(format!("{:?}", unit.class_id));
+        self.race.append_value(format!("{:?}", unit.race_id));
+        self.level.append_value(unit.level);
+        self.champion_points.append_value(unit.champion_points);
+        self.owner_unit_id.append_value(unit.owner_unit_id);
+        self.reaction.append_value(format!("{:?}", unit.reaction));
+    }
+}
+
+impl Table for UnitTable {
+    fn len(&self) -> usize {
+        self.event.time.len()
+    }
+
+    fn columns(&mut self) -> Vec<(String, ArrayRef)> {
+        let mut columns = self.event.columns();
+        columns.extend([
+            column("unit_id", &mut self.unit_id),
+            column("unit_type", &mut self.unit_type),
+            column("monster_id", &mut self.monster_id),
+            column("is_boss", &mut self.is_boss),
+            column("is_local_player", &mut self.is_local_player),
+            column("is_grouped_with_local_player", &mut self.is_grouped_with_local_player),
+            column("player_per_session_id", &mut self.player_per_session_id),
+            column("name", &mut self.name),
+            column("display_name", &mut self.display_name),
+            column("character_id", &mut self.character_id),
+            column("class", &mut self.class),
+            column("race", &mut self.race),
+            column("level", &mut self.level),
+            column("champion_points", &mut self.champion_points),
+            column("owner_unit_id", &mut self.owner_unit_id),
+            column("reaction", &mut self.reaction),
+        ]);
+        columns
+    }
+}
+
+/// One row per ability id, from the first ABILITY_INFO seen for it.
+#[derive(Default)]
+struct AbilityTable {
+    ability_id: UInt32Builder,
+    name: StringBuilder,
+    icon: StringBuilder,
+    interruptible: BooleanBuilder,
+    blockable: BooleanBuilder,
+    scribing_focus: StringBuilder,
+    scribing_signature: StringBuilder,
+    scribing_affix: StringBuilder,
+}
+
+impl AbilityTable {
+    fn append(&mut self, ability: &AbilityInfo) {
+        self.ability_id.append_value(ability.ability_id);
+        self.name.append_value(ability.name);
+        self.icon.append_value(ability.icon_path);
+        self.interruptible.append_value(ability.interruptible);
+        self.blockable.append_value(ability.blockable);
+        self.scribing_focus.append_option(ability.scribing.map(|s| s[0]));
+        self.scribing_signature.append_option(ability.scribing.map(|s| s[1]));
+        self.scribing_affix.append_option(ability.scribing.map(|s| s[2]));
+    }
+}
+
+impl Table for AbilityTable {
+    fn len(&self) -> usize {
+        self.ability_id.len()
+    }
+
+    fn columns(&mut self) -> Vec<(String, ArrayRef)> {
+        vec![
+            column("ability_id", &mut self.ability_id),
+            column("name", &mut self.name),
+            column("icon", &mut self.icon),
+            column("interruptible", &mut self.interruptible),
+            column("blockable", &mut self.blockable),
+            column("scribing_focus", &mut self.scribing_focus),
+            column("scribing_signature", &mut self.scribing_signature),
+            column("scribing_affix", &mut self.scribing_affix),
+        ]
+    }
+}
+
+struct FightTable {
+    fight: UInt32Builder,
+    log: UInt32Builder,
+    start_timestamp: TimestampMillisecondBuilder,
+    end_timestamp: TimestampMillisecondBuilder,
+    start_time: UInt64Builder,
+    end_time: UInt64Builder,
+    duration: UInt64Builder,
+    zone_id: UInt16Builder,
+    zone_name: StringBuilder,
+    boss_name: StringBuilder,
+    boss_monster_id: UInt32Builder,
+    outcome: DictionaryBuilder,
+    players: UInt32Builder,
+    trial_id: UInt32Builder,
+    trial_score: UInt32Builder,
+    start_offset: UInt64Builder,
+    end_offset: UInt64Builder,
+}
+
+impl Default for FightTable {
+    fn default() -> Self {
+        Self {
+            fight: UInt32Builder::new(),
+            log: UInt32Builder::new(),
+            start_timestamp: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+            end_timestamp: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+            start_time: UInt64Builder::new(),
+            end_time: UInt64Builder::new(),
+            duration: UInt64Builder::new(),
+            zone_id: UInt16Builder::new(),
+            zone_name: StringBuilder::new(),
+            boss_name: StringBuilder::new(),
+            boss_monster_id: UInt32Builder::new(),
+            outcome: DictionaryBuilder::new(),
+            players: UInt32Builder::new(),
+            trial_id: UInt32Builder::new(),
+            trial_score: UInt32Builder::new(),
+            start_offset: UInt64Builder::new(),
+            end_offset: UInt64Builder::new(),
+        }
+    }
+}
+
+impl FightTable {
+    fn append(&mut self, number: u32, log: u32, fight: &Fight) {
+        self.fight.append_value(number);
+        self.log.append_value(log);
+        self.start_timestamp.append_option(fight.unix_start_time().map(|t| t as i64));
+        self.end_timestamp.append_option(fight.unix_end_time().map(|t| t as i64));
+        self.start_time.append_value(fight.start_time);
+        self.end_time.append_value(fight.end_time);
+        self.duration.append_value(fight.duration());
+        self.zone_id.append_option(fight.zone_id);
+        self.zone_name.append_option(fight.zone_name.as_deref());
+        self.boss_name.append_option(fight.boss_name());
+        self.boss_monster_id.append_option(fight.bosses.first().map(|b| b.monster_id));
+        self.outcome.append_value(format!("{:?}", fight.outcome));
+        self.players.append_value(fight.players.len() as u32);
+        self.trial_id.append_option(fight.trial_id);
+        self.trial_score.append_option(fight.trial_score);
+        self.start_offset.append_value(fight.start_offset);
+        self.end_offset.append_value(fight.end_offset);
+    }
+}
+
+impl Table for FightTable {
+    fn len(&self) -> usize {
+        self.fight.len()
+    }
+
+    fn columns(&mut self) -> Vec<(String, ArrayRef)> {
+        vec![
+            column("fight", &mut self.fight),
+            column("log", &mut self.log),
+            column("start_timestamp", &mut self.start_timestamp),
+            column("end_timestamp", &mut self.end_timestamp),
+            column("start_time", &mut self.start_time),
+            column("end_time", &mut self.end_time),
+            column("duration", &mut self.duration),
+            column("zone_id", &mut self.zone_id),
+            column("zone_name", &mut self.zone_name),
+            column("boss_name", &mut self.boss_name),
+            column("boss_monster_id", &mut self.boss_monster_id),
+            column("outcome", &mut self.outcome),
+            column("players", &mut self.players),
+            column("trial_id", &mut self.trial_id),
+            column("trial_score", &mut self.trial_score),
+            column("start_offset", &mut self.start_offset),
+            column("end_offset", &mut self.end_offset),
+        ]
+    }
+}
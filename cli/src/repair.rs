@@ -0,0 +1,205 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use parser::line::LogLine;
+use serde::Serialize;
+
+/// A change made while repairing a log.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Repair {
+    /// Bytes that were not valid UTF-8 text, or control characters, were removed from a line
+    StrippedGarbage { bytes: usize },
+    /// A line without a line ending at the end of the file, as left by a crash, was removed
+    DroppedPartialLine { text: String },
+    /// A line that does not parse was removed
+    DroppedMalformedLine { error: String },
+    /// A BEGIN_LOG written straight after a partial line was moved to a line of its own
+    SplitBeginLog { dropped: String },
+    /// An END_COMBAT was added to close a fight that was still going when the session ended
+    ClosedCombat { time: u64 },
+    /// An END_LOG was added to close a session that ended without one
+    ClosedSession { time: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RepairChange {
+    /// Input line the change was made at, counting from 1. Lines added when a session is closed are counted at the line after it
+    pub line_number: usize,
+    pub repair: Repair,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RepairReport {
+    pub lines_read: usize,
+    pub lines_written: usize,
+    pub changes: Vec<RepairChange>,
+}
+
+impl RepairReport {
+    fn record(&mut self, line_number: usize, repair: Repair) {
+        self.changes.push(RepairChange { line_number, repair });
+    }
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Repair::StrippedGarbage { bytes } => write!(f, "stripped {bytes} bytes that were not text"),
+            Repair::DroppedPartialLine { text } => write!(f, "dropped partial line \"{text}\""),
+            Repair::DroppedMalformedLine { error } => write!(f, "dropped malformed line: {error}"),
+            Repair::SplitBeginLog { dropped } => write!(f, "moved BEGIN_LOG to its own line, dropping partial line \"{dropped}\""),
+            Repair::ClosedCombat { time } => write!(f, "added END_COMBAT at {time}"),
+            Repair::ClosedSession { time } => write!(f, "added END_LOG at {time}"),
+        }
+    }
+}
+
+impl Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} lines read, {} lines written, {} changes", self.lines_read, self.lines_written, self.changes.len())?;
+        for change in &self.changes {
+            write!(f, "\n  line {}: {}", change.line_number, change.repair)?;
+        }
+        Ok(())
+    }
+}
+
+/// `Encounter.log` -> `Encounter-REPAIRED.log` in the same folder
+pub fn repaired_log_path(file_path: &Path) -> PathBuf {
+    let stem = file_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    file_path.with_file_name(format!("{stem}-REPAIRED.log"))
+}
+
+/// Repairs the log at `input_path` into `output_path`, see [`repair_log_stream`].
+pub fn repair_log_file(input_path: &Path, output_path: &Path) -> Result<RepairReport, String> {
+    if let (Ok(input), Ok(output)) = (input_path.canonicalize(), output_path.canonicalize())
+        && input == output {
+        return Err("Output path must differ from the input log".to_string());
+    }
+    let input = File::open(input_path).map_err(|e| format!("Failed to open input file: {e}"))?;
+    let output = File::create(output_path).map_err(|e| format!("Failed to create output file: {e}"))?;
+    repair_log_stream(BufReader::new(input), BufWriter::new(output))
+}
+
+/// Where the repair is in the current session.
+#[derive(Default)]
+struct SessionState {
+    in_session: bool,
+    in_combat: bool,
+    last_time: u64,
+}
+
+/// Copies a log from `reader` to `writer`, salvaging what a game crash or disconnect left behind.
+///
+/// Garbage bytes are stripped, lines that no longer parse (such as the partial line a crash leaves at the end of a session) are dropped,
+/// and sessions that end without an END_LOG are closed, along with any fight still in progress, at the last time logged in them.
+/// Every change is listed in the returned report.
+pub fn repair_log_stream<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> Result<RepairReport, String> {
+    let mut report = RepairReport::default();
+    let mut state = SessionState::default();
+    let mut buffer = Vec::new();
+    let write_error = |e: std::io::Error| format!("Failed to write line: {e}");
+
+    loop {
+        buffer.clear();
+        let length = reader.read_until(b'\n', &mut buffer).map_err(|e| format!("Read error: {e}"))?;
+        if length == 0 {break}
+        report.lines_read += 1;
+        let line_number = report.lines_read;
+        let complete = buffer.ends_with(b"\n");
+        while buffer.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            buffer.pop();
+        }
+
+        let (mut text, stripped) = strip_garbage(&buffer);
+        if stripped > 0 {
+            report.record(line_number, Repair::StrippedGarbage { bytes: stripped });
+        }
+        if text.is_empty() {continue}
+
+        if LogLine::parse(&text).is_err()
+            && let Some(begin_log) = begin_log_start(&text).filter(|i| *i > 0) {
+            let dropped = text[..begin_log].to_owned();
+            text.replace_range(..begin_log, "");
+            report.record(line_number, Repair::SplitBeginLog { dropped });
+        }
+
+        let line = match LogLine::parse(&text) {
+            Ok(line) => line,
+            Err(e) => {
+                let repair = if complete {
+                    Repair::DroppedMalformedLine { error: e.to_string() }
+                } else {
+                    Repair::DroppedPartialLine { text: text.clone() }
+                };
+                report.record(line_number, repair);
+                continue;
+            }
+        };
+
+        match line {
+            LogLine::BeginLog(_) => {
+                close_session(&mut state, &mut writer, &mut report, line_number).map_err(write_error)?;
+                state = SessionState { in_session: true, ..Default::default() };
+            }
+            LogLine::EndLog { .. } => {
+                if state.in_combat {
+                    writeln!(writer, "{},END_COMBAT", state.last_time).map_err(write_error)?;
+                    report.lines_written += 1;
+                    report.record(line_number, Repair::ClosedCombat { time: state.last_time });
+                }
+                state.in_session = false;
+                state.in_combat = false;
+            }
+            LogLine::BeginCombat { .. } => state.in_combat = true,
+            LogLine::EndCombat { .. } => state.in_combat = false,
+            _ => {}
+        }
+        state.last_time = state.last_time.max(line.time());
+
+        writeln!(writer, "{text}").map_err(write_error)?;
+        report.lines_written += 1;
+    }
+
+    let end_of_file = report.lines_read + 1;
+    close_session(&mut state, &mut writer, &mut report, end_of_file).map_err(write_error)?;
+    writer.flush().map_err(|e| format!("Failed to flush output: {e}"))?;
+    Ok(report)
+}
+
+/// Writes the END_COMBAT and END_LOG a session is missing, if it is still open.
+fn close_session<W: Write>(state: &mut SessionState, writer: &mut W, report: &mut RepairReport, line_number: usize) -> std::io::Result<()> {
+    if !state.in_session {
+        return Ok(());
+    }
+    if state.in_combat {
+        writeln!(writer, "{},END_COMBAT", state.last_time)?;
+        report.lines_written += 1;
+        report.record(line_number, Repair::ClosedCombat { time: state.last_time });
+    }
+    writeln!(writer, "{},END_LOG", state.last_time)?;
+    report.lines_written += 1;
+    report.record(line_number, Repair::ClosedSession { time: state.last_time });
+    *state = SessionState::default();
+    Ok(())
+}
+
+/// Where a BEGIN_LOG starts in a line, including its time, which runs into the end of a partial line before it.
+fn begin_log_start(text: &str) -> Option<usize> {
+    let line_type = text.find(",BEGIN_LOG,")?;
+    let time_digits = text[..line_type].bytes().rev().take_while(u8::is_ascii_digit).count().max(1);
+    Some(line_type.saturating_sub(time_digits))
+}
+
+/// Decodes a line, leaving out bytes that are not valid UTF-8 and control characters such as the NULs
+/// a crash can leave in place of unwritten data. Returns the text and the number of bytes left out.
+fn strip_garbage(bytes: &[u8]) -> (String, usize) {
+    let decoded = String::from_utf8_lossy(bytes);
+    let text: String = decoded.chars()
+        .filter(|c| *c != char::REPLACEMENT_CHARACTER && (!c.is_control() || *c == '\t'))
+        .collect();
+    let stripped = bytes.len().saturating_sub(text.len());
+    (text, stripped)
+}
@@ -1,9 +1,14 @@
-use std::fs::File;
-use std::io::{BufReader, BufRead, Write};
+use std::fs::{self, File};
+use std::io::{BufReader, BufRead, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::error::Error;
+use crate::esologs_format::LINE_COUNT_FOR_PROGRESS;
 
 pub fn split_encounter_file_into_log_files(file_path: &Path) -> Result<(), Box<dyn Error>> {
+    split_encounter_file_into_directory(file_path, file_path.parent().unwrap_or_else(|| Path::new(".")))
+}
+
+pub fn split_encounter_file_into_directory(file_path: &Path, output_dir: &Path) -> Result<(), Box<dyn Error>> {
     let file = File::open(file_path)?;
     let reader = BufReader::new(file);
 
@@ -18,7 +23,7 @@ pub fn split_encounter_file_into_log_files(file_path: &Path) -> Result<(), Box<d
 
         if let (Some("BEGIN_LOG"), Some(time)) = (linetype, timestamp) {
             let out_name = format!("Split-encounter-{time}.log");
-            let out_path = file_path.parent().unwrap_or_else(|| Path::new(".")).join(out_name);
+            let out_path = output_dir.join(out_name);
             current_writer = Some(File::create(out_path)?);
         }
 
@@ -30,40 +35,142 @@ pub fn split_encounter_file_into_log_files(file_path: &Path) -> Result<(), Box<d
     Ok(())
 }
 
-pub fn combine_encounter_log_files(file_paths: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+pub fn combine_encounter_log_files(file_paths: &[PathBuf]) -> Result<PathBuf, Box<dyn Error>> {
+    combine_encounter_log_files_into(file_paths, None)
+}
+
+pub fn combine_encounter_log_files_into(file_paths: &[PathBuf], output_path: Option<&Path>) -> Result<PathBuf, Box<dyn Error>> {
+    combine_encounter_log_files_with_progress(file_paths, output_path, |_| {})
+}
+
+/// One BEGIN_LOG and the lines after it, up to the next BEGIN_LOG or the end of the file.
+struct LogSession {
+    file: usize,
+    start_offset: u64,
+    end_offset: u64,
+    log_time: u64,
+    end_time: u64,
+}
+
+/// Combines logs into one, ordered by the wall-clock time of their BEGIN_LOGs whatever order the files are given in.
+///
+/// Every file must start with a BEGIN_LOG. A session found in more than one file, as when a log is combined twice
+/// or a copy of Encounter.log is combined with the log it was taken from, is written once, from its longest copy.
+/// A session starting before the previous one ended cannot come from the same game client and is left out.
+/// Without an `output_path` the combined log is written to `Combined-encounter-<start>-<end>.log` next to the earliest input.
+/// `progress_callback` receives the percentage of the work done so far. Returns the path of the combined log.
+pub fn combine_encounter_log_files_with_progress<F: FnMut(u8)>(file_paths: &[PathBuf], output_path: Option<&Path>, mut progress_callback: F) -> Result<PathBuf, Box<dyn Error>> {
     if file_paths.is_empty() {
         return Err("No files provided".into());
     }
 
-    let first_file = File::open(&file_paths[0])?;
-    let mut first_reader = BufReader::new(first_file);
-    let mut first_line = String::new();
-    first_reader.read_line(&mut first_line)?;
-    let start_timestamp = first_line.split(',').nth(2)
-        .ok_or("Malformed BEGIN_LOG line in first file")?
-        .trim();
-
-    let last_file = File::open(file_paths.last().unwrap())?;
-    let mut last_reader = BufReader::new(last_file);
-    let mut last_line = String::new();
-    last_reader.read_line(&mut last_line)?;
-    let end_timestamp = last_line.split(',').nth(2)
-        .ok_or("Malformed BEGIN_LOG line in last file")?
-        .trim();
-
-    let out_name = format!("Combined-encounter-{start_timestamp}-{end_timestamp}.log");
-    let out_path = Path::new(&out_name);
-    let mut out_file = File::create(out_path)?;
-
-    for path in file_paths {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let line = line?;
-            writeln!(out_file, "{line}")?;
+    // Finding the sessions is the first half of the work and copying them the second
+    let total_bytes = file_paths.iter().map(|path| fs::metadata(path).map(|m| m.len()).unwrap_or(0)).sum::<u64>().max(1);
+    let mut read_bytes = 0;
+    let mut sessions = Vec::new();
+    for (file, path) in file_paths.iter().enumerate() {
+        sessions.extend(read_log_sessions(file, path, |bytes| {
+            progress_callback(((read_bytes + bytes) as f64 / total_bytes as f64 * 50.0).round().min(50.0) as u8);
+        })?);
+        read_bytes += fs::metadata(path)?.len();
+    }
+    sessions.sort_by_key(|s| (s.log_time, std::cmp::Reverse(s.end_offset - s.start_offset)));
+
+    let mut kept: Vec<LogSession> = Vec::new();
+    for session in sessions {
+        let path = file_paths[session.file].display();
+        match kept.last() {
+            Some(previous) if previous.log_time == session.log_time => {
+                log::info!("Skipping session {} in {path}, it is already in {}", session.log_time, file_paths[previous.file].display());
+            }
+            Some(previous) if session.log_time < previous.end_time => {
+                log::warn!("Skipping session {} in {path}, it overlaps session {} in {}", session.log_time, previous.log_time, file_paths[previous.file].display());
+            }
+            _ => kept.push(session),
         }
     }
 
-    Ok(())
-}
\ No newline at end of file
+    let (first, last) = (&kept[0], &kept[kept.len() - 1]);
+    let out_name = format!("Combined-encounter-{}-{}.log", first.log_time, last.log_time);
+    let out_path = match output_path {
+        Some(path) => path.to_path_buf(),
+        None => file_paths[first.file].parent().unwrap_or(Path::new(".")).join(out_name),
+    };
+    if let Ok(output) = out_path.canonicalize()
+        && file_paths.iter().any(|path| path.canonicalize().is_ok_and(|input| input == output)) {
+        return Err("Output path must differ from the input logs".into());
+    }
+
+    let mut writer = BufWriter::new(File::create(&out_path)?);
+    let mut buffer = Vec::new();
+    let kept_bytes = kept.iter().map(|s| s.end_offset - s.start_offset).sum::<u64>().max(1);
+    let mut written_bytes = 0;
+    let mut line_number = 0usize;
+    for session in &kept {
+        let mut reader = BufReader::new(File::open(&file_paths[session.file])?);
+        reader.seek(SeekFrom::Start(session.start_offset))?;
+        let mut remaining = session.end_offset - session.start_offset;
+        while remaining > 0 {
+            buffer.clear();
+            let length = reader.by_ref().take(remaining).read_until(b'\n', &mut buffer)? as u64;
+            if length == 0 {break}
+            writer.write_all(&buffer)?;
+            if !buffer.ends_with(b"\n") {
+                writer.write_all(b"\n")?;
+            }
+            remaining -= length;
+            written_bytes += length;
+            line_number += 1;
+            if line_number.is_multiple_of(LINE_COUNT_FOR_PROGRESS) {
+                progress_callback(50 + (written_bytes as f64 / kept_bytes as f64 * 50.0).round().min(50.0) as u8);
+            }
+        }
+    }
+    writer.flush()?;
+    progress_callback(100);
+    Ok(out_path)
+}
+
+/// Finds the sessions in a log, checking that it starts with a BEGIN_LOG. `progress` receives the bytes read so far.
+fn read_log_sessions(file: usize, path: &Path, mut progress: impl FnMut(u64)) -> Result<Vec<LogSession>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut sessions: Vec<LogSession> = Vec::new();
+    let mut buffer = Vec::new();
+    let mut offset = 0;
+    let mut line_number = 0usize;
+
+    loop {
+        buffer.clear();
+        let length = reader.read_until(b'\n', &mut buffer)? as u64;
+        if length == 0 {break}
+        let line = String::from_utf8_lossy(&buffer);
+        let mut parts = line.trim_end().splitn(4, ',');
+        let time = parts.next().and_then(|t| t.parse::<u64>().ok());
+        let linetype = parts.next();
+
+        if linetype == Some("BEGIN_LOG") {
+            let log_time = parts.next().and_then(|t| t.parse::<u64>().ok())
+                .ok_or_else(|| format!("Malformed BEGIN_LOG line in {}", path.display()))?;
+            sessions.push(LogSession { file, start_offset: offset, end_offset: offset, log_time, end_time: log_time });
+        } else if line_number == 0 {
+            return Err(format!("{} does not start with a BEGIN_LOG", path.display()).into());
+        }
+        if let Some(session) = sessions.last_mut() {
+            session.end_offset = offset + length;
+            if let Some(time) = time {
+                session.end_time = session.end_time.max(session.log_time + time);
+            }
+        }
+
+        offset += length;
+        line_number += 1;
+        if line_number.is_multiple_of(LINE_COUNT_FOR_PROGRESS) {
+            progress(offset);
+        }
+    }
+
+    if sessions.is_empty() {
+        return Err(format!("{} is empty", path.display()).into());
+    }
+    Ok(sessions)
+}
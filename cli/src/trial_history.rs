@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use parser::trial::TrialRun;
+use parser::zone::DungeonDifficulty;
+
+const HEADER: &str = "start_time,trial_id,trial,difficulty,success,duration,score,vitality_bonus,fights,wipes,deaths,boss_kills,players";
+
+/// Quotes a field if it holds a comma, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}", ms as f64 / 1000.0)
+}
+
+/// One row of the history: times are Unix milliseconds for the start and seconds for lengths,
+/// and each boss kill is written as `name seconds-into-run`, separated by semicolons.
+fn history_row(run: &TrialRun, start_time: u64) -> String {
+    let boss_kills: Vec<String> = run.bosses.iter()
+        .filter_map(|boss| boss.kill_time.map(|time| format!("{} {}", boss.name, seconds(time))))
+        .collect();
+    [
+        start_time.to_string(),
+        run.trial_id.to_string(),
+        csv_field(run.zone_name.as_deref().unwrap_or_default()),
+        run.difficulty.filter(|d| *d != DungeonDifficulty::None).map(|d| format!("{d:?}")).unwrap_or_default(),
+        run.success.to_string(),
+        run.duration.map(seconds).unwrap_or_default(),
+        run.final_score.map(|s| s.to_string()).unwrap_or_default(),
+        run.vitality_bonus.map(|v| v.to_string()).unwrap_or_default(),
+        run.fights.to_string(),
+        run.wipes.to_string(),
+        run.deaths.to_string(),
+        csv_field(&boss_kills.join("; ")),
+        csv_field(&run.players.join("; ")),
+    ].join(",")
+}
+
+/// Appends the completed runs in `runs` to the CSV history at `path`, creating it with a header if it does not exist.
+/// Runs are told apart by start time and trial id, so a run already in the history is not added twice when a log is read again.
+/// Runs that did not end with an END_TRIAL are left out, as they have no score and may still be in progress.
+/// Returns the number of runs added.
+pub fn append_trial_history(path: &Path, runs: &[TrialRun]) -> Result<usize, String> {
+    let mut recorded: HashSet<(u64, u32)> = HashSet::new();
+    if let Ok(file) = File::open(path) {
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            let mut fields = line.split(',');
+            if let (Some(Ok(start_time)), Some(Ok(trial_id))) = (fields.next().map(str::parse), fields.next().map(str::parse)) {
+                recorded.insert((start_time, trial_id));
+            }
+        }
+    }
+
+    let needs_header = !fs::metadata(path).is_ok_and(|m| m.len() > 0);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)
+        .map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    let write_error = |e: std::io::Error| format!("Failed to write to {}: {e}", path.display());
+    if needs_header {
+        writeln!(file, "{HEADER}").map_err(write_error)?;
+    }
+
+    let mut added = 0;
+    for run in runs.iter().filter(|run| run.completed) {
+        let Some(start_time) = run.unix_start_time else {
+            log::warn!("Trial {} at {}ms has no start time, leaving it out of the history", run.trial_id, run.start_time);
+            continue;
+        };
+        if !recorded.insert((start_time, run.trial_id)) {continue}
+        writeln!(file, "{}", history_row(run, start_time)).map_err(write_error)?;
+        added += 1;
+    }
+    Ok(added)
+}
@@ -169,3 +169,9 @@ pub struct UpdateInformation {
     pub version: String,
     pub current_version: String,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogFixInfo {
+    pub name: String,
+    pub description: String,
+}
@@ -1,5 +1,6 @@
-use cli::{esologs_convert::{build_master_table, build_report_segment, event_timestamp, split_and_zip_log_by_fight, write_zip_with_logtxt, ESOLogProcessor}, esologs_format::{ESO_LOGS_COM_VERSION, ESO_LOGS_PARSER_VERSION, LINE_COUNT_FOR_PROGRESS}, log_edit::{handle_line, CustomLogData}};
-use esologtool_common::{EncounterReportCode, LoginResponse, UpdateInformation, UploadSettings};
+use cli::{esologs_convert::{build_master_table, build_report_segment, event_timestamp, split_and_zip_log_by_fight, write_zip_with_logtxt, ESOLogProcessor}, esologs_format::{ESO_LOGS_COM_VERSION, ESO_LOGS_PARSER_VERSION, LINE_COUNT_FOR_PROGRESS}, log_edit::{handle_line, modified_log_path, modify_log_file_with_progress, LogFixes}, split_log::combine_encounter_log_files_with_progress};
+use esologtool_common::{EncounterReportCode, LogFixInfo, LoginResponse, UpdateInformation, UploadSettings};
+use parser::index::LogIndex;
 use reqwest::{multipart::{Form, Part}, Client};
 use serde_json::json;
 use state::AppState;
@@ -16,54 +17,41 @@ use crate::state::{cookie_file_path, cookie_folder_path};
 mod state;
 
 #[tauri::command]
-fn modify_log_file(window: Window, state: State<'_, AppState>) -> Result<(), String> {
-    let paths_guard = state.log_files.read().unwrap();
-    let file_paths = paths_guard.as_ref().ok_or("No file paths set")?;
-    let file_path = file_paths.first().ok_or("No file path in vector")?;
-    let path_ref = file_path.as_path().ok_or("Invalid file path")?;
-    let file = File::open(path_ref).map_err(|e| format!("Failed to open file: {e}"))?;
-    let reader = BufReader::new(&file);
-    let total_lines = reader.lines().count();
-
-    let parent = path_ref.parent().unwrap_or_else(|| Path::new("."));
-    let orig_file_name = path_ref.file_stem().and_then(|s| s.to_str()).unwrap_or("Encounter");
-    let new_file_name = format!("{orig_file_name}-MODIFIED.log");
-    let new_path = parent.join(new_file_name);
-    let file = File::open(path_ref).map_err(|e| format!("Failed to reopen file: {e}"))?;
-    let reader = BufReader::new(file);
-
-    let file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&new_path)
-        .map_err(|e| format!("Failed to create output file: {e}"))?;
-    let mut writer = BufWriter::new(file);
-    let mut custom_log_data = CustomLogData::new();
+fn get_log_fixes() -> Vec<LogFixInfo> {
+    LogFixes::new().enabled().map(|fix| LogFixInfo {
+        name: fix.name().to_string(),
+        description: fix.description().to_string(),
+    }).collect()
+}
 
-    let mut processed = 0;
-    for line_result in reader.lines() {
-        let line = match line_result {
-            Ok(l) => l,
-            Err(e) => {
-                log::warn!("Error reading line: {e}");
-                continue;
+#[tauri::command]
+async fn modify_log_file(window: Window, state: State<'_, AppState>, disabled_fixes: Vec<String>) -> Result<(), String> {
+    let path = {
+        let paths_guard = state.log_files.read().map_err(|e| e.to_string())?;
+        let file_paths = paths_guard.as_ref().ok_or("No file paths set")?;
+        let file_path = file_paths.first().ok_or("No file path in vector")?;
+        file_path.as_path().ok_or("Invalid file path")?.to_path_buf()
+    };
+    let new_path = modified_log_path(&path).map_err(|e| format!("Failed to build output path: {e}"))?;
+
+    state.modify_cancel_flag.store(false, SeqCst);
+    let modify_cancel_flag = state.modify_cancel_flag.clone();
+    spawn_blocking(move || {
+        let log_fixes = LogFixes::without(&disabled_fixes)?;
+        let mut last_progress = None;
+        modify_log_file_with_progress(&path, &new_path, log_fixes, |progress| {
+            if last_progress == Some(progress) {return}
+            last_progress = Some(progress);
+            if let Err(e) = window.emit("log_modify_progress", progress) {
+                log::warn!("Failed to emit progress: {e}");
             }
-        };
-        let modified_line = handle_line(line, &mut custom_log_data);
-        for entry in modified_line {
-            writeln!(writer, "{entry}").ok();
-        }
-        processed += 1;
-
-        if processed % LINE_COUNT_FOR_PROGRESS == 0 || processed == total_lines {
-            let progress = (processed * 100 / total_lines).min(100);
-            window
-                .emit("log_modify_progress", progress)
-                .map_err(|e| format!("Failed to emit progress: {e}"))?;
-        }
-    }
+        }, &modify_cancel_flag)
+    }).await.map_err(|e| format!("spawn_blocking error: {e}"))?
+}
 
+#[tauri::command]
+fn cancel_modify_log(state: State<'_, AppState>) -> Result<(), String> {
+    state.modify_cancel_flag.store(true, SeqCst);
     Ok(())
 }
 
@@ -133,76 +121,18 @@ fn split_encounter_file_into_log_files(window: Window, state: State<'_, AppState
 fn combine_encounter_log_files(window: Window, state: State<'_, AppState>) -> Result<(), String> {
     let paths_guard = state.log_files.read().map_err(|e| e.to_string())?;
     let file_paths = paths_guard.as_ref().ok_or("No file paths set")?;
-    if file_paths.is_empty() {
-        return Err("No files provided".into());
-    }
-
-    let first_file = File::open(file_paths[0].as_path().unwrap())
-        .map_err(|e| format!("Failed to open first file: {e}"))?;
-    let mut first_reader = BufReader::new(first_file);
-    let mut first_line = String::new();
-    first_reader
-        .read_line(&mut first_line)
-        .map_err(|e| format!("Failed to read first line: {e}"))?;
-    let start_timestamp = first_line.split(',')
-        .nth(2)
-        .ok_or("Malformed BEGIN_LOG line in first file")?
-        .trim();
-
-    let last_path = file_paths
-        .last()
-        .unwrap()
-        .as_path()
-        .ok_or("Invalid file path")?;
-    let last_file =
-        File::open(last_path).map_err(|e| format!("Failed to open last file: {e}"))?;
-    let mut last_reader = BufReader::new(last_file);
-    let mut last_line = String::new();
-    last_reader
-        .read_line(&mut last_line)
-        .map_err(|e| format!("Failed to read last line: {e}"))?;
-    let end_timestamp = last_line.split(',')
-        .nth(2)
-        .ok_or("Malformed BEGIN_LOG line in last file")?
-        .trim();
-
-    let out_name = format!(
-        "Combined-Encounter-{start_timestamp}-{end_timestamp}.log"
-    );
-    let parent = file_paths[0]
-        .as_path()
-        .and_then(|p| p.parent())
-        .unwrap_or_else(|| Path::new("."));
-    let out_path = parent.join(out_name);
-    let mut out_file =
-        File::create(&out_path).map_err(|e| format!("Failed to create output file: {e}"))?;
-
-    let mut total_lines = 0;
-    for path in file_paths {
-        let file = File::open(path.as_path().unwrap())
-            .map_err(|e| format!("Failed to open file for counting: {e}"))?;
-        let reader = BufReader::new(file);
-        total_lines += reader.lines().count();
-    }
-
-    let mut processed = 0;
-    for path in file_paths {
-        let file = File::open(path.as_path().unwrap())
-            .map_err(|e| format!("Failed to open file: {e}"))?;
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let line = line.map_err(|e| format!("Failed to read line: {e}"))?;
-            writeln!(out_file, "{line}").map_err(|e| format!("Failed to write line: {e}"))?;
-            processed += 1;
-            if processed % LINE_COUNT_FOR_PROGRESS == 0 || processed == total_lines {
-                let progress = (processed * 100 / total_lines).min(100);
-                window
-                    .emit("log_combine_progress", progress)
-                    .map_err(|e| format!("Failed to emit progress: {e}"))?;
-            }
+    let file_paths = file_paths.iter()
+        .map(|path| path.as_path().map(Path::to_path_buf).ok_or("Invalid file path"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut last_progress = None;
+    combine_encounter_log_files_with_progress(&file_paths, None, |progress| {
+        if last_progress == Some(progress) {return}
+        last_progress = Some(progress);
+        if let Err(e) = window.emit("log_combine_progress", progress) {
+            log::warn!("Failed to emit progress: {e}");
         }
-    }
+    }).map_err(|e| format!("Failed to combine log files: {e}"))?;
 
     Ok(())
 }
@@ -258,7 +188,7 @@ fn live_log_from_folder(window: Window, app_state: State<'_, AppState>) -> Resul
             if let Some(last_newline_offset) = buffer.iter().rposition(|&b| b == b'\n') {
                 let complete_data = &buffer[..=last_newline_offset];
                 let text = String::from_utf8_lossy(complete_data);
-                let mut custom_log_data = CustomLogData::new();
+                let mut custom_log_data = LogFixes::new();
                 let mut new_lines = 0;
 
                 for line in text.lines() {
@@ -944,29 +874,19 @@ async fn live_log_upload(window: Window, app_state: State<'_, AppState>, upload_
             input_file.seek(std::io::SeekFrom::Start(0)).expect("seek failed")
         } else {
             log::trace!("[live_log_upload] Rewind disabled → seeking to end of file.");
-            let scan_file = std::fs::OpenOptions::new().read(true).open(&input_path)
-                .expect("Failed to open file for scanning");
-            let mut reader = std::io::BufReader::new(&scan_file);
-            let mut pos = 0u64;
-            let mut last_begin_log_pos = 0u64;
-            loop {
-                let start_pos = pos;
-                let mut buf = String::new();
-                let bytes_read = reader.read_line(&mut buf).expect("read failed");
-                if bytes_read == 0 {
-                    break;
-                }
-                if buf.contains("BEGIN_LOG") {
-                    last_begin_log_pos = start_pos;
+            let last_begin_log_pos = match LogIndex::open(&input_path) {
+                Ok(index) => index.last_begin_log().map_or(0, |entry| entry.offset),
+                Err(e) => {
+                    log::warn!("[live_log_upload] Failed to index Encounter.log ({e}). Reading from the start.");
+                    0
                 }
-                pos += bytes_read as u64;
-            }
+            };
             input_file.seek(std::io::SeekFrom::Start(last_begin_log_pos)).expect("seek failed")
         };
         log::trace!("[live_log_upload] Initial file position: {pos}");
 
         let mut elp = ESOLogProcessor::new();
-        let mut custom_state = CustomLogData::new();
+        let mut custom_state = LogFixes::new();
         let mut first_timestamp: Option<u64> = None;
         let mut segment_id: u16 = 1;
         let mut processed = 0usize;
@@ -1204,7 +1124,9 @@ pub fn run() {
             pick_and_load_file,
             pick_and_load_files,
             pick_and_load_folder,
+            get_log_fixes,
             modify_log_file,
+            cancel_modify_log,
             split_encounter_file_into_log_files,
             combine_encounter_log_files,
             live_log_from_folder,
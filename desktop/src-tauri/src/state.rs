@@ -91,6 +91,7 @@ pub struct AppState {
     pub http: RwLock<HttpState>,
     pub esolog_code: RwLock<Option<String>>,
     pub upload_cancel_flag: Arc<AtomicBool>,
+    pub modify_cancel_flag: Arc<AtomicBool>,
     pub update: RwLock<Option<UpdateInformation>>,
 }
 
@@ -102,6 +103,7 @@ impl AppState {
             http: RwLock::new(HttpState::new()),
             esolog_code: RwLock::new(None),
             upload_cancel_flag: Arc::new(AtomicBool::new(false)),
+            modify_cancel_flag: Arc::new(AtomicBool::new(false)),
             update: RwLock::new(None),
         }
     }
@@ -1,4 +1,6 @@
-use tauri_sys::{core::invoke, event};
+use esologtool_common::LogFixInfo;
+use tauri_sys::{core::{invoke, invoke_result}, event};
+use web_sys::HtmlInputElement;
 use yew::prelude::*;
 use yew_router::hooks::use_navigator;
 use yew_icons::IconData;
@@ -11,6 +13,8 @@ pub fn modify_screen() -> Html {
     let is_modifying = use_state(|| false);
     let progress = use_state(|| 0u32);
     let has_chosen_file = use_state(|| false);
+    let log_fixes = use_state(Vec::<LogFixInfo>::new);
+    let disabled_fixes = use_state(Vec::<String>::new);
     let progress_effect = progress.clone();
     let is_modifying_effect = is_modifying.clone();
     let navigator_effect = navigator.clone();
@@ -33,22 +37,79 @@ pub fn modify_screen() -> Html {
         || ()
     });
 
+    {
+        let log_fixes = log_fixes.clone();
+        use_effect_with((), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                log_fixes.set(invoke::<Vec<LogFixInfo>>("get_log_fixes", &()).await);
+            });
+            || ()
+        });
+    }
+
     let select_log = {
         let has_chosen_file = has_chosen_file.clone();
         let is_modifying = is_modifying.clone();
+        let disabled_fixes = disabled_fixes.clone();
         move |_| {
             let has_chosen_file = has_chosen_file.clone();
             let is_modifying = is_modifying.clone();
+            let disabled: Vec<String> = (*disabled_fixes).clone();
             wasm_bindgen_futures::spawn_local(async move {
                 invoke::<()>("pick_and_load_file", &()).await;
                 has_chosen_file.set(true);
                 is_modifying.set(true);
 
-                invoke::<()>("modify_log_file", &()).await;
+                // a cancelled or failed modification goes back to the file selection
+                if invoke_result::<(), String>("modify_log_file", &serde_json::json!({"disabledFixes": disabled})).await.is_err() {
+                    is_modifying.set(false);
+                    has_chosen_file.set(false);
+                }
             });
         }
     };
 
+    let cancel_modify = {
+        let is_modifying = is_modifying.clone();
+        let has_chosen_file = has_chosen_file.clone();
+        let progress = progress.clone();
+        Callback::from(move |_| {
+            let is_modifying = is_modifying.clone();
+            let has_chosen_file = has_chosen_file.clone();
+            let progress = progress.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                invoke::<()>("cancel_modify_log", &()).await;
+                is_modifying.set(false);
+                has_chosen_file.set(false);
+                progress.set(0);
+            });
+        })
+    };
+
+    let fix_toggles = log_fixes.iter().map(|fix| {
+        let name = fix.name.clone();
+        let disabled_fixes = disabled_fixes.clone();
+        let onchange = Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut disabled = (*disabled_fixes).clone();
+            disabled.retain(|n| *n != name);
+            if !input.checked() {
+                disabled.push(name.clone());
+            }
+            disabled_fixes.set(disabled);
+        });
+        html! {
+            <div style="margin-top:0.5em;">
+                <input
+                    type="checkbox"
+                    checked={!disabled_fixes.contains(&fix.name)}
+                    {onchange}
+                />
+                <span style="margin-left:0.5em;">{&fix.description}</span>
+            </div>
+        }
+    }).collect::<Html>();
+
     html! {
         <>
             <div class={classes!(if *is_modifying {hide_style().clone()} else {none_style().clone()})}>
@@ -68,6 +129,9 @@ pub fn modify_screen() -> Html {
                     <div class={paragraph_style()}>
                         {"If you intended to upload a log directly to esologs.com, please press the back arrow and log in instead."}
                     </div>
+                    <div class={paragraph_style()}>
+                        { fix_toggles }
+                    </div>
                 }
                 <div class={
                     if *is_modifying {
@@ -76,6 +140,16 @@ pub fn modify_screen() -> Html {
                         classes!(hide_style().clone(), header_style().clone())
                     }
                 }>{format!("{}%", *progress)}</div>
+                if *is_modifying {
+                    <IconButton
+                        data={IconData::BOOTSTRAP_X_LG}
+                        description={"Cancel"}
+                        onclick={Some(cancel_modify.clone())}
+                        class={icon_border_style().clone()}
+                        width={"2em"}
+                        height={"2em"}
+                    />
+                }
             </div>
         </>
     }
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, io::{self, BufRead}};
+
+use esosim::data::{item_type::{GearSlot, ITEM_TYPES, ItemType}, skill::ability_id_to_subclass};
+use serde::Serialize;
+
+use crate::{fights::{Fight, FightHeader, summarise_fights}, line::{LogLine, PlayerInfo}, player::{Class, Race, match_gear_slot}, session::Session, set::{get_item_type_name, get_set_name, is_armour_slot, is_jewellery_slot, is_mythic_set, is_weapon_slot}, subclassing::subclass_to_name};
+
+const PERFECTED_SUFFIX: &str = " (Perfected)";
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BuildPiece {
+    /// E.g. `Main Hand` or `Backup Off Hand`
+    pub slot: String,
+    pub item_id: u32,
+    /// Weapon type or armour weight, e.g. `Inferno Staff` or `Medium`, when the item is known
+    pub item_type: Option<String>,
+    pub set_id: u16,
+    pub set_name: Option<String>,
+    pub perfected: bool,
+    pub mythic: bool,
+    pub gear_trait: Option<String>,
+    pub quality: String,
+    pub enchant: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BuildSet {
+    /// Without the perfected suffix, as perfected and normal pieces count towards the same set
+    pub name: String,
+    /// Armour, jewellery and front bar weapons, with two-handed weapons counting twice
+    pub pieces: u8,
+    pub perfected_pieces: u8,
+    /// Back bar weapons, which only count while that bar is active
+    pub back_bar_pieces: u8,
+    pub mythic: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BuildAbility {
+    pub ability_id: u32,
+    pub name: String,
+    /// Focus, signature and affix scripts of a scribed ability
+    pub scripts: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PlayerBuild {
+    pub unit_id: u32,
+    pub name: String,
+    pub display_name: String,
+    pub class: Class,
+    pub race: Race,
+    pub level: u8,
+    pub champion_points: u16,
+    /// Class skill lines, going by the passives and slotted abilities the player has from each
+    pub subclasses: Vec<String>,
+    /// Sorted by pieces, most first
+    pub sets: Vec<BuildSet>,
+    /// In the order the log lists them
+    pub gear: Vec<BuildPiece>,
+    pub front_bar: Vec<BuildAbility>,
+    pub back_bar: Vec<BuildAbility>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightBuilds {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    /// Sorted by name
+    pub players: Vec<PlayerBuild>,
+}
+
+/// `INCREASE_BASH_DAMAGE` -> `Increase Bash Damage`
+fn title_case(text: &str) -> String {
+    text.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let lower = word.to_lowercase();
+            let mut chars = lower.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The in-game name of an equipment slot as the log writes it
+fn slot_name(slot: &str) -> String {
+    match slot {
+        "HAND" => "Hands".to_string(),
+        "NECK" => "Necklace".to_string(),
+        "RING1" => "Ring 1".to_string(),
+        "RING2" => "Ring 2".to_string(),
+        "BACKUP_MAIN" => "Backup Main Hand".to_string(),
+        "BACKUP_OFF" => "Backup Off Hand".to_string(),
+        other => title_case(other),
+    }
+}
+
+/// The in-game name of an item quality as the log writes it
+fn quality_name(quality: &str) -> String {
+    match quality {
+        "NORMAL" => "Normal".to_string(),
+        "MAGIC" => "Fine".to_string(),
+        "ARCANE" => "Superior".to_string(),
+        "ARTIFACT" => "Epic".to_string(),
+        "LEGENDARY" => "Legendary".to_string(),
+        "MYTHIC_OVERRIDE" => "Mythic".to_string(),
+        other => title_case(other),
+    }
+}
+
+/// How many pieces a weapon counts as towards its set
+fn set_pieces(item_type: Option<&ItemType>) -> u8 {
+    match item_type {
+        Some(ItemType::TwoHandedAxe | ItemType::TwoHandedMace | ItemType::TwoHandedSword | ItemType::FrostStaff
+            | ItemType::FireStaff | ItemType::LightningStaff | ItemType::HealingStaff | ItemType::Bow) => 2,
+        _ => 1,
+    }
+}
+
+/// Decodes one `<equipmentInfo>` entry, naming its set, trait, quality and enchant. Read from the text rather than through
+/// [`crate::parse::gear_piece`] so that pieces with enchants the simulator does not know still show up.
+fn build_piece(text: &str) -> Option<(BuildPiece, GearSlot)> {
+    let fields: Vec<&str> = text.split(',').collect();
+    if fields.len() < 10 {return None}
+    let slot = match_gear_slot(fields[0])?;
+    let item_id = fields[1].parse().unwrap_or(0);
+    let set_id = fields[6].parse().unwrap_or(0);
+    let set_name = get_set_name(set_id);
+    let known = |value: &&str| !value.is_empty() && *value != "NONE" && *value != "INVALID";
+    Some((BuildPiece {
+        slot: slot_name(fields[0]),
+        item_id,
+        item_type: ITEM_TYPES.get(&item_id).filter(|t| !matches!(t, ItemType::Unknown)).map(|t| get_item_type_name(t).to_owned()),
+        set_id,
+        set_name: set_name.map(|n| n.strip_suffix(PERFECTED_SUFFIX).unwrap_or(n).to_owned()),
+        perfected: set_name.is_some_and(|n| n.ends_with(PERFECTED_SUFFIX)),
+        mythic: is_mythic_set(set_id),
+        gear_trait: Some(fields[4].trim_start_matches("ARMOR_").trim_start_matches("JEWELRY_").trim_start_matches("WEAPON_"))
+            .filter(known)
+            .map(title_case),
+        quality: quality_name(fields[5]),
+        enchant: Some(fields[7]).filter(known).map(title_case),
+    }, slot))
+}
+
+fn sets(gear: &[(BuildPiece, GearSlot)]) -> Vec<BuildSet> {
+    let mut sets: Vec<BuildSet> = Vec::new();
+    for (piece, slot) in gear {
+        let Some(name) = &piece.set_name else {continue};
+        let i = match sets.iter().position(|s| s.name == *name) {
+            Some(i) => i,
+            None => {
+                sets.push(BuildSet { name: name.clone(), pieces: 0, perfected_pieces: 0, back_bar_pieces: 0, mythic: piece.mythic });
+                sets.len() - 1
+            }
+        };
+        let set = &mut sets[i];
+        let count = if is_weapon_slot(slot) { set_pieces(ITEM_TYPES.get(&piece.item_id)) } else { 1 };
+        if matches!(slot, GearSlot::MainHandBackup | GearSlot::OffHandBackup) {
+            set.back_bar_pieces += count;
+            continue;
+        }
+        if !is_weapon_slot(slot) && !is_armour_slot(slot) && !is_jewellery_slot(slot) {continue}
+        set.pieces += count;
+        if piece.perfected {
+            set.perfected_pieces += count;
+        }
+    }
+    sets.sort_by(|a, b| b.pieces.cmp(&a.pieces).then(b.back_bar_pieces.cmp(&a.back_bar_pieces)).then(a.name.cmp(&b.name)));
+    sets
+}
+
+fn bar(ids: impl Iterator<Item = u32>, session: &Session) -> Vec<BuildAbility> {
+    ids.map(|ability_id| {
+        let ability = session.ability(ability_id);
+        BuildAbility {
+            ability_id,
+            name: ability.map(|a| a.name.to_string()).unwrap_or_default(),
+            scripts: ability.and_then(|a| a.scribing.clone()),
+        }
+    }).collect()
+}
+
+/// Reads a player's build from their PLAYER_INFO, once the session has applied it.
+pub fn player_build(info: &PlayerInfo, session: &Session) -> Option<PlayerBuild> {
+    let player = session.player(info.unit_id)?;
+    let gear: Vec<(BuildPiece, GearSlot)> = info.gear.iter().filter_map(|g| build_piece(g)).collect();
+
+    let mut subclasses: Vec<String> = Vec::new();
+    let ability_ids = info.long_term_effects().map(|(id, _)| id).chain(info.primary_ability_ids()).chain(info.backup_ability_ids());
+    for id in ability_ids {
+        if let Some(subclass) = ability_id_to_subclass(&id) {
+            let name = subclass_to_name(subclass);
+            if !subclasses.contains(&name) {
+                subclasses.push(name);
+            }
+        }
+    }
+
+    Some(PlayerBuild {
+        unit_id: info.unit_id,
+        name: player.name.clone(),
+        display_name: player.display_name.clone(),
+        class: player.class_id,
+        race: player.race_id.clone(),
+        level: player.level,
+        champion_points: player.champion_points,
+        subclasses,
+        sets: sets(&gear),
+        gear: gear.into_iter().map(|(piece, _)| piece).collect(),
+        front_bar: bar(info.primary_ability_ids(), session),
+        back_bar: bar(info.backup_ability_ids(), session),
+    })
+}
+
+/// Keeps the latest build of every player, as the game logs a PLAYER_INFO for each player when a fight begins.
+#[derive(Debug, Default)]
+pub struct BuildAccumulator {
+    builds: HashMap<u32, PlayerBuild>,
+}
+
+impl BuildAccumulator {
+    /// A PLAYER_INFO without gear or long-term effects leaves those as they were, as the game often logs players with only their bars.
+    pub fn handle_player_info(&mut self, info: &PlayerInfo, session: &Session) {
+        let Some(mut build) = player_build(info, session) else {return};
+        if let Some(previous) = self.builds.remove(&info.unit_id) {
+            if info.gear.is_empty() {
+                build.gear = previous.gear;
+                build.sets = previous.sets;
+            }
+            if info.long_term_effect_ids.is_empty() {
+                build.subclasses = previous.subclasses;
+            }
+        }
+        self.builds.insert(info.unit_id, build);
+    }
+
+    /// Forgets every build, as unit ids are reused once a new log begins.
+    pub fn clear(&mut self) {
+        self.builds.clear();
+    }
+
+    /// The builds of the players who took part in `fight`.
+    pub fn finish(&self, fight: &Fight) -> FightBuilds {
+        let mut players: Vec<PlayerBuild> = fight.players.iter()
+            .filter_map(|p| self.builds.get(&p.unit_id).cloned())
+            .collect();
+        players.sort_by(|a, b| a.name.cmp(&b.name));
+
+        FightBuilds {
+            fight: FightHeader::from(fight),
+            players,
+        }
+    }
+}
+
+/// Reads the build every player had in every fight of a log.
+pub fn fight_builds<R: BufRead>(reader: R) -> io::Result<Vec<FightBuilds>> {
+    summarise_fights(reader, BuildAccumulator::default(), |accumulator, line, tracker| {
+        match line {
+            LogLine::BeginLog(_) => accumulator.clear(),
+            LogLine::PlayerInfo(info) => accumulator.handle_player_info(info, &tracker.session),
+            _ => {}
+        }
+    }, |accumulator, fight, _| accumulator.finish(fight))
+}
@@ -0,0 +1,211 @@
+use std::{collections::{HashMap, VecDeque}, io::{self, BufRead}};
+
+use serde::Serialize;
+
+use crate::{effect::EffectType, event::{Event, EventResult, is_damage_event, is_death_event, is_heal_event}, fights::{Fight, FightHeader, summarise_fights}, line::LogLine, session::{Session, SessionUnit}};
+
+/// How far back a recap looks before a death, in milliseconds
+pub const DEFAULT_RECAP_WINDOW: u64 = 10_000;
+
+/// A hit or heal taken by a player shortly before they died.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct RecapEvent {
+    /// Milliseconds since logging began
+    pub time: u64,
+    pub result: EventResult,
+    pub ability_id: u32,
+    pub ability_name: String,
+    pub source_unit_id: u32,
+    pub source_name: String,
+    pub amount: u32,
+    pub overflow: u32,
+    pub is_heal: bool,
+    /// Health and shield of the player right after the event
+    pub health: u32,
+    pub max_health: u32,
+    pub shield: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct RecapEffect {
+    pub ability_id: u32,
+    pub name: String,
+    pub source_name: String,
+    pub stack_count: u16,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct DeathRecap {
+    pub unit_id: u32,
+    pub name: String,
+    pub display_name: String,
+    /// Milliseconds since logging began
+    pub time: u64,
+    /// The event that killed the player, when the log names one
+    pub killing_blow: Option<RecapEvent>,
+    /// Damage and healing taken in the window before the death, oldest first
+    pub events: Vec<RecapEvent>,
+    /// Debuffs on the player when they died
+    pub debuffs: Vec<RecapEffect>,
+    pub resurrected_at: Option<u64>,
+    pub resurrected_by: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightDeaths {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    /// In the order the players died
+    pub deaths: Vec<DeathRecap>,
+}
+
+/// Collects a recap of every player death, fight by fight.
+/// A player counts as dead from their first death event until they are resurrected, so the several death results the game logs for one death count once.
+/// Resurrections are still taken after a fight ends, as most come after a wipe, until the next BEGIN_COMBAT or BEGIN_LOG.
+#[derive(Debug)]
+pub struct DeathAccumulator {
+    window: u64,
+    recent: HashMap<u32, VecDeque<RecapEvent>>,
+    /// Index into `deaths` of the unresurrected death of each dead player
+    dead: HashMap<u32, usize>,
+    deaths: Vec<DeathRecap>,
+    /// The fight `deaths` belong to once it has ended
+    ended: Option<FightHeader>,
+    fights: Vec<FightDeaths>,
+}
+
+impl Default for DeathAccumulator {
+    fn default() -> Self {
+        Self::new(DEFAULT_RECAP_WINDOW)
+    }
+}
+
+impl DeathAccumulator {
+    /// `window` is how far back each recap looks, in milliseconds.
+    pub fn new(window: u64) -> Self {
+        Self { window, recent: HashMap::new(), dead: HashMap::new(), deaths: Vec::new(), ended: None, fights: Vec::new() }
+    }
+
+    /// Call with each combat event of a fight. Debuffs are read as the death comes in, before the ones that end on death fade.
+    pub fn handle_event(&mut self, event: &Event, session: &Session) {
+        let target = event.target_unit_state.unit_id;
+        let Some(player) = session.player(target) else {return};
+
+        if is_death_event(event.result) {
+            if let Some(&i) = self.dead.get(&target) {
+                let death = &mut self.deaths[i];
+                if death.killing_blow.is_none() && event.ability_id != 0 {
+                    death.killing_blow = Some(recap_event(event, session));
+                }
+                return;
+            }
+            let mut events = self.recent.remove(&target).unwrap_or_default();
+            events.retain(|e| e.time + self.window >= event.time);
+            self.dead.insert(target, self.deaths.len());
+            self.deaths.push(DeathRecap {
+                unit_id: target,
+                name: player.name.clone(),
+                display_name: player.display_name.clone(),
+                time: event.time,
+                killing_blow: (event.ability_id != 0).then(|| recap_event(event, session)),
+                events: Vec::from(events),
+                debuffs: debuffs(target, session),
+                resurrected_at: None,
+                resurrected_by: None,
+            });
+            return;
+        }
+
+        if self.handle_resurrection(event, session) {return}
+
+        let is_damage = is_damage_event(event.result) || matches!(event.result, EventResult::DamageShielded | EventResult::Absorbed);
+        if (!is_damage && !is_heal_event(event.result)) || event.hit_value == 0 {return}
+        if self.dead.contains_key(&target) {return}
+        let recent = self.recent.entry(target).or_default();
+        recent.push_back(recap_event(event, session));
+        while recent.front().is_some_and(|e| e.time + self.window < event.time) {
+            recent.pop_front();
+        }
+    }
+
+    /// Call with each combat event between the end of a fight and the next BEGIN_COMBAT or BEGIN_LOG.
+    /// Returns whether the event was a resurrection.
+    pub fn handle_resurrection(&mut self, event: &Event, session: &Session) -> bool {
+        if !matches!(event.result, EventResult::Resurrect | EventResult::SoulGemResurrectionAccepted) {return false}
+        let target = event.target_unit_state.unit_id;
+        if let Some(i) = self.dead.remove(&target) {
+            let source = event.source_unit_state.unit_id;
+            self.deaths[i].resurrected_at = Some(event.time);
+            self.deaths[i].resurrected_by = (source != 0 && source != target)
+                .then(|| session.unit(source).map(|u| u.name().to_owned()))
+                .flatten();
+        }
+        true
+    }
+
+    /// Call when `fight` ends. Its recaps are kept open for resurrections until [`finalise`](Self::finalise).
+    pub fn finish(&mut self, fight: &Fight) {
+        self.recent.clear();
+        self.ended = Some(FightHeader::from(fight));
+    }
+
+    /// Call on a BEGIN_COMBAT or BEGIN_LOG and at the end of the log, to close the recaps of the fight that ended last.
+    pub fn finalise(&mut self) {
+        self.dead.clear();
+        if let Some(fight) = self.ended.take() {
+            self.fights.push(FightDeaths { fight, deaths: std::mem::take(&mut self.deaths) });
+        }
+    }
+
+    /// The recaps of every finalised fight, in the order the fights ended.
+    pub fn into_fights(self) -> Vec<FightDeaths> {
+        self.fights
+    }
+}
+
+fn recap_event(event: &Event, session: &Session) -> RecapEvent {
+    let source = event.source_unit_state.unit_id;
+    RecapEvent {
+        time: event.time,
+        result: event.result,
+        ability_id: event.ability_id,
+        ability_name: session.ability(event.ability_id).map(|a| a.name.to_string()).unwrap_or_default(),
+        source_unit_id: source,
+        source_name: session.unit(source).map(|u| u.name().to_owned()).unwrap_or_default(),
+        amount: event.hit_value,
+        overflow: event.overflow,
+        is_heal: is_heal_event(event.result),
+        health: event.target_unit_state.health,
+        max_health: event.target_unit_state.max_health,
+        shield: event.target_unit_state.shield,
+    }
+}
+
+fn debuffs(unit_id: u32, session: &Session) -> Vec<RecapEffect> {
+    session.active_effects(unit_id).iter()
+        .filter(|e| session.effect(e.ability_id).is_some_and(|effect| effect.effect_type == EffectType::Debuff))
+        .map(|e| RecapEffect {
+            ability_id: e.ability_id,
+            name: session.ability(e.ability_id).map(|a| a.name.to_string()).unwrap_or_default(),
+            source_name: session.unit(e.source_unit_id).map(SessionUnit::name).unwrap_or_default().to_owned(),
+            stack_count: e.stack_count,
+        })
+        .collect()
+}
+
+/// Recaps every player death in every fight of a log, looking back `window` milliseconds from each death.
+pub fn fight_deaths<R: BufRead>(reader: R, window: u64) -> io::Result<Vec<FightDeaths>> {
+    let mut accumulator = DeathAccumulator::new(window);
+    summarise_fights(reader, &mut accumulator, |accumulator, line, tracker| {
+        match line {
+            LogLine::BeginLog(_) | LogLine::BeginCombat { .. } => accumulator.finalise(),
+            LogLine::CombatEvent(event) if tracker.current_fight().is_some() => accumulator.handle_event(event, &tracker.session),
+            LogLine::CombatEvent(event) => {
+                accumulator.handle_resurrection(event, &tracker.session);
+            }
+            _ => {}
+        }
+    }, |accumulator, fight, _| accumulator.finish(fight))?;
+    accumulator.finalise();
+    Ok(accumulator.into_fights())
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use crate::unit::UnitState;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Ability {
     pub id: u32,
     pub name: Arc<str>,
@@ -13,7 +13,7 @@ pub struct Ability {
     pub scribing: Option<Vec<String>>
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Effect {
     pub ability: Ability,
     pub stack_count: u16,
@@ -22,7 +22,7 @@ pub struct Effect {
     pub synergy: Option<u32>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct EffectEvent {
     pub time: u64,
     pub change_type: EffectChangeType,
@@ -34,7 +34,7 @@ pub struct EffectEvent {
     pub player_initiated_remove_cast_track_id: bool,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum EffectChangeType {
     Faded,
     Gained,
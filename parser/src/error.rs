@@ -0,0 +1,126 @@
+use std::{error::Error, fmt::{self, Display}};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParseErrorKind {
+    MissingField,
+    InvalidValue,
+    UnknownAbility,
+    Other(String),
+}
+
+/// Why a line (or part of one) could not be parsed.
+/// `text` holds the offending field, or the whole line when the field is missing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseError {
+    pub line_number: Option<usize>,
+    pub event_type: String,
+    pub field_index: Option<usize>,
+    pub text: String,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn missing_field(parts: &[&str], index: usize) -> Self {
+        Self {
+            line_number: None,
+            event_type: event_type_of(parts),
+            field_index: Some(index),
+            text: parts.join(","),
+            kind: ParseErrorKind::MissingField,
+        }
+    }
+
+    pub fn invalid_value(parts: &[&str], index: usize) -> Self {
+        Self {
+            line_number: None,
+            event_type: event_type_of(parts),
+            field_index: Some(index),
+            text: parts.get(index).map(|s| s.to_string()).unwrap_or_default(),
+            kind: ParseErrorKind::InvalidValue,
+        }
+    }
+
+    pub fn unknown_ability(parts: &[&str], index: usize) -> Self {
+        Self {
+            kind: ParseErrorKind::UnknownAbility,
+            ..Self::invalid_value(parts, index)
+        }
+    }
+
+    pub fn other(parts: &[&str], message: impl Into<String>) -> Self {
+        Self {
+            line_number: None,
+            event_type: event_type_of(parts),
+            field_index: None,
+            text: parts.join(","),
+            kind: ParseErrorKind::Other(message.into()),
+        }
+    }
+
+    pub fn at_line(mut self, line_number: usize) -> Self {
+        self.line_number = Some(line_number);
+        self
+    }
+}
+
+fn event_type_of(parts: &[&str]) -> String {
+    parts.get(1).map(|s| s.to_string()).unwrap_or_default()
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line_number) = self.line_number {
+            write!(f, "line {line_number}: ")?;
+        }
+        if !self.event_type.is_empty() {
+            write!(f, "{} ", self.event_type)?;
+        }
+        if let Some(index) = self.field_index {
+            write!(f, "field {index} ")?;
+        }
+        match &self.kind {
+            ParseErrorKind::MissingField => write!(f, "is missing in \"{}\"", self.text),
+            ParseErrorKind::InvalidValue => write!(f, "has invalid value \"{}\"", self.text),
+            ParseErrorKind::UnknownAbility => write!(f, "references unknown ability \"{}\"", self.text),
+            ParseErrorKind::Other(message) => write!(f, "{message} in \"{}\"", self.text),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ParseMode {
+    /// Stop at the first line that fails to parse
+    #[default]
+    Strict,
+    /// Skip lines that fail to parse and collect their errors in a [`ParseReport`]
+    Lenient,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParseReport {
+    pub lines: usize,
+    pub errors: Vec<ParseError>,
+}
+
+impl ParseReport {
+    pub fn record(&mut self, error: ParseError) {
+        self.errors.push(error);
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} lines could not be parsed", self.errors.len(), self.lines)?;
+        for error in &self.errors {
+            write!(f, "\n  {error}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use crate::unit::UnitState;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Cast {
     pub time: u64,
     pub duration: u32,
@@ -12,7 +13,7 @@ pub struct Cast {
     pub interrupt_reason: Option<CastEndReason>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Event {
     pub time: u64,
     pub result: EventResult,
@@ -26,7 +27,7 @@ pub struct Event {
     pub target_unit_state: UnitState,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum CastEndReason {
     Completed,
     PlayerCancelled,
@@ -44,7 +45,7 @@ pub fn parse_cast_end_reason(end_reason: &str) -> Option<CastEndReason> {
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone, Eq, Hash)]
+#[derive(Debug, PartialEq, Copy, Clone, Eq, Hash, Serialize, Deserialize)]
 pub enum EventResult {
     AbilityOnCooldown,
     Absorbed,
@@ -288,7 +289,15 @@ pub fn is_heal_event(event_result: EventResult) -> bool {
     matches!(event_result, EventResult::Heal | EventResult::HotTick | EventResult::HotTickCritical | EventResult::CriticalHeal)
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+pub fn is_critical_event(event_result: EventResult) -> bool {
+    matches!(event_result, EventResult::CriticalDamage | EventResult::DotTickCritical | EventResult::CriticalHeal | EventResult::HotTickCritical)
+}
+
+pub fn is_death_event(event_result: EventResult) -> bool {
+    matches!(event_result, EventResult::Died | EventResult::DiedXP | EventResult::DiedCompanionXP | EventResult::KillingBlow | EventResult::KilledBySubzone)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum DamageType {
     Bleed,
     Cold,
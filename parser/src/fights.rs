@@ -0,0 +1,290 @@
+use std::{collections::{HashMap, HashSet}, io::{self, BufRead}};
+
+use crate::{event::{EventResult, is_death_event}, line::{LogLine, for_each_line}, session::{Session, SessionUnit}, zone::ZONE_TO_NAME};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum FightOutcome {
+    Kill,
+    Wipe,
+    /// No boss took part in the fight
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FightBoss {
+    pub unit_id: u32,
+    pub monster_id: u32,
+    pub name: String,
+    pub died: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FightPlayer {
+    pub unit_id: u32,
+    pub name: String,
+    pub display_name: String,
+    pub deaths: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Fight {
+    /// Milliseconds since logging began
+    pub start_time: u64,
+    pub end_time: u64,
+    /// Unix time in milliseconds of the BEGIN_LOG this fight belongs to
+    pub log_start_time: Option<u64>,
+    /// Byte offset of the BEGIN_COMBAT line
+    pub start_offset: u64,
+    /// Byte offset just past the END_COMBAT line
+    pub end_offset: u64,
+    pub zone_id: Option<u16>,
+    pub zone_name: Option<String>,
+    pub bosses: Vec<FightBoss>,
+    pub players: Vec<FightPlayer>,
+    pub outcome: FightOutcome,
+    pub trial_id: Option<u32>,
+    pub trial_score: Option<u32>,
+}
+
+impl Fight {
+    pub fn duration(&self) -> u64 {
+        self.end_time.saturating_sub(self.start_time)
+    }
+
+    pub fn unix_start_time(&self) -> Option<u64> {
+        self.log_start_time.map(|t| t + self.start_time)
+    }
+
+    pub fn unix_end_time(&self) -> Option<u64> {
+        self.log_start_time.map(|t| t + self.end_time)
+    }
+
+    /// Name of the first boss, or `None` for trash fights
+    pub fn boss_name(&self) -> Option<&str> {
+        self.bosses.first().map(|b| b.name.as_str())
+    }
+}
+
+/// The fight a per-fight summary covers, shared by the summaries of every analysis.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FightHeader {
+    /// Milliseconds since logging began
+    pub start_time: u64,
+    pub end_time: u64,
+    pub zone_name: Option<String>,
+    pub boss_name: Option<String>,
+}
+
+impl FightHeader {
+    pub fn duration(&self) -> u64 {
+        self.end_time.saturating_sub(self.start_time)
+    }
+
+    /// `Fight <number> - <zone> - <boss>`, numbered as in [`find_fights`]
+    pub fn title(&self, number: usize) -> String {
+        format!(
+            "Fight {number} - {} - {}",
+            self.zone_name.as_deref().unwrap_or("Unknown zone"),
+            self.boss_name.as_deref().unwrap_or("Trash"),
+        )
+    }
+}
+
+impl From<&Fight> for FightHeader {
+    fn from(fight: &Fight) -> Self {
+        Self {
+            start_time: fight.start_time,
+            end_time: fight.end_time,
+            zone_name: fight.zone_name.clone(),
+            boss_name: fight.boss_name().map(str::to_owned),
+        }
+    }
+}
+
+/// Splits a log into fights as lines are fed to it.
+/// A fight runs from BEGIN_COMBAT to END_COMBAT; a fight left open by a new BEGIN_LOG or the end of the file is closed at the last line seen.
+#[derive(Debug, Default)]
+pub struct FightTracker {
+    pub session: Session,
+    pub fights: Vec<Fight>,
+    current: Option<Fight>,
+    /// Units of the current fight that died and have not been resurrected, as the game logs several death results for one death
+    dead: HashSet<u32>,
+    last_offset: u64,
+}
+
+impl FightTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `offset` is the byte offset of the start of `line`, `length` its length including the line ending.
+    pub fn handle_line(&mut self, line: &LogLine, offset: u64, length: u64) {
+        if matches!(line, LogLine::BeginLog(_)) {
+            self.close_fight(self.session.time, offset);
+        }
+        self.session.apply(line);
+        let end_offset = offset + length;
+        self.last_offset = end_offset;
+
+        match line {
+            LogLine::BeginCombat { time } => {
+                self.close_fight(*time, offset);
+                self.current = Some(Fight {
+                    start_time: *time,
+                    end_time: *time,
+                    log_start_time: self.session.log_start_time,
+                    start_offset: offset,
+                    end_offset,
+                    zone_id: self.session.zone.as_ref().map(|z| z.id),
+                    zone_name: self.session.zone.as_ref().map(|z| {
+                        ZONE_TO_NAME.get(&z.id).map(|n| n.to_string()).unwrap_or_else(|| z.name.clone())
+                    }),
+                    bosses: Vec::new(),
+                    players: Vec::new(),
+                    outcome: FightOutcome::Unknown,
+                    trial_id: None,
+                    trial_score: None,
+                });
+            }
+            LogLine::EndCombat { time } => self.close_fight(*time, end_offset),
+            LogLine::CombatEvent(event) => {
+                if self.current.is_none() {return}
+                self.add_participant(event.source_unit_state.unit_id);
+                self.add_participant(event.target_unit_state.unit_id);
+                if is_death_event(event.result) {
+                    self.record_death(event.target_unit_state.unit_id);
+                } else if matches!(event.result, EventResult::Resurrect | EventResult::SoulGemResurrectionAccepted) {
+                    self.dead.remove(&event.target_unit_state.unit_id);
+                }
+            }
+            LogLine::EndTrial(end) => {
+                let fight = match self.current.as_mut() {
+                    Some(fight) => Some(fight),
+                    None => self.fights.last_mut().filter(|f| f.log_start_time == self.session.log_start_time),
+                };
+                if let Some(fight) = fight {
+                    fight.trial_id = Some(end.trial_id);
+                    fight.trial_score = Some(end.final_score);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn current_fight(&self) -> Option<&Fight> {
+        self.current.as_ref()
+    }
+
+    /// Closes a fight left open at the end of the input.
+    pub fn close_open_fight(&mut self) {
+        self.close_fight(self.session.time, self.last_offset);
+    }
+
+    /// Closes a fight left open at the end of the input and returns all fights found.
+    pub fn finish(mut self) -> Vec<Fight> {
+        self.close_open_fight();
+        self.fights
+    }
+
+    fn close_fight(&mut self, end_time: u64, end_offset: u64) {
+        let Some(mut fight) = self.current.take() else {return};
+        self.dead.clear();
+        fight.end_time = end_time;
+        fight.end_offset = end_offset;
+        fight.outcome = if fight.bosses.is_empty() {
+            FightOutcome::Unknown
+        } else if fight.bosses.iter().all(|b| b.died) {
+            FightOutcome::Kill
+        } else {
+            FightOutcome::Wipe
+        };
+        self.fights.push(fight);
+    }
+
+    fn add_participant(&mut self, unit_id: u32) {
+        let Some(fight) = self.current.as_mut() else {return};
+        match self.session.unit(unit_id) {
+            Some(SessionUnit::Player(p)) if !fight.players.iter().any(|f| f.unit_id == unit_id) => {
+                fight.players.push(FightPlayer {
+                    unit_id,
+                    name: p.name.clone(),
+                    display_name: p.display_name.clone(),
+                    deaths: 0,
+                });
+            }
+            Some(SessionUnit::Other(u)) if u.is_boss && !fight.bosses.iter().any(|b| b.unit_id == unit_id) => {
+                fight.bosses.push(FightBoss {
+                    unit_id,
+                    monster_id: u.monster_id,
+                    name: u.name.clone(),
+                    died: false,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn record_death(&mut self, unit_id: u32) {
+        let Some(fight) = self.current.as_mut() else {return};
+        if !self.dead.insert(unit_id) {return}
+        if let Some(boss) = fight.bosses.iter_mut().find(|b| b.unit_id == unit_id) {
+            boss.died = true;
+        } else if let Some(player) = fight.players.iter_mut().find(|p| p.unit_id == unit_id) {
+            player.deaths += 1;
+        }
+    }
+}
+
+/// Reads a whole Encounter.log and returns every fight in it.
+/// Lines that fail to parse (such as a line truncated by a game crash) are skipped.
+pub fn find_fights<R: BufRead>(reader: R) -> io::Result<Vec<Fight>> {
+    let mut tracker = FightTracker::new();
+    for_each_line(reader, |line, offset, length| tracker.handle_line(line, offset, length))?;
+    Ok(tracker.finish())
+}
+
+/// Reads a whole log and sums up every fight in it, the way every per-fight analysis does.
+/// `on_line` is given each line after the tracker has applied it, alongside `state`, and `on_fight` each fight as it ends,
+/// including a fight left open at the end of the input. A fight closed by a BEGIN_LOG is given to `on_fight` before the session is reset. Lines that fail to parse are skipped.
+pub fn summarise_fights<R, S, T>(
+    reader: R,
+    mut state: S,
+    mut on_line: impl FnMut(&mut S, &LogLine, &FightTracker),
+    mut on_fight: impl FnMut(&mut S, &Fight, &Session) -> T,
+) -> io::Result<Vec<T>> where R: BufRead {
+    let mut tracker = FightTracker::new();
+    let mut summaries = Vec::new();
+    let mut summarise = |tracker: &FightTracker, state: &mut S, summaries: &mut Vec<T>| {
+        for fight in &tracker.fights[summaries.len()..] {
+            summaries.push(on_fight(state, fight, &tracker.session));
+        }
+    };
+
+    for_each_line(reader, |line, offset, length| {
+        // A fight left open by a new BEGIN_LOG is summed up before the BEGIN_LOG clears the session it refers to
+        if matches!(line, LogLine::BeginLog(_)) {
+            tracker.close_fight(tracker.session.time, offset);
+            summarise(&tracker, &mut state, &mut summaries);
+        }
+        tracker.handle_line(line, offset, length);
+        summarise(&tracker, &mut state, &mut summaries);
+        on_line(&mut state, line, &tracker);
+    })?;
+
+    tracker.close_open_fight();
+    summarise(&tracker, &mut state, &mut summaries);
+    Ok(summaries)
+}
+
+/// Groups fights by boss name, skipping trash fights. Useful for listing every attempt on a boss.
+pub fn fights_by_boss(fights: &[Fight]) -> HashMap<&str, Vec<&Fight>> {
+    let mut by_boss: HashMap<&str, Vec<&Fight>> = HashMap::new();
+    for fight in fights {
+        if let Some(name) = fight.boss_name() {
+            by_boss.entry(name).or_default().push(fight);
+        }
+    }
+    by_boss
+}
@@ -0,0 +1,477 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::EventType;
+
+/// First line of every index file, followed by a hash of the log's first line. The number is the format version.
+const INDEX_HEADER: &str = "ESO_LOG_INDEX,2";
+/// Marks how much of the log the entries above it cover. Entries after the last checkpoint were cut off and are ignored.
+const CHECKPOINT: &str = "INDEXED";
+/// Starts a line of the state a fight began in, written after the fight's BEGIN_COMBAT entry
+const STATE: &str = "STATE";
+
+/// One indexed line of a log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    /// Byte offset of the start of the line
+    pub offset: u64,
+    /// Length of the line including its line ending
+    pub length: u64,
+    /// Milliseconds since logging began
+    pub time: u64,
+    pub event_type: EventType,
+    /// Unit, ability, zone, map or trial id depending on the line, the Unix time for a BEGIN_LOG and 0 for combat lines
+    pub id: u64,
+}
+
+/// Whether lines of this type are kept in the index: the boundaries of logs and fights, and every line that defines
+/// the zone, units or abilities later lines refer to.
+pub fn is_indexed(event_type: EventType) -> bool {
+    matches!(
+        event_type,
+        EventType::BeginLog | EventType::EndLog | EventType::BeginCombat | EventType::EndCombat
+            | EventType::ZoneChanged | EventType::MapChanged | EventType::TrialInit | EventType::BeginTrial | EventType::EndTrial
+            | EventType::UnitAdded | EventType::UnitChanged | EventType::UnitRemoved | EventType::PlayerInfo
+            | EventType::AbilityInfo | EventType::EffectInfo
+    )
+}
+
+/// A fight as found in the index, numbered the same way as [`crate::fights::find_fights`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexedFight {
+    /// Unix time in milliseconds of the BEGIN_LOG the fight belongs to
+    pub log_start_time: Option<u64>,
+    pub start_time: u64,
+    /// Time of the END_COMBAT, or of the last indexed line for a fight that never ended
+    pub end_time: u64,
+    /// Offset of the BEGIN_COMBAT line
+    pub start_offset: u64,
+    /// Offset just past the last line of the fight
+    pub end_offset: u64,
+}
+
+/// Byte offsets of the lines needed to find and decode any fight in a log without reading the whole file.
+///
+/// Stored next to the log as `<log>.idx` and brought up to date by reading only what was appended since the last update.
+/// The index is rebuilt when the log's first line changes or the log shrinks, as happens when the game starts a new Encounter.log.
+#[derive(Debug, Default)]
+pub struct LogIndex {
+    pub entries: Vec<IndexEntry>,
+    /// Bytes of the log covered by the entries
+    pub indexed_length: u64,
+    /// Keyed by the offset of each BEGIN_COMBAT, the lines before it that give the effects active and the state of each player when the fight began
+    fight_states: HashMap<u64, Vec<IndexEntry>>,
+    /// The state at the end of the indexed lines, rebuilt by the first update after loading
+    state: Option<SessionState>,
+    first_line_hash: u64,
+    /// Length of the index file up to its last checkpoint, 0 if it has to be written from scratch
+    saved_length: u64,
+    saved_entries: usize,
+}
+
+impl LogIndex {
+    /// `Encounter.log` -> `Encounter.log.idx`
+    pub fn path_for(log_path: &Path) -> PathBuf {
+        let mut path = log_path.as_os_str().to_os_string();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    /// Loads the index next to `log_path`, updates it with any lines added to the log since and saves it.
+    pub fn open(log_path: &Path) -> io::Result<Self> {
+        let index_path = Self::path_for(log_path);
+        let mut index = Self::load(&index_path).unwrap_or_default();
+        if index.update(log_path)? || index.saved_length == 0 {
+            index.save(&index_path)?;
+        }
+        Ok(index)
+    }
+
+    /// Reads an index file, or `None` if it is missing or not in the current format.
+    fn load(index_path: &Path) -> Option<Self> {
+        let mut reader = BufReader::new(File::open(index_path).ok()?);
+        let mut index = Self::default();
+        let mut buffer = String::new();
+        let mut position = 0u64;
+
+        loop {
+            buffer.clear();
+            let length = reader.read_line(&mut buffer).ok()? as u64;
+            if length == 0 || !buffer.ends_with('\n') {break}
+            position += length;
+            let line = buffer.trim_end();
+
+            if position == length {
+                let hash = line.strip_prefix(INDEX_HEADER)?.strip_prefix(',')?;
+                index.first_line_hash = hash.parse().ok()?;
+            } else if let Some(indexed_length) = line.strip_prefix(CHECKPOINT).and_then(|l| l.strip_prefix(',')) {
+                index.indexed_length = indexed_length.parse().ok()?;
+                index.saved_length = position;
+                index.saved_entries = index.entries.len();
+            } else if let Some(state) = line.strip_prefix(STATE).and_then(|l| l.strip_prefix(',')) {
+                let fight_offset = index.entries.last().filter(|e| e.event_type == EventType::BeginCombat)?.offset;
+                let mut fields = state.split(',');
+                let mut number = || fields.next()?.parse::<u64>().ok();
+                let (offset, length, time) = (number()?, number()?, number()?);
+                let event_type = EventType::from(fields.next()?);
+                index.fight_states.entry(fight_offset).or_default().push(IndexEntry { offset, length, time, event_type, id: 0 });
+            } else {
+                let mut fields = line.split(',');
+                let mut number = || fields.next()?.parse::<u64>().ok();
+                let (offset, length, time) = (number()?, number()?, number()?);
+                let event_type = EventType::from(fields.next()?);
+                let id = fields.next()?.parse().ok()?;
+                index.entries.push(IndexEntry { offset, length, time, event_type, id });
+            }
+        }
+
+        if index.saved_length == 0 {
+            return None;
+        }
+        index.entries.truncate(index.saved_entries);
+        let indexed_length = index.indexed_length;
+        index.fight_states.retain(|offset, _| *offset < indexed_length);
+        Some(index)
+    }
+
+    /// Indexes the lines added to the log since the last update, or the whole log if it is not the one that was indexed.
+    /// A last line without a line ending is left for the next update, since the game may still be writing it.
+    /// The first update after loading the index reads the log again from its last BEGIN_COMBAT or BEGIN_LOG, to pick up the state there.
+    /// Returns whether anything changed.
+    pub fn update(&mut self, log_path: &Path) -> io::Result<bool> {
+        let mut reader = BufReader::new(File::open(log_path)?);
+        let mut first_line = Vec::new();
+        reader.read_until(b'\n', &mut first_line)?;
+        let first_line_hash = hash(&first_line);
+        let log_length = reader.get_ref().metadata()?.len();
+
+        if first_line_hash != self.first_line_hash || log_length < self.indexed_length {
+            *self = Self { first_line_hash, ..Self::default() };
+        }
+        if log_length == self.indexed_length {
+            return Ok(false);
+        }
+
+        let mut state = match self.state.take() {
+            Some(state) => state,
+            None => self.resume_state(&mut reader)?,
+        };
+        reader.seek(SeekFrom::Start(self.indexed_length))?;
+        let mut buffer = Vec::new();
+        let mut changed = false;
+        loop {
+            buffer.clear();
+            let length = reader.read_until(b'\n', &mut buffer)? as u64;
+            if length == 0 || buffer.last() != Some(&b'\n') {break}
+            if let Some(entry) = index_entry(&buffer, self.indexed_length, length) {
+                if entry.event_type == EventType::BeginCombat {
+                    self.fight_states.insert(entry.offset, state.lines());
+                }
+                if is_indexed(entry.event_type) {
+                    self.entries.push(entry);
+                }
+                state.add(&buffer, entry);
+            }
+            self.indexed_length += length;
+            changed = true;
+        }
+        self.state = Some(state);
+        Ok(changed)
+    }
+
+    /// Rebuilds the state at the end of the indexed lines from the state at the last BEGIN_COMBAT, or from the last BEGIN_LOG,
+    /// and the lines after it.
+    fn resume_state<R: BufRead + Seek>(&self, log: &mut R) -> io::Result<SessionState> {
+        let start = self.entries.iter()
+            .rfind(|e| matches!(e.event_type, EventType::BeginCombat | EventType::BeginLog))
+            .map_or(0, |e| e.offset);
+        let mut state = SessionState::default();
+        for entry in self.context(start) {
+            state.add(read_line_at(log, entry.offset, entry.length)?.as_bytes(), *entry);
+        }
+
+        log.seek(SeekFrom::Start(start))?;
+        let mut buffer = Vec::new();
+        let mut offset = start;
+        while offset < self.indexed_length {
+            buffer.clear();
+            let length = log.read_until(b'\n', &mut buffer)? as u64;
+            if length == 0 {break}
+            if let Some(entry) = index_entry(&buffer, offset, length) {
+                state.add(&buffer, entry);
+            }
+            offset += length;
+        }
+        Ok(state)
+    }
+
+    /// Appends the entries added since the last save to the index file, or writes it anew if it can't be appended to.
+    pub fn save(&mut self, index_path: &Path) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).write(true).truncate(false).open(index_path)?;
+        if self.saved_length == 0 {
+            self.saved_entries = 0;
+        }
+        file.set_len(self.saved_length)?;
+        let mut writer = BufWriter::new(file);
+        writer.seek(SeekFrom::End(0))?;
+
+        if self.saved_length == 0 {
+            writeln!(writer, "{INDEX_HEADER},{}", self.first_line_hash)?;
+        }
+        for entry in &self.entries[self.saved_entries..] {
+            writeln!(writer, "{},{},{},{},{}", entry.offset, entry.length, entry.time, entry.event_type.as_str(), entry.id)?;
+            for line in self.fight_states.get(&entry.offset).into_iter().flatten() {
+                writeln!(writer, "{STATE},{},{},{},{}", line.offset, line.length, line.time, line.event_type.as_str())?;
+            }
+        }
+        writeln!(writer, "{CHECKPOINT},{}", self.indexed_length)?;
+        writer.flush()?;
+
+        self.saved_length = writer.stream_position()?;
+        self.saved_entries = self.entries.len();
+        Ok(())
+    }
+
+    /// The latest BEGIN_LOG, where reading has to start to follow the log as it is written.
+    pub fn last_begin_log(&self) -> Option<&IndexEntry> {
+        self.entries.iter().rev().find(|e| e.event_type == EventType::BeginLog)
+    }
+
+    /// Every fight in the log. Like [`crate::fights::FightTracker`], a fight left open by a new BEGIN_COMBAT or BEGIN_LOG ends where that line starts.
+    pub fn fights(&self) -> Vec<IndexedFight> {
+        let mut fights = Vec::new();
+        let mut current: Option<IndexedFight> = None;
+        let mut log_start_time = None;
+        let mut last_time = 0;
+
+        for entry in &self.entries {
+            match entry.event_type {
+                EventType::BeginLog => {
+                    fights.extend(current.take().map(|f| IndexedFight { end_time: last_time, end_offset: entry.offset, ..f }));
+                    log_start_time = Some(entry.id);
+                }
+                EventType::BeginCombat => {
+                    fights.extend(current.take().map(|f| IndexedFight { end_time: entry.time, end_offset: entry.offset, ..f }));
+                    current = Some(IndexedFight {
+                        log_start_time,
+                        start_time: entry.time,
+                        end_time: entry.time,
+                        start_offset: entry.offset,
+                        end_offset: entry.offset + entry.length,
+                    });
+                }
+                EventType::EndCombat => {
+                    fights.extend(current.take().map(|f| IndexedFight { end_time: entry.time, end_offset: entry.offset + entry.length, ..f }));
+                }
+                _ => {}
+            }
+            last_time = entry.time;
+        }
+        fights.extend(current.map(|f| IndexedFight { end_time: last_time, end_offset: self.indexed_length, ..f }));
+        fights
+    }
+
+    /// Offset of the first BEGIN_COMBAT or BEGIN_LOG from the end of `fight` on, or the end of the indexed log.
+    /// What comes in between, such as resurrections after a wipe, still belongs to the fight.
+    pub fn aftermath_end(&self, fight: &IndexedFight) -> u64 {
+        self.entries[self.entries.partition_point(|e| e.offset < fight.end_offset)..].iter()
+            .find(|e| matches!(e.event_type, EventType::BeginCombat | EventType::BeginLog))
+            .map_or(self.indexed_length, |e| e.offset)
+    }
+
+    /// The lines to read before `offset` so that the log can be followed from there, as [`LogContext`] describes them.
+    /// When `offset` is that of a BEGIN_COMBAT, they end with the lines that give the effects active and the state of each player
+    /// when the fight began, in the order of the log.
+    pub fn context(&self, offset: u64) -> Vec<&IndexEntry> {
+        let mut context = LogContext::new();
+        for entry in &self.entries[..self.entries.partition_point(|e| e.offset < offset)] {
+            context.add(entry.event_type, entry.id, entry);
+        }
+        let mut lines: Vec<&IndexEntry> = context.lines().into_iter().copied().collect();
+        lines.extend(self.fight_states.get(&offset).into_iter().flatten());
+        lines
+    }
+
+    /// Reads the lines of the [`context`](Self::context) of `offset`, without line endings.
+    pub fn read_context<R: Read + Seek>(&self, log: &mut R, offset: u64) -> io::Result<Vec<String>> {
+        self.context(offset).into_iter().map(|entry| read_line_at(log, entry.offset, entry.length)).collect()
+    }
+
+    /// Reads the lines of `fight` preceded by its [`context`](Self::context), without line endings.
+    /// Fed to a [`crate::session::Session`] or [`crate::fights::FightTracker`], they give the same result for the fight as the whole log would.
+    pub fn read_fight<R: Read + Seek>(&self, log: &mut R, fight: &IndexedFight) -> io::Result<Vec<String>> {
+        let mut lines = self.read_context(log, fight.start_offset)?;
+        let fight_lines = read_line_at(log, fight.start_offset, fight.end_offset - fight.start_offset)?;
+        lines.extend(fight_lines.lines().map(str::to_owned));
+        Ok(lines)
+    }
+}
+
+/// The lines needed to follow a log from some point onwards, kept up to date as the lines before that point are read:
+/// the BEGIN_LOG, the zone and map, the units still present and the latest definition of every ability, including those defined in earlier logs.
+#[derive(Debug, Clone)]
+pub struct LogContext<T> {
+    begin_log: Option<T>,
+    /// Keyed by line type and id, alongside the position of the line so that the log's order can be kept
+    latest: HashMap<(EventType, u64), (usize, T)>,
+    count: usize,
+}
+
+impl<T> Default for LogContext<T> {
+    fn default() -> Self {
+        Self { begin_log: None, latest: HashMap::new(), count: 0 }
+    }
+}
+
+impl<T> LogContext<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes note of the next line of the log, with `id` as [`IndexEntry::id`] gives it. Lines that are not part of any context are ignored.
+    pub fn add(&mut self, event_type: EventType, id: u64, line: T) {
+        let position = self.count;
+        self.count += 1;
+        match event_type {
+            EventType::BeginLog => {
+                self.latest.retain(|(event_type, _), _| matches!(event_type, EventType::AbilityInfo | EventType::EffectInfo));
+                self.begin_log = Some(line);
+            }
+            EventType::ZoneChanged | EventType::MapChanged | EventType::TrialInit | EventType::BeginTrial => {
+                self.latest.insert((event_type, 0), (position, line));
+            }
+            EventType::UnitAdded | EventType::UnitRemoved => {
+                for event_type in [EventType::UnitAdded, EventType::UnitChanged, EventType::PlayerInfo] {
+                    self.latest.remove(&(event_type, id));
+                }
+                if event_type == EventType::UnitAdded {
+                    self.latest.insert((event_type, id), (position, line));
+                }
+            }
+            EventType::UnitChanged | EventType::PlayerInfo | EventType::AbilityInfo | EventType::EffectInfo => {
+                self.latest.insert((event_type, id), (position, line));
+            }
+            _ => {}
+        }
+    }
+
+    /// The lines of the context, starting with the BEGIN_LOG. Ability definitions come next, since effect definitions refer to them;
+    /// everything else keeps the order of the log.
+    pub fn lines(&self) -> Vec<&T> {
+        let mut lines: Vec<(bool, usize, &T)> = self.latest.iter()
+            .map(|((event_type, _), (position, line))| (*event_type != EventType::AbilityInfo, *position, line))
+            .collect();
+        lines.sort_by_key(|(not_ability, position, _)| (*not_ability, *position));
+        self.begin_log.iter().chain(lines.into_iter().map(|(_, _, line)| line)).collect()
+    }
+}
+
+fn read_line_at<R: Read + Seek>(log: &mut R, offset: u64, length: u64) -> io::Result<String> {
+    let mut buffer = vec![0; length as usize];
+    log.seek(SeekFrom::Start(offset))?;
+    log.read_exact(&mut buffer)?;
+    let text = String::from_utf8_lossy(&buffer);
+    Ok(text.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/// Reads the time, type and, for indexed lines, the id of a line without parsing the rest of it.
+fn index_entry(line: &[u8], offset: u64, length: u64) -> Option<IndexEntry> {
+    let mut fields = line.splitn(4, |&b| b == b',');
+    let time = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    let event_type = EventType::from(std::str::from_utf8(fields.next()?).ok()?.trim_end());
+    let id = fields.next()
+        .filter(|_| is_indexed(event_type))
+        .and_then(|f| std::str::from_utf8(f).ok())
+        .and_then(|f| f.trim_end().parse().ok())
+        .unwrap_or(0);
+    Some(IndexEntry { offset, length, time, event_type, id })
+}
+
+/// The lines a fight's state depends on, kept up to date as the log is indexed: the latest EFFECT_CHANGED line of every active effect
+/// and the latest line carrying the state of each player. Lines are split only as far as the unit and ability ids.
+#[derive(Debug, Default)]
+struct SessionState {
+    players: HashSet<u32>,
+    /// Keyed by target, source and ability, as the session keeps active effects
+    effects: HashMap<(u32, u32, u32), IndexEntry>,
+    unit_states: HashMap<u32, IndexEntry>,
+}
+
+impl SessionState {
+    fn add(&mut self, line: &[u8], entry: IndexEntry) {
+        if !matches!(
+            entry.event_type,
+            EventType::BeginLog | EventType::UnitAdded | EventType::UnitRemoved
+                | EventType::EffectChanged | EventType::CombatEvent | EventType::BeginCast | EventType::HealthRegen
+        ) {
+            return;
+        }
+        let fields: Vec<&[u8]> = line.splitn(21, |&b| b == b',').collect();
+        let number = |i: usize| fields.get(i).and_then(|f| std::str::from_utf8(f).ok()).and_then(|f| f.trim_end().parse::<u32>().ok());
+        // A target of `*` is the source itself
+        let units = |source: usize, target: usize| {
+            let source = number(source).unwrap_or(0);
+            (source, number(target).unwrap_or(source))
+        };
+        let entry = IndexEntry { id: 0, ..entry };
+
+        match entry.event_type {
+            EventType::BeginLog => *self = Self::default(),
+            EventType::UnitAdded => {
+                if let Some(unit_id) = number(2) && fields.get(3) == Some(&&b"PLAYER"[..]) {
+                    self.players.insert(unit_id);
+                }
+            }
+            EventType::UnitRemoved => {
+                let unit_id = number(2).unwrap_or(0);
+                self.players.remove(&unit_id);
+                self.unit_states.remove(&unit_id);
+                self.effects.retain(|(target, _, _), _| *target != unit_id);
+            }
+            EventType::EffectChanged => {
+                let (source, target) = units(6, 16);
+                let key = (target, source, number(5).unwrap_or(0));
+                if fields.get(2) == Some(&&b"FADED"[..]) {
+                    self.effects.remove(&key);
+                } else {
+                    self.effects.insert(key, entry);
+                }
+                self.unit_state(source, entry);
+                self.unit_state(target, entry);
+            }
+            EventType::CombatEvent => {
+                let (source, target) = units(9, 19);
+                self.unit_state(source, entry);
+                self.unit_state(target, entry);
+            }
+            EventType::BeginCast => {
+                let (source, target) = units(6, 16);
+                self.unit_state(source, entry);
+                self.unit_state(target, entry);
+            }
+            EventType::HealthRegen => self.unit_state(number(3).unwrap_or(0), entry),
+            _ => {}
+        }
+    }
+
+    fn unit_state(&mut self, unit_id: u32, entry: IndexEntry) {
+        if self.players.contains(&unit_id) {
+            self.unit_states.insert(unit_id, entry);
+        }
+    }
+
+    /// The lines to replay to reach this state, in the order of the log.
+    fn lines(&self) -> Vec<IndexEntry> {
+        let mut lines: Vec<IndexEntry> = self.effects.values().chain(self.unit_states.values()).copied().collect();
+        lines.sort_by_key(|e| e.offset);
+        lines.dedup_by_key(|e| e.offset);
+        lines
+    }
+}
+
+/// 64 bit FNV-1a, which unlike the standard library's hasher gives the same value on every run and platform.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
@@ -6,10 +6,28 @@ pub mod unit;
 pub mod parse;
 pub mod zone;
 pub mod subclassing;
+pub mod line;
+pub mod error;
+pub mod session;
+pub mod fights;
+pub mod meter;
+pub mod uptime;
+pub mod death;
+pub mod rotation;
+pub mod resource;
+pub mod position;
+pub mod phase;
+pub mod trial;
+pub mod build;
+pub mod index;
+pub mod validate;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum EventType {
     BeginLog,
+    EndLog,
     EndCombat,
     BeginCombat,
     UnitAdded,
@@ -26,34 +44,87 @@ pub enum EventType {
     UnitChanged,
     EndCast,
     UnitRemoved,
+    TrialInit,
+    BeginTrial,
+    EndlessDungeonInit,
+    EndlessDungeonBegin,
+    EndlessDungeonEnd,
+    EndlessDungeonStageEnd,
+    EndlessDungeonBuffAdded,
+    EndlessDungeonBuffRemoved,
     Unknown,
 }
 
 impl From<&str> for EventType {
     fn from(s: &str) -> Self {
         match s {
-            "BEGIN_LOG"      => EventType::BeginLog,
-            "END_COMBAT"     => EventType::EndCombat,
-            "BEGIN_COMBAT"   => EventType::BeginCombat,
-            "UNIT_ADDED"     => EventType::UnitAdded,
-            "PLAYER_INFO"    => EventType::PlayerInfo,
-            "ABILITY_INFO"   => EventType::AbilityInfo,
-            "COMBAT_EVENT"   => EventType::CombatEvent,
-            "BEGIN_CAST"     => EventType::BeginCast,
-            "EFFECT_CHANGED" => EventType::EffectChanged,
-            "MAP_CHANGED"    => EventType::MapChanged,
-            "ZONE_CHANGED"   => EventType::ZoneChanged,
-            "END_TRIAL"      => EventType::EndTrial,
-            "HEALTH_REGEN"   => EventType::HealthRegen,
-            "EFFECT_INFO"    => EventType::EffectInfo,
-            "UNIT_CHANGED"   => EventType::UnitChanged,
-            "END_CAST"       => EventType::EndCast,
-            "UNIT_REMOVED"   => EventType::UnitRemoved,
-            _                => EventType::Unknown,
+            "BEGIN_LOG"                    => EventType::BeginLog,
+            "END_LOG"                      => EventType::EndLog,
+            "END_COMBAT"                   => EventType::EndCombat,
+            "BEGIN_COMBAT"                 => EventType::BeginCombat,
+            "UNIT_ADDED"                   => EventType::UnitAdded,
+            "PLAYER_INFO"                  => EventType::PlayerInfo,
+            "ABILITY_INFO"                 => EventType::AbilityInfo,
+            "COMBAT_EVENT"                 => EventType::CombatEvent,
+            "BEGIN_CAST"                   => EventType::BeginCast,
+            "EFFECT_CHANGED"               => EventType::EffectChanged,
+            "MAP_CHANGED"                  => EventType::MapChanged,
+            "ZONE_CHANGED"                 => EventType::ZoneChanged,
+            "END_TRIAL"                    => EventType::EndTrial,
+            "HEALTH_REGEN"                 => EventType::HealthRegen,
+            "EFFECT_INFO"                  => EventType::EffectInfo,
+            "UNIT_CHANGED"                 => EventType::UnitChanged,
+            "END_CAST"                     => EventType::EndCast,
+            "UNIT_REMOVED"                 => EventType::UnitRemoved,
+            "TRIAL_INIT"                   => EventType::TrialInit,
+            "BEGIN_TRIAL"                  => EventType::BeginTrial,
+            "ENDLESS_DUNGEON_INIT"         => EventType::EndlessDungeonInit,
+            "ENDLESS_DUNGEON_BEGIN"        => EventType::EndlessDungeonBegin,
+            "ENDLESS_DUNGEON_END"          => EventType::EndlessDungeonEnd,
+            "ENDLESS_DUNGEON_STAGE_END"    => EventType::EndlessDungeonStageEnd,
+            "ENDLESS_DUNGEON_BUFF_ADDED"   => EventType::EndlessDungeonBuffAdded,
+            "ENDLESS_DUNGEON_BUFF_REMOVED" => EventType::EndlessDungeonBuffRemoved,
+            _                              => EventType::Unknown,
+        }
+    }
+}
+
+impl EventType {
+    /// The line type as written in the log, `UNKNOWN` for [`EventType::Unknown`]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventType::BeginLog                  => "BEGIN_LOG",
+            EventType::EndLog                    => "END_LOG",
+            EventType::EndCombat                 => "END_COMBAT",
+            EventType::BeginCombat               => "BEGIN_COMBAT",
+            EventType::UnitAdded                 => "UNIT_ADDED",
+            EventType::PlayerInfo                => "PLAYER_INFO",
+            EventType::AbilityInfo               => "ABILITY_INFO",
+            EventType::CombatEvent               => "COMBAT_EVENT",
+            EventType::BeginCast                 => "BEGIN_CAST",
+            EventType::EffectChanged             => "EFFECT_CHANGED",
+            EventType::MapChanged                => "MAP_CHANGED",
+            EventType::ZoneChanged               => "ZONE_CHANGED",
+            EventType::EndTrial                  => "END_TRIAL",
+            EventType::HealthRegen               => "HEALTH_REGEN",
+            EventType::EffectInfo                => "EFFECT_INFO",
+            EventType::UnitChanged               => "UNIT_CHANGED",
+            EventType::EndCast                   => "END_CAST",
+            EventType::UnitRemoved               => "UNIT_REMOVED",
+            EventType::TrialInit                 => "TRIAL_INIT",
+            EventType::BeginTrial                => "BEGIN_TRIAL",
+            EventType::EndlessDungeonInit        => "ENDLESS_DUNGEON_INIT",
+            EventType::EndlessDungeonBegin       => "ENDLESS_DUNGEON_BEGIN",
+            EventType::EndlessDungeonEnd         => "ENDLESS_DUNGEON_END",
+            EventType::EndlessDungeonStageEnd    => "ENDLESS_DUNGEON_STAGE_END",
+            EventType::EndlessDungeonBuffAdded   => "ENDLESS_DUNGEON_BUFF_ADDED",
+            EventType::EndlessDungeonBuffRemoved => "ENDLESS_DUNGEON_BUFF_REMOVED",
+            EventType::Unknown                   => "UNKNOWN",
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UnitAddedEventType {
     Player,
     Monster,
@@ -74,6 +145,7 @@ impl From<&str> for UnitAddedEventType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EffectChangedEventType {
     Gained,
     Updated,
@@ -0,0 +1,605 @@
+use std::io::{self, BufRead};
+
+use esosim::data::item_type::GearSlot;
+use esosim::models::player::GearPiece;
+use serde::Serialize;
+
+use crate::{EventType, UnitAddedEventType, effect::{self, EffectEvent, EffectType, StatusEffectType}, event::{self, Cast, CastEndReason, Event}, error::{ParseError, ParseMode, ParseReport}, parse::{self, field, is_true, unit_state}, player::{self, Class, Race}, unit::{self, Reaction, UnitState}, zone::{self, DungeonDifficulty}};
+
+/// A single Encounter.log line with typed fields.
+/// String fields borrow from the source line, so parsing a line never copies its text.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LogLine<'a> {
+    BeginLog(BeginLog<'a>),
+    EndLog { time: u64 },
+    BeginCombat { time: u64 },
+    EndCombat { time: u64 },
+    UnitAdded(UnitAdded<'a>),
+    UnitChanged(UnitChanged<'a>),
+    UnitRemoved { time: u64, unit_id: u32 },
+    PlayerInfo(PlayerInfo<'a>),
+    AbilityInfo(AbilityInfo<'a>),
+    EffectInfo(EffectInfo<'a>),
+    CombatEvent(Event),
+    BeginCast(Cast),
+    EndCast(EndCast),
+    EffectChanged(EffectEvent),
+    HealthRegen(HealthRegen),
+    MapChanged(MapChanged<'a>),
+    ZoneChanged(ZoneChanged<'a>),
+    TrialInit(TrialInit),
+    BeginTrial(BeginTrial),
+    EndTrial(EndTrial),
+    EndlessDungeonInit(RawLine<'a>),
+    EndlessDungeonBegin(EndlessDungeonBegin),
+    EndlessDungeonEnd(EndlessDungeonEnd),
+    EndlessDungeonStageEnd(EndlessDungeonStageEnd),
+    EndlessDungeonBuffAdded(EndlessDungeonBuff),
+    EndlessDungeonBuffRemoved(EndlessDungeonBuff),
+    Unknown(RawLine<'a>),
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BeginLog<'a> {
+    pub time: u64,
+    /// Unix time in milliseconds
+    pub log_time: u64,
+    pub log_version: u32,
+    pub realm: &'a str,
+    pub language: &'a str,
+    pub game_version: &'a str,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UnitAdded<'a> {
+    pub time: u64,
+    pub unit_id: u32,
+    pub unit_type: UnitAddedEventType,
+    pub is_local_player: bool,
+    pub player_per_session_id: u32,
+    pub monster_id: u32,
+    pub is_boss: bool,
+    pub class_id: Class,
+    pub race_id: Race,
+    pub name: &'a str,
+    pub display_name: &'a str,
+    pub character_id: u64,
+    pub level: u8,
+    pub champion_points: u16,
+    pub owner_unit_id: u32,
+    pub reaction: Reaction,
+    pub is_grouped_with_local_player: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UnitChanged<'a> {
+    pub time: u64,
+    pub unit_id: u32,
+    pub class_id: Class,
+    pub race_id: Race,
+    pub name: &'a str,
+    pub display_name: &'a str,
+    pub character_id: u64,
+    pub level: u8,
+    pub champion_points: u16,
+    pub owner_unit_id: u32,
+    pub reaction: Reaction,
+    pub is_grouped_with_local_player: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PlayerInfo<'a> {
+    pub time: u64,
+    pub unit_id: u32,
+    /// Comma separated ability ids, e.g. `142210,142079`
+    pub long_term_effect_ids: &'a str,
+    /// Comma separated stack counts, one per long term effect
+    pub long_term_effect_stacks: &'a str,
+    /// One `<equipmentInfo>` per entry, without the surrounding brackets
+    pub gear: Vec<&'a str>,
+    pub primary_abilities: &'a str,
+    pub backup_abilities: &'a str,
+}
+
+impl<'a> PlayerInfo<'a> {
+    pub fn long_term_effects(&self) -> impl Iterator<Item = (u32, u8)> + 'a {
+        id_list(self.long_term_effect_ids).zip(self.long_term_effect_stacks.split(',').map(|s| s.parse().unwrap_or(0)))
+    }
+
+    pub fn primary_ability_ids(&self) -> impl Iterator<Item = u32> + 'a {
+        id_list(self.primary_abilities)
+    }
+
+    pub fn backup_ability_ids(&self) -> impl Iterator<Item = u32> + 'a {
+        id_list(self.backup_abilities)
+    }
+
+    /// Gear pieces that can't be represented (unknown slot or enchant) are skipped.
+    pub fn gear_pieces(&self) -> Result<Vec<(GearPiece, GearSlot)>, ParseError> {
+        self.gear.iter().filter_map(|g| parse::gear_piece(g).transpose()).collect()
+    }
+}
+
+fn id_list(list: &str) -> impl Iterator<Item = u32> + '_ {
+    list.split(',').filter(|s| !s.is_empty()).filter_map(|s| s.parse().ok())
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AbilityInfo<'a> {
+    pub time: u64,
+    pub ability_id: u32,
+    pub name: &'a str,
+    pub icon_path: &'a str,
+    pub interruptible: bool,
+    pub blockable: bool,
+    /// Focus, signature and affix scripts
+    pub scribing: Option<[&'a str; 3]>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EffectInfo<'a> {
+    pub time: u64,
+    pub ability_id: u32,
+    pub effect_type: EffectType,
+    pub status_effect_type: StatusEffectType,
+    pub effect_bar_display_behaviour: &'a str,
+    pub synergy_ability_id: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EndCast {
+    pub time: u64,
+    pub end_reason: Option<CastEndReason>,
+    pub cast_track_id: u32,
+    pub interrupted_ability_id: u32,
+    pub interrupting_ability_id: Option<u32>,
+    pub interrupting_unit_id: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HealthRegen {
+    pub time: u64,
+    pub effective_regen: u32,
+    pub unit_state: UnitState,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MapChanged<'a> {
+    pub time: u64,
+    pub map_id: u32,
+    pub name: &'a str,
+    pub texture_path: &'a str,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ZoneChanged<'a> {
+    pub time: u64,
+    pub zone_id: u16,
+    pub name: &'a str,
+    pub difficulty: DungeonDifficulty,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TrialInit {
+    pub time: u64,
+    pub trial_id: u32,
+    pub in_progress: bool,
+    pub completed: bool,
+    pub start_time: u64,
+    pub duration: u64,
+    pub success: bool,
+    pub final_score: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BeginTrial {
+    pub time: u64,
+    pub trial_id: u32,
+    pub start_time: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EndTrial {
+    pub time: u64,
+    pub trial_id: u32,
+    pub duration: u64,
+    pub success: bool,
+    pub final_score: u32,
+    pub final_vitality_bonus: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EndlessDungeonBegin {
+    pub time: u64,
+    pub dungeon_id: u32,
+    pub start_time: u64,
+    pub unknown: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EndlessDungeonEnd {
+    pub time: u64,
+    pub dungeon_id: u32,
+    pub duration: u64,
+    pub final_score: u32,
+    pub unknown: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EndlessDungeonStageEnd {
+    pub time: u64,
+    pub dungeon_id: u32,
+    pub dungeon_begin_start_time: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EndlessDungeonBuff {
+    pub time: u64,
+    pub dungeon_id: u32,
+    pub ability_id: u32,
+}
+
+/// A line whose fields are not (yet) understood, kept as raw slices.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RawLine<'a> {
+    pub time: u64,
+    pub line_type: &'a str,
+    pub fields: Vec<&'a str>,
+}
+
+impl<'a> LogLine<'a> {
+    pub fn parse(line: &'a str) -> Result<LogLine<'a>, ParseError> {
+        let parts = parse::split_line(line);
+        Self::from_parts(&parts)
+    }
+
+    pub fn from_parts(parts: &[&'a str]) -> Result<LogLine<'a>, ParseError> {
+        let time = field(parts, 0)?;
+        let line_type = raw(parts, 1)?;
+
+        let line = match EventType::from(line_type) {
+            EventType::BeginLog => LogLine::BeginLog(BeginLog {
+                time,
+                log_time: field(parts, 2)?,
+                log_version: field(parts, 3)?,
+                realm: text(parts, 4)?,
+                language: text(parts, 5)?,
+                game_version: text(parts, 6)?,
+            }),
+            EventType::EndLog => LogLine::EndLog { time },
+            EventType::BeginCombat => LogLine::BeginCombat { time },
+            EventType::EndCombat => LogLine::EndCombat { time },
+            EventType::UnitAdded => LogLine::UnitAdded(UnitAdded {
+                time,
+                unit_id: field(parts, 2)?,
+                unit_type: UnitAddedEventType::from(raw(parts, 3)?),
+                is_local_player: flag(parts, 4)?,
+                player_per_session_id: field(parts, 5)?,
+                monster_id: field(parts, 6)?,
+                is_boss: flag(parts, 7)?,
+                class_id: player::match_class(raw(parts, 8)?),
+                race_id: player::match_race(raw(parts, 9)?),
+                name: text(parts, 10)?,
+                display_name: text(parts, 11)?,
+                character_id: field(parts, 12)?,
+                level: field(parts, 13)?,
+                champion_points: field(parts, 14)?,
+                owner_unit_id: field(parts, 15)?,
+                reaction: unit::match_reaction(raw(parts, 16)?),
+                is_grouped_with_local_player: flag(parts, 17)?,
+            }),
+            EventType::UnitChanged => LogLine::UnitChanged(UnitChanged {
+                time,
+                unit_id: field(parts, 2)?,
+                class_id: player::match_class(raw(parts, 3)?),
+                race_id: player::match_race(raw(parts, 4)?),
+                name: text(parts, 5)?,
+                display_name: text(parts, 6)?,
+                character_id: field(parts, 7)?,
+                level: field(parts, 8)?,
+                champion_points: field(parts, 9)?,
+                owner_unit_id: field(parts, 10)?,
+                reaction: unit::match_reaction(raw(parts, 11)?),
+                is_grouped_with_local_player: flag(parts, 12)?,
+            }),
+            EventType::UnitRemoved => LogLine::UnitRemoved { time, unit_id: field(parts, 2)? },
+            EventType::PlayerInfo => {
+                if parts.len() < 7 {
+                    return Err(ParseError::missing_field(parts, parts.len()));
+                }
+                let length = parts.len();
+                LogLine::PlayerInfo(PlayerInfo {
+                    time,
+                    unit_id: field(parts, 2)?,
+                    long_term_effect_ids: parts[3],
+                    long_term_effect_stacks: parts[4],
+                    gear: parts[5..length - 2].iter().copied().filter(|g| !g.is_empty()).collect(),
+                    primary_abilities: parts[length - 2],
+                    backup_abilities: parts[length - 1],
+                })
+            }
+            EventType::AbilityInfo => LogLine::AbilityInfo(AbilityInfo {
+                time,
+                ability_id: field(parts, 2)?,
+                name: text(parts, 3)?,
+                icon_path: text(parts, 4)?,
+                interruptible: flag(parts, 5)?,
+                blockable: flag(parts, 6)?,
+                scribing: if parts.len() == 10 {
+                    Some([text(parts, 7)?, text(parts, 8)?, text(parts, 9)?])
+                } else {
+                    None
+                },
+            }),
+            EventType::EffectInfo => LogLine::EffectInfo(EffectInfo {
+                time,
+                ability_id: field(parts, 2)?,
+                effect_type: effect::parse_effect_type(raw(parts, 3)?),
+                status_effect_type: effect::parse_status_effect_type(raw(parts, 4)?),
+                effect_bar_display_behaviour: raw(parts, 5)?,
+                synergy_ability_id: field(parts, 6).ok(),
+            }),
+            EventType::CombatEvent => {
+                let source_unit_state = unit_state(parts, 9)?;
+                LogLine::CombatEvent(Event {
+                    time,
+                    result: event::parse_event_result(raw(parts, 2)?).ok_or_else(|| ParseError::invalid_value(parts, 2))?,
+                    damage_type: event::parse_damage_type(raw(parts, 3)?),
+                    power_type: field(parts, 4)?,
+                    hit_value: field(parts, 5)?,
+                    overflow: field(parts, 6)?,
+                    cast_track_id: field(parts, 7)?,
+                    ability_id: field(parts, 8)?,
+                    source_unit_state,
+                    target_unit_state: target_unit_state(parts, 19, source_unit_state)?,
+                })
+            }
+            EventType::BeginCast => {
+                let source_unit_state = unit_state(parts, 6)?;
+                LogLine::BeginCast(Cast {
+                    time,
+                    duration: field(parts, 2)?,
+                    channeled: flag(parts, 3)?,
+                    cast_track_id: field(parts, 4)?,
+                    ability_id: field(parts, 5)?,
+                    source_unit_state,
+                    target_unit_state: target_unit_state(parts, 16, source_unit_state)?,
+                    interrupt_reason: None,
+                })
+            }
+            EventType::EndCast => LogLine::EndCast(EndCast {
+                time,
+                end_reason: event::parse_cast_end_reason(raw(parts, 2)?),
+                cast_track_id: field(parts, 3)?,
+                interrupted_ability_id: field(parts, 4)?,
+                interrupting_ability_id: field(parts, 5).ok(),
+                interrupting_unit_id: field(parts, 6).ok(),
+            }),
+            EventType::EffectChanged => {
+                let source_unit_state = unit_state(parts, 6)?;
+                LogLine::EffectChanged(EffectEvent {
+                    time,
+                    change_type: effect::parse_effect_change_type(raw(parts, 2)?),
+                    stack_count: field(parts, 3)?,
+                    cast_track_id: field(parts, 4)?,
+                    ability_id: field(parts, 5)?,
+                    source_unit_state,
+                    target_unit_state: target_unit_state(parts, 16, source_unit_state)?,
+                    player_initiated_remove_cast_track_id: parts.last().is_some_and(|s| is_true(s)),
+                })
+            }
+            EventType::HealthRegen => LogLine::HealthRegen(HealthRegen {
+                time,
+                effective_regen: field(parts, 2)?,
+                unit_state: unit_state(parts, 3)?,
+            }),
+            EventType::MapChanged => LogLine::MapChanged(MapChanged {
+                time,
+                map_id: field(parts, 2)?,
+                name: text(parts, 3)?,
+                texture_path: text(parts, 4)?,
+            }),
+            EventType::ZoneChanged => LogLine::ZoneChanged(ZoneChanged {
+                time,
+                zone_id: field(parts, 2)?,
+                name: text(parts, 3)?,
+                difficulty: zone::parse_dungeon_difficulty(text(parts, 4)?),
+            }),
+            EventType::TrialInit => LogLine::TrialInit(TrialInit {
+                time,
+                trial_id: field(parts, 2)?,
+                in_progress: flag(parts, 3)?,
+                completed: flag(parts, 4)?,
+                start_time: field(parts, 5)?,
+                duration: field(parts, 6)?,
+                success: flag(parts, 7)?,
+                final_score: field(parts, 8)?,
+            }),
+            EventType::BeginTrial => LogLine::BeginTrial(BeginTrial {
+                time,
+                trial_id: field(parts, 2)?,
+                start_time: field(parts, 3)?,
+            }),
+            EventType::EndTrial => LogLine::EndTrial(EndTrial {
+                time,
+                trial_id: field(parts, 2)?,
+                duration: field(parts, 3)?,
+                success: flag(parts, 4)?,
+                final_score: field(parts, 5)?,
+                final_vitality_bonus: field(parts, 6).unwrap_or(0),
+            }),
+            EventType::EndlessDungeonInit => LogLine::EndlessDungeonInit(raw_line(time, line_type, parts)),
+            EventType::EndlessDungeonBegin => LogLine::EndlessDungeonBegin(EndlessDungeonBegin {
+                time,
+                dungeon_id: field(parts, 2)?,
+                start_time: field(parts, 3)?,
+                unknown: flag(parts, 4).unwrap_or(false),
+            }),
+            EventType::EndlessDungeonEnd => LogLine::EndlessDungeonEnd(EndlessDungeonEnd {
+                time,
+                dungeon_id: field(parts, 2)?,
+                duration: field(parts, 3)?,
+                final_score: field(parts, 4)?,
+                unknown: flag(parts, 5).unwrap_or(false),
+            }),
+            EventType::EndlessDungeonStageEnd => LogLine::EndlessDungeonStageEnd(EndlessDungeonStageEnd {
+                time,
+                dungeon_id: field(parts, 2)?,
+                dungeon_begin_start_time: field(parts, 3)?,
+            }),
+            EventType::EndlessDungeonBuffAdded => LogLine::EndlessDungeonBuffAdded(EndlessDungeonBuff {
+                time,
+                dungeon_id: field(parts, 2)?,
+                ability_id: field(parts, 3)?,
+            }),
+            EventType::EndlessDungeonBuffRemoved => LogLine::EndlessDungeonBuffRemoved(EndlessDungeonBuff {
+                time,
+                dungeon_id: field(parts, 2)?,
+                ability_id: field(parts, 3)?,
+            }),
+            EventType::Unknown => LogLine::Unknown(raw_line(time, line_type, parts)),
+        };
+
+        Ok(line)
+    }
+
+    pub fn event_type(&self) -> EventType {
+        match self {
+            LogLine::BeginLog(_) => EventType::BeginLog,
+            LogLine::EndLog { .. } => EventType::EndLog,
+            LogLine::BeginCombat { .. } => EventType::BeginCombat,
+            LogLine::EndCombat { .. } => EventType::EndCombat,
+            LogLine::UnitAdded(_) => EventType::UnitAdded,
+            LogLine::UnitChanged(_) => EventType::UnitChanged,
+            LogLine::UnitRemoved { .. } => EventType::UnitRemoved,
+            LogLine::PlayerInfo(_) => EventType::PlayerInfo,
+            LogLine::AbilityInfo(_) => EventType::AbilityInfo,
+            LogLine::EffectInfo(_) => EventType::EffectInfo,
+            LogLine::CombatEvent(_) => EventType::CombatEvent,
+            LogLine::BeginCast(_) => EventType::BeginCast,
+            LogLine::EndCast(_) => EventType::EndCast,
+            LogLine::EffectChanged(_) => EventType::EffectChanged,
+            LogLine::HealthRegen(_) => EventType::HealthRegen,
+            LogLine::MapChanged(_) => EventType::MapChanged,
+            LogLine::ZoneChanged(_) => EventType::ZoneChanged,
+            LogLine::TrialInit(_) => EventType::TrialInit,
+            LogLine::BeginTrial(_) => EventType::BeginTrial,
+            LogLine::EndTrial(_) => EventType::EndTrial,
+            LogLine::EndlessDungeonInit(_) => EventType::EndlessDungeonInit,
+            LogLine::EndlessDungeonBegin(_) => EventType::EndlessDungeonBegin,
+            LogLine::EndlessDungeonEnd(_) => EventType::EndlessDungeonEnd,
+            LogLine::EndlessDungeonStageEnd(_) => EventType::EndlessDungeonStageEnd,
+            LogLine::EndlessDungeonBuffAdded(_) => EventType::EndlessDungeonBuffAdded,
+            LogLine::EndlessDungeonBuffRemoved(_) => EventType::EndlessDungeonBuffRemoved,
+            LogLine::Unknown(_) => EventType::Unknown,
+        }
+    }
+
+    /// Milliseconds since logging began
+    pub fn time(&self) -> u64 {
+        match self {
+            LogLine::EndLog { time } | LogLine::BeginCombat { time } | LogLine::EndCombat { time } | LogLine::UnitRemoved { time, .. } => *time,
+            LogLine::BeginLog(l) => l.time,
+            LogLine::UnitAdded(l) => l.time,
+            LogLine::UnitChanged(l) => l.time,
+            LogLine::PlayerInfo(l) => l.time,
+            LogLine::AbilityInfo(l) => l.time,
+            LogLine::EffectInfo(l) => l.time,
+            LogLine::CombatEvent(l) => l.time,
+            LogLine::BeginCast(l) => l.time,
+            LogLine::EndCast(l) => l.time,
+            LogLine::EffectChanged(l) => l.time,
+            LogLine::HealthRegen(l) => l.time,
+            LogLine::MapChanged(l) => l.time,
+            LogLine::ZoneChanged(l) => l.time,
+            LogLine::TrialInit(l) => l.time,
+            LogLine::BeginTrial(l) => l.time,
+            LogLine::EndTrial(l) => l.time,
+            LogLine::EndlessDungeonBegin(l) => l.time,
+            LogLine::EndlessDungeonEnd(l) => l.time,
+            LogLine::EndlessDungeonStageEnd(l) => l.time,
+            LogLine::EndlessDungeonBuffAdded(l) | LogLine::EndlessDungeonBuffRemoved(l) => l.time,
+            LogLine::EndlessDungeonInit(l) | LogLine::Unknown(l) => l.time,
+        }
+    }
+}
+
+fn raw_line<'a>(time: u64, line_type: &'a str, parts: &[&'a str]) -> RawLine<'a> {
+    RawLine {
+        time,
+        line_type,
+        fields: parts.get(2..).map(|f| f.to_vec()).unwrap_or_default(),
+    }
+}
+
+/// Parses lines one at a time while keeping count of line numbers.
+/// In [`ParseMode::Lenient`] lines that fail to parse are skipped and recorded in the report instead of returned as errors.
+#[derive(Debug, Default)]
+pub struct LineParser {
+    pub mode: ParseMode,
+    pub report: ParseReport,
+}
+
+impl LineParser {
+    pub fn new(mode: ParseMode) -> Self {
+        Self { mode, report: ParseReport::default() }
+    }
+
+    pub fn parse<'a>(&mut self, line: &'a str) -> Result<Option<LogLine<'a>>, ParseError> {
+        self.report.lines += 1;
+        match LogLine::parse(line) {
+            Ok(log_line) => Ok(Some(log_line)),
+            Err(e) => {
+                let e = e.at_line(self.report.lines);
+                match self.mode {
+                    ParseMode::Strict => Err(e),
+                    ParseMode::Lenient => {
+                        self.report.record(e);
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Calls `f` with every line of `reader` that parses, along with its byte offset and length including the line ending.
+/// Lines that fail to parse (such as a line truncated by a game crash) are skipped.
+pub fn for_each_line<R: BufRead>(mut reader: R, mut f: impl FnMut(&LogLine, u64, u64)) -> io::Result<()> {
+    let mut buffer = Vec::new();
+    let mut offset = 0u64;
+
+    loop {
+        buffer.clear();
+        let length = reader.read_until(b'\n', &mut buffer)? as u64;
+        if length == 0 {break}
+        let text = String::from_utf8_lossy(&buffer);
+        if let Ok(line) = LogLine::parse(text.trim_end()) {
+            f(&line, offset, length);
+        }
+        offset += length;
+    }
+
+    Ok(())
+}
+
+fn raw<'a>(parts: &[&'a str], index: usize) -> Result<&'a str, ParseError> {
+    parse::text(parts, index)
+}
+
+fn text<'a>(parts: &[&'a str], index: usize) -> Result<&'a str, ParseError> {
+    raw(parts, index).map(|s| s.trim_matches('"'))
+}
+
+fn flag(parts: &[&str], index: usize) -> Result<bool, ParseError> {
+    raw(parts, index).map(is_true)
+}
+
+fn target_unit_state(parts: &[&str], start_index: usize, source: UnitState) -> Result<UnitState, ParseError> {
+    if parts.get(start_index) == Some(&"*") {
+        Ok(source)
+    } else {
+        unit_state(parts, start_index)
+    }
+}
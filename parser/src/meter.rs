@@ -0,0 +1,134 @@
+use std::{collections::HashMap, io::{self, BufRead}};
+
+use serde::Serialize;
+
+use crate::{event::{Event, is_critical_event, is_damage_event, is_heal_event}, fights::{Fight, FightHeader, summarise_fights}, line::LogLine, session::{Session, SessionUnit}};
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct AbilityMeter {
+    pub ability_id: u32,
+    pub name: String,
+    pub damage: u64,
+    pub healing: u64,
+    pub overhealing: u64,
+    pub hits: u32,
+    pub critical_hits: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct PlayerMeter {
+    pub unit_id: u32,
+    pub name: String,
+    pub display_name: String,
+    pub damage_done: u64,
+    pub damage_taken: u64,
+    pub healing_done: u64,
+    pub overhealing: u64,
+    pub dps: f64,
+    pub hps: f64,
+    /// Sorted by damage, then healing, highest first
+    pub abilities: Vec<AbilityMeter>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightMeter {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    /// Sorted by damage done, highest first
+    pub players: Vec<PlayerMeter>,
+}
+
+/// Accumulates damage and healing for the fight currently in progress.
+/// Damage and healing done by pets and other owned units is credited to the owning player.
+#[derive(Debug, Default)]
+pub struct MeterAccumulator {
+    players: HashMap<u32, PlayerMeter>,
+    abilities: HashMap<(u32, u32), AbilityMeter>,
+}
+
+impl MeterAccumulator {
+    pub fn handle_event(&mut self, event: &Event, session: &Session) {
+        let damage = is_damage_event(event.result);
+        let heal = is_heal_event(event.result);
+        if !damage && !heal {return}
+
+        let source = session.owner(event.source_unit_state.unit_id);
+        let target = session.owner(event.target_unit_state.unit_id);
+        let source_is_player = session.player(source).is_some();
+        let target_is_player = session.player(target).is_some();
+
+        if damage && target_is_player {
+            self.player(target, session).damage_taken += event.hit_value as u64;
+        }
+        if !source_is_player || (damage && target_is_player) {return}
+
+        let player = self.player(source, session);
+        if damage {
+            player.damage_done += event.hit_value as u64;
+        } else {
+            player.healing_done += event.hit_value as u64;
+            player.overhealing += event.overflow as u64;
+        }
+
+        let ability = self.abilities.entry((source, event.ability_id)).or_insert_with(|| AbilityMeter {
+            ability_id: event.ability_id,
+            name: session.ability(event.ability_id).map(|a| a.name.to_string()).unwrap_or_default(),
+            ..Default::default()
+        });
+        if damage {
+            ability.damage += event.hit_value as u64;
+        } else {
+            ability.healing += event.hit_value as u64;
+            ability.overhealing += event.overflow as u64;
+        }
+        ability.hits += 1;
+        if is_critical_event(event.result) {
+            ability.critical_hits += 1;
+        }
+    }
+
+    fn player(&mut self, unit_id: u32, session: &Session) -> &mut PlayerMeter {
+        self.players.entry(unit_id).or_insert_with(|| {
+            let (name, display_name) = match session.unit(unit_id) {
+                Some(SessionUnit::Player(p)) => (p.name.clone(), p.display_name.clone()),
+                _ => Default::default(),
+            };
+            PlayerMeter { unit_id, name, display_name, ..Default::default() }
+        })
+    }
+
+    /// Produces the meter for `fight` and clears the accumulator for the next one.
+    pub fn finish(&mut self, fight: &Fight) -> FightMeter {
+        let seconds = fight.duration() as f64 / 1000.0;
+        let mut abilities: HashMap<u32, Vec<AbilityMeter>> = HashMap::new();
+        for ((unit_id, _), ability) in self.abilities.drain() {
+            abilities.entry(unit_id).or_default().push(ability);
+        }
+
+        let mut players: Vec<PlayerMeter> = self.players.drain().map(|(unit_id, mut player)| {
+            if seconds > 0.0 {
+                player.dps = player.damage_done as f64 / seconds;
+                player.hps = player.healing_done as f64 / seconds;
+            }
+            player.abilities = abilities.remove(&unit_id).unwrap_or_default();
+            player.abilities.sort_by(|a, b| b.damage.cmp(&a.damage).then(b.healing.cmp(&a.healing)));
+            player
+        }).collect();
+        players.sort_by(|a, b| b.damage_done.cmp(&a.damage_done).then(b.healing_done.cmp(&a.healing_done)));
+
+        FightMeter {
+            fight: FightHeader::from(fight),
+            players,
+        }
+    }
+}
+
+/// Computes a damage and healing meter for every fight in a log.
+pub fn fight_meters<R: BufRead>(reader: R) -> io::Result<Vec<FightMeter>> {
+    summarise_fights(reader, MeterAccumulator::default(), |accumulator, line, tracker| {
+        if let LogLine::CombatEvent(event) = line
+            && tracker.current_fight().is_some() {
+            accumulator.handle_event(event, &tracker.session);
+        }
+    }, |accumulator, fight, _| accumulator.finish(fight))
+}
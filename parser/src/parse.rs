@@ -1,11 +1,11 @@
-use std::{collections::HashMap, u32};
+use std::{collections::HashMap, str::FromStr};
 
 use esosim::data::item_type::EnchantType;
 use esosim::data::item_type::GearSlot;
 use esosim::models::player::GearPiece;
 use esosim::models::player::GearEnchant;
 
-use crate::{effect::{self, Ability, Effect}, player::{self, Player, effective_level, match_gear_quality, match_gear_trait}, unit::{self, Unit, UnitType, blank_unit_state}};
+use crate::{effect::{self, Ability, Effect}, error::{ParseError, ParseErrorKind}, player::{self, Player, effective_level, match_gear_quality, match_gear_trait}, unit::{self, Unit, UnitType}};
 
 pub fn is_true(value: &str) -> bool {
     value == "T"
@@ -26,22 +26,26 @@ fn parse_pair(s: &str) -> (u32, u32) {
     (first, second)
 }
 
-pub fn unit_state(parts: &[String], start_index: usize) -> unit::UnitState {
+pub(crate) fn field<T: FromStr>(parts: &[&str], index: usize) -> Result<T, ParseError> {
+    let text = parts.get(index).ok_or_else(|| ParseError::missing_field(parts, index))?;
+    text.parse().map_err(|_| ParseError::invalid_value(parts, index))
+}
+
+pub(crate) fn text<'a>(parts: &[&'a str], index: usize) -> Result<&'a str, ParseError> {
+    parts.get(index).copied().ok_or_else(|| ParseError::missing_field(parts, index))
+}
+
+pub fn unit_state(parts: &[&str], start_index: usize) -> Result<unit::UnitState, ParseError> {
     if parts.len() < start_index + 10 {
-        eprintln!("Impossible unit state: {parts:?}");
-        return blank_unit_state();
+        return Err(ParseError::missing_field(parts, parts.len()));
     }
 
     let slice = &parts[start_index..start_index + 10];
     let [
-        unit_id_str, health_str, magicka_str, stamina_str, ultimate_str,
+        _, health_str, magicka_str, stamina_str, ultimate_str,
         werewolf_str, shield_str, map_x_str, map_y_str, heading_str
-    ] = match slice {
-        [a, b, c, d, e, f, g, h, i, j] => [a, b, c, d, e, f, g, h, i, j],
-        _ => {
-            eprintln!("Invalid unit state slice: {parts:?}");
-            return blank_unit_state();
-        }
+    ] = slice else {
+        return Err(ParseError::missing_field(parts, parts.len()));
     };
 
     let (health, max_health) = parse_pair(health_str);
@@ -50,8 +54,8 @@ pub fn unit_state(parts: &[String], start_index: usize) -> unit::UnitState {
     let (ultimate, max_ultimate) = parse_pair(ultimate_str);
     let (werewolf, werewolf_max) = parse_pair(werewolf_str);
 
-    unit::UnitState {
-        unit_id: parse_u32(unit_id_str),
+    Ok(unit::UnitState {
+        unit_id: field(parts, start_index)?,
         health,
         max_health,
         magicka,
@@ -66,170 +70,170 @@ pub fn unit_state(parts: &[String], start_index: usize) -> unit::UnitState {
         map_x: parse_f32(map_x_str),
         map_y: parse_f32(map_y_str),
         heading: parse_f32(heading_str),
-    }
+    })
 }
 
-pub fn unit_state_id_only(parts: &[String], start_index: usize) -> Option<u32> {
-    if parts.len() <= start_index {
-        eprintln!("Impossible unit state: {parts:?}");
-        return None;
-    }
-
-    Some(parse_u32(&parts[start_index]))
+pub fn unit_state_id_only(parts: &[&str], start_index: usize) -> Result<u32, ParseError> {
+    field(parts, start_index)
 }
 
 
-pub fn player(parts: &[String]) -> Player {
-    let unit_id: u32 = parts[2].parse().unwrap();
-    Player {
-        unit_id,
-        is_local_player: is_true(&parts[4]),
-        player_per_session_id: parts[5].parse().unwrap(),
-        class_id: player::match_class(&parts[8]),
-        race_id: player::match_race(&parts[9]),
-        name: parts[10].trim_matches('"').to_string(),
-        display_name: parts[11].trim_matches('"').to_string(),
-        character_id: parts[12].parse().unwrap(),
-        level: parts[13].parse().unwrap(),
-        champion_points: parts[14].parse().unwrap(),
-        is_grouped_with_local_player: is_true(&parts[17]),
+pub fn player(parts: &[&str]) -> Result<Player, ParseError> {
+    Ok(Player {
+        unit_id: field(parts, 2)?,
+        is_local_player: is_true(text(parts, 4)?),
+        player_per_session_id: field(parts, 5)?,
+        class_id: player::match_class(text(parts, 8)?),
+        race_id: player::match_race(text(parts, 9)?),
+        name: text(parts, 10)?.trim_matches('"').to_string(),
+        display_name: text(parts, 11)?.trim_matches('"').to_string(),
+        character_id: field(parts, 12)?,
+        level: field(parts, 13)?,
+        champion_points: field(parts, 14)?,
+        is_grouped_with_local_player: is_true(text(parts, 17)?),
         unit_state: unit::blank_unit_state(),
         effects: Vec::new(),
         gear: player::empty_loadout(),
+        gear_pieces: Vec::new(),
         primary_abilities: Vec::new(),
         backup_abilities: Vec::new(),
-    }
+    })
 }
 
-pub fn monster(parts: &[String]) -> Unit {
-    let unit_id: u32 = parts[2].parse().unwrap();
-    Unit {
-        unit_id,
+pub fn monster(parts: &[&str]) -> Result<Unit, ParseError> {
+    Ok(Unit {
+        unit_id: field(parts, 2)?,
         unit_type: UnitType::Monster,
-        monster_id: parts[6].parse().unwrap(),
-        is_boss: is_true(&parts[7]),
-        name: parts[10].trim_matches('"').to_string(),
-        level: parts[13].parse().unwrap(),
-        champion_points: parts[14].parse().unwrap(),
-        owner_unit_id: parts[15].parse().unwrap(),
-        reaction: unit::match_reaction(&parts[16]),
+        monster_id: field(parts, 6)?,
+        is_boss: is_true(text(parts, 7)?),
+        name: text(parts, 10)?.trim_matches('"').to_string(),
+        level: field(parts, 13)?,
+        champion_points: field(parts, 14)?,
+        owner_unit_id: field(parts, 15)?,
+        reaction: unit::match_reaction(text(parts, 16)?),
         unit_state: unit::blank_unit_state(),
         effects: Vec::new(),
-    }
+    })
 }
 
-pub fn monster_updated(parts: &[String], unit: Unit) -> Unit {
-    Unit {
-        unit_id: parts[2].parse().unwrap(),
+pub fn monster_updated(parts: &[&str], unit: Unit) -> Result<Unit, ParseError> {
+    Ok(Unit {
+        unit_id: field(parts, 2)?,
         unit_type: unit.unit_type,
         monster_id: unit.monster_id,
         is_boss: unit.is_boss,
-        name: parts[5].trim_matches('"').to_string(),
-        level: parts[8].parse().unwrap(),
-        champion_points: parts[9].parse().unwrap(),
-        owner_unit_id: parts[10].parse().unwrap(),
-        reaction: unit::match_reaction(&parts[11]),
+        name: text(parts, 5)?.trim_matches('"').to_string(),
+        level: field(parts, 8)?,
+        champion_points: field(parts, 9)?,
+        owner_unit_id: field(parts, 10)?,
+        reaction: unit::match_reaction(text(parts, 11)?),
         unit_state: unit.unit_state,
         effects: unit.effects,
-    }
+    })
 }
 
-pub fn object(parts: &[String]) -> Unit {
-    let unit_id: u32 = parts[2].parse().unwrap();
-    Unit {
-        unit_id,
+pub fn object(parts: &[&str]) -> Result<Unit, ParseError> {
+    Ok(Unit {
+        unit_id: field(parts, 2)?,
         unit_type: UnitType::Object,
         monster_id: 0,
         is_boss: false,
-        name: parts[10].trim_matches('"').to_string(),
-        level: parts[13].parse().unwrap(),
-        champion_points: parts[14].parse().unwrap(),
-        owner_unit_id: parts[15].parse().unwrap(),
-        reaction: unit::match_reaction(&parts[16]),
+        name: text(parts, 10)?.trim_matches('"').to_string(),
+        level: field(parts, 13)?,
+        champion_points: field(parts, 14)?,
+        owner_unit_id: field(parts, 15)?,
+        reaction: unit::match_reaction(text(parts, 16)?),
         unit_state: unit::blank_unit_state(),
         effects: Vec::new(),
-    }
+    })
 }
 
-pub fn gear_piece(part: &str) -> Option<(GearPiece, GearSlot)> {
+/// Parses one `<equipmentInfo>` entry of a PLAYER_INFO line.
+/// Returns `Ok(None)` for pieces that are well formed but can't be represented, such as an unknown slot or enchant.
+pub fn gear_piece(part: &str) -> Result<Option<(GearPiece, GearSlot)>, ParseError> {
     let split: Vec<&str> = part.split(',').collect();
-    if split.len() < 3 {println!("Gear piece malformed: {split:?}"); return None}
+    if split.len() < 10 {
+        return Err(ParseError {
+            line_number: None,
+            event_type: "PLAYER_INFO".to_string(),
+            field_index: None,
+            text: part.to_string(),
+            kind: ParseErrorKind::MissingField,
+        });
+    }
     let level = split[9].parse::<u8>().unwrap_or(u8::MAX);
-    let slot = player::match_gear_slot(split[0]);
-    if slot.is_none() {return None}
+    let Some(slot) = player::match_gear_slot(split[0]) else {return Ok(None)};
     let is_cp = split.get(8).is_some_and(|v| is_true(v));
     let quality = split.get(10).map(|v| player::match_gear_quality(v)).unwrap_or(esosim::data::item_type::ItemQuality::Normal);
 
     let enchant = if level > 0 {
         let mut et = player::match_enchant_type(split[7]);
-        if et.is_none() && matches!(slot.as_ref().unwrap(), GearSlot::Ring1 | GearSlot::Necklace | GearSlot::Ring2) {
+        if et.is_none() && matches!(slot, GearSlot::Ring1 | GearSlot::Necklace | GearSlot::Ring2) {
             // assume enchant is indeko tri recovery
             et = Some(EnchantType::PrismaticRecovery);
-        } else if et.is_none() {
-            return None
         }
+        let Some(glyph) = et else {return Ok(None)};
 
         Some(GearEnchant {
-            glyph: et.unwrap(),
+            glyph,
             effective_level: level,
-            quality: quality,
+            quality,
         })
     } else {
         None
     };
 
-    Some((GearPiece {
+    Ok(Some((GearPiece {
         item_id: split[1].parse().unwrap_or(0),
         gear_trait: match_gear_trait(split[4]),
         quality: match_gear_quality(split[5]),
         set_id: Some(split[6].parse().unwrap_or(0)),
         enchant,
         effective_level: effective_level(level, is_cp),
-    }, slot.unwrap()))
+    }, slot)))
 }
 
-pub fn ability(parts: &[String]) -> Ability {
-    let id: u32 = parts[2].parse().unwrap();
-    Ability {
-        id,
-        name: parts[3].trim_matches('"').into(),
-        icon: parts[4]
-            .trim_matches('"')
-            .split('/')
-            .next_back()
-            .unwrap()
-            .replace(".dds", ".png")
-            .into(),
-        interruptible: is_true(&parts[5]),
-        blockable: is_true(&parts[6]),
+pub fn ability(parts: &[&str]) -> Result<Ability, ParseError> {
+    Ok(Ability {
+        id: field(parts, 2)?,
+        name: text(parts, 3)?.trim_matches('"').into(),
+        icon: icon_file_name(text(parts, 4)?.trim_matches('"')).into(),
+        interruptible: is_true(text(parts, 5)?),
+        blockable: is_true(text(parts, 6)?),
         scribing: if parts.len() == 10 {
             Some((7..10).map(|i| parts[i].trim_matches('"').to_owned()).collect())
         } else {
             None
         },
-    }
+    })
+}
+
+/// `/esoui/art/icons/ability_mage_065.dds` -> `ability_mage_065.png`
+pub fn icon_file_name(path: &str) -> String {
+    path.split('/').next_back().unwrap_or_default().replace(".dds", ".png")
 }
 
-pub fn effect(parts: &[String], ability_lookup: &HashMap<u32, Ability>) -> Effect {
-    let effect_id: u32 = parts[2].parse().unwrap();
+pub fn effect(parts: &[&str], ability_lookup: &HashMap<u32, Ability>) -> Result<Effect, ParseError> {
+    let effect_id: u32 = field(parts, 2)?;
     let ability = ability_lookup
         .get(&effect_id)
-        .expect("ABILITY_INFO must precede EFFECT_INFO")
+        .ok_or_else(|| ParseError::unknown_ability(parts, 2))?
         .clone();
-    Effect {
+    Ok(Effect {
         ability,
         stack_count: 0,
-        effect_type: effect::parse_effect_type(&parts[3]),
-        status_effect_type: effect::parse_status_effect_type(&parts[4]),
-        synergy: if parts.len() > 6 {
-            parts[6].parse().ok()
-        } else {
-            None
-        },
-    }
+        effect_type: effect::parse_effect_type(text(parts, 3)?),
+        status_effect_type: effect::parse_status_effect_type(text(parts, 4)?),
+        synergy: parts.get(6).and_then(|s| s.parse().ok()),
+    })
 }
 
 pub fn handle_line(line: &str) -> Vec<String> {
+    split_line(line).into_iter().map(str::to_owned).collect()
+}
+
+/// Borrowed counterpart of [`handle_line`]: every field is a slice of `line`, so no per-field allocation happens.
+pub fn split_line(line: &str) -> Vec<&str> {
     let mut result = Vec::with_capacity(17);
 
     let bytes = line.as_bytes();
@@ -274,7 +278,7 @@ pub fn handle_line(line: &str) -> Vec<String> {
     result
 }
 
-fn process_segment_bytes(line: &str, start: usize, end: usize, result: &mut Vec<String>) {
+fn process_segment_bytes<'a>(line: &'a str, start: usize, end: usize, result: &mut Vec<&'a str>) {
     let seg = &line[start..end];
     let trimmed = seg.trim();
 
@@ -285,18 +289,18 @@ fn process_segment_bytes(line: &str, start: usize, end: usize, result: &mut Vec<
     if trimmed.starts_with('[') && trimmed.ends_with(']') && trimmed.len() >= 2 {
         process_array_bytes(&trimmed[1..trimmed.len() - 1], result);
     } else {
-        result.push(trimmed.to_string());
+        result.push(trimmed);
     }
 }
 
-fn process_array_bytes(s: &str, result: &mut Vec<String>) {
+fn process_array_bytes<'a>(s: &'a str, result: &mut Vec<&'a str>) {
     if s.is_empty() {
-        result.push(String::new());
+        result.push("");
         return;
     }
 
     if !s.contains('[') {
-        result.push(s.to_string());
+        result.push(s);
         return;
     }
 
@@ -337,17 +341,17 @@ fn process_array_bytes(s: &str, result: &mut Vec<String>) {
     }
 }
 
-fn process_nested_segment_bytes(segment: &str, result: &mut Vec<String>) {
+fn process_nested_segment_bytes<'a>(segment: &'a str, result: &mut Vec<&'a str>) {
     let trimmed = segment.trim();
 
     if trimmed.is_empty() {
-        result.push(String::new());
+        result.push("");
         return;
     }
 
     if trimmed.starts_with('[') && trimmed.ends_with(']') && trimmed.len() >= 2 {
         process_array_bytes(&trimmed[1..trimmed.len() - 1], result);
     } else {
-        result.push(trimmed.to_string());
+        result.push(trimmed);
     }
-}
\ No newline at end of file
+}
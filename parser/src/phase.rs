@@ -0,0 +1,373 @@
+use std::{collections::{HashMap, HashSet}, io::{self, BufRead}};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::{event::{Event, EventResult, is_damage_event, is_death_event}, fights::{Fight, FightBoss, FightHeader, summarise_fights}, line::LogLine, session::{Session, SessionUnit}, unit::UnitState};
+
+lazy_static! {
+    /// The phase definitions that ship with the parser
+    pub static ref BOSS_PHASES: PhaseDefinitions = PhaseDefinitions::parse(include_str!("boss_phase_data.csv"))
+        .expect("built-in boss phase data is valid");
+}
+
+/// What begins a phase.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PhaseTrigger {
+    /// The fight began
+    Start,
+    /// The boss first fell to this percentage of its health
+    Health { percent: f64 },
+    /// A unit other than a player first cast or used this ability
+    Ability { ability_id: u32 },
+    /// The boss went at least `gap` milliseconds without taking damage
+    Invulnerable { gap: u64 },
+    /// The boss took damage again after being invulnerable
+    Resumed,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PhaseRule {
+    pub trigger: PhaseTrigger,
+    pub name: String,
+}
+
+/// Phase rules for each boss, keyed by monster id or by boss name. Monster id 0 holds the rules for bosses without rules of their own.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PhaseDefinitions {
+    bosses: HashMap<u32, Vec<PhaseRule>>,
+    /// Keyed by boss name in lower case
+    named: HashMap<String, Vec<PhaseRule>>,
+}
+
+impl PhaseDefinitions {
+    /// Reads definitions in the format of `boss_phase_data.csv`: one `boss,trigger,value,phase name` rule per line, with `#` comments,
+    /// where `boss` is a monster id or a boss name.
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let mut bosses: HashMap<u32, Vec<PhaseRule>> = HashMap::new();
+        let mut named: HashMap<String, Vec<PhaseRule>> = HashMap::new();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {continue}
+            let parts: Vec<&str> = line.splitn(4, ',').map(str::trim).collect();
+            let [boss, trigger, value, name] = parts[..] else {
+                return Err(format!("Line {}: expected boss,trigger,value,phase name", i + 1));
+            };
+            if boss.is_empty() {
+                return Err(format!("Line {}: missing monster id or boss name", i + 1));
+            }
+            let trigger = match trigger {
+                "start" => PhaseTrigger::Start,
+                "health" => PhaseTrigger::Health {
+                    percent: value.parse().ok().filter(|p| (0.0..=100.0).contains(p))
+                        .ok_or_else(|| format!("Line {}: health must be a percentage", i + 1))?,
+                },
+                "ability" => PhaseTrigger::Ability {
+                    ability_id: value.parse().map_err(|e| format!("Line {}: invalid ability id: {e}", i + 1))?,
+                },
+                "invulnerable" => PhaseTrigger::Invulnerable {
+                    gap: value.parse().map_err(|e| format!("Line {}: invalid gap: {e}", i + 1))?,
+                },
+                other => return Err(format!("Line {}: unknown trigger {other}", i + 1)),
+            };
+            let rule = PhaseRule { trigger, name: name.to_owned() };
+            match boss.parse::<u32>() {
+                Ok(monster_id) => bosses.entry(monster_id).or_default().push(rule),
+                Err(_) => named.entry(boss.to_lowercase()).or_default().push(rule),
+            }
+        }
+        Ok(Self { bosses, named })
+    }
+
+    /// Replaces the rules of every boss `other` has rules for.
+    pub fn extend(&mut self, other: PhaseDefinitions) {
+        self.bosses.extend(other.bosses);
+        self.named.extend(other.named);
+    }
+
+    pub fn has_rules(&self, monster_id: u32, name: &str) -> bool {
+        self.bosses.contains_key(&monster_id) || self.named.contains_key(&name.to_lowercase())
+    }
+
+    /// Rules for a boss by its monster id, then by its name, falling back to the rules for monster id 0.
+    pub fn rules(&self, monster_id: u32, name: &str) -> &[PhaseRule] {
+        self.bosses.get(&monster_id)
+            .or_else(|| self.named.get(&name.to_lowercase()))
+            .or_else(|| self.bosses.get(&0))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    fn ability_ids(&self) -> HashSet<u32> {
+        self.bosses.values().chain(self.named.values()).flatten()
+            .filter_map(|rule| match rule.trigger {
+                PhaseTrigger::Ability { ability_id } => Some(ability_id),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PlayerPhase {
+    pub unit_id: u32,
+    pub name: String,
+    pub display_name: String,
+    pub damage_done: u64,
+    pub dps: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PhaseDeath {
+    pub unit_id: u32,
+    pub name: String,
+    /// Milliseconds since logging began
+    pub time: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Phase {
+    pub name: String,
+    pub trigger: PhaseTrigger,
+    pub start_time: u64,
+    pub end_time: u64,
+    /// Damage done by players and their pets to units other than players
+    pub damage_done: u64,
+    pub dps: f64,
+    /// Sorted by damage done, highest first
+    pub players: Vec<PlayerPhase>,
+    pub deaths: Vec<PhaseDeath>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightPhases {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    /// The boss the phases follow, `None` for fights without a boss
+    pub monster_id: Option<u32>,
+    /// Empty for fights without a boss
+    pub phases: Vec<Phase>,
+}
+
+enum Change<'r> {
+    Begin(&'r PhaseRule),
+    Invulnerable(&'r PhaseRule),
+    Resumed,
+}
+
+/// Collects what is needed to split the fight currently in progress into phases.
+/// Phases are only worked out when the fight ends, as a stretch without damage to the boss is only known once it is over.
+#[derive(Debug)]
+pub struct PhaseAccumulator<'a> {
+    definitions: &'a PhaseDefinitions,
+    signature_abilities: HashSet<u32>,
+    /// Time, player and damage done of each hit by a player on a unit other than a player
+    damage: Vec<(u64, u32, u64)>,
+    /// Times each boss, by monster id, took damage
+    boss_hits: HashMap<u32, Vec<u64>>,
+    /// New lows of each boss's health percentage, by monster id
+    boss_lows: HashMap<u32, Vec<(u64, f64)>>,
+    /// First use of each signature ability
+    ability_uses: HashMap<u32, u64>,
+    deaths: Vec<PhaseDeath>,
+    dead: HashSet<u32>,
+}
+
+impl<'a> PhaseAccumulator<'a> {
+    pub fn new(definitions: &'a PhaseDefinitions) -> Self {
+        Self {
+            definitions,
+            signature_abilities: definitions.ability_ids(),
+            damage: Vec::new(),
+            boss_hits: HashMap::new(),
+            boss_lows: HashMap::new(),
+            ability_uses: HashMap::new(),
+            deaths: Vec::new(),
+            dead: HashSet::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event, session: &Session) {
+        self.boss_health(&event.source_unit_state, event.time, session);
+        self.boss_health(&event.target_unit_state, event.time, session);
+        self.ability_used(event.ability_id, event.source_unit_state.unit_id, event.time, session);
+
+        let target = event.target_unit_state.unit_id;
+        if let Some(player) = session.player(target) {
+            if matches!(event.result, EventResult::Resurrect | EventResult::SoulGemResurrectionAccepted) {
+                self.dead.remove(&target);
+            } else if is_death_event(event.result) && self.dead.insert(target) {
+                self.deaths.push(PhaseDeath { unit_id: target, name: player.name.clone(), time: event.time });
+            }
+        }
+
+        if !is_damage_event(event.result) || event.hit_value == 0 {return}
+        if let Some(SessionUnit::Other(unit)) = session.unit(target)
+            && unit.is_boss {
+            self.boss_hits.entry(unit.monster_id).or_default().push(event.time);
+        }
+        let source = session.owner(event.source_unit_state.unit_id);
+        if session.player(source).is_some() && session.player(session.owner(target)).is_none() {
+            self.damage.push((event.time, source, event.hit_value as u64));
+        }
+    }
+
+    pub fn ability_used(&mut self, ability_id: u32, source_unit_id: u32, time: u64, session: &Session) {
+        if !self.signature_abilities.contains(&ability_id) || session.player(session.owner(source_unit_id)).is_some() {return}
+        self.ability_uses.entry(ability_id).or_insert(time);
+    }
+
+    fn boss_health(&mut self, state: &UnitState, time: u64, session: &Session) {
+        let Some(SessionUnit::Other(unit)) = session.unit(state.unit_id) else {return};
+        if !unit.is_boss || state.max_health == 0 {return}
+        let percent = state.health as f64 * 100.0 / state.max_health as f64;
+        let lows = self.boss_lows.entry(unit.monster_id).or_default();
+        if lows.last().is_none_or(|(_, low)| percent < *low) {
+            lows.push((time, percent));
+        }
+    }
+
+    /// Splits `fight` into phases and clears the accumulator for the next one.
+    pub fn finish(&mut self, fight: &Fight, session: &Session) -> FightPhases {
+        let boss = fight.bosses.iter().find(|b| self.definitions.has_rules(b.monster_id, &b.name))
+            .or(fight.bosses.first());
+        let phases = boss.map(|boss| self.phases(fight, boss, session)).unwrap_or_default();
+
+        self.damage.clear();
+        self.boss_hits.clear();
+        self.boss_lows.clear();
+        self.ability_uses.clear();
+        self.deaths.clear();
+        self.dead.clear();
+
+        FightPhases {
+            fight: FightHeader::from(fight),
+            monster_id: boss.map(|b| b.monster_id),
+            phases,
+        }
+    }
+
+    fn phases(&self, fight: &Fight, boss: &FightBoss, session: &Session) -> Vec<Phase> {
+        let monster_id = boss.monster_id;
+        let rules = self.definitions.rules(monster_id, &boss.name);
+        let lows = self.boss_lows.get(&monster_id).map_or(&[][..], Vec::as_slice);
+
+        // Points where the phase named by a rule begins
+        let mut starts: Vec<(u64, &PhaseRule)> = rules.iter().filter_map(|rule| {
+            let time = match rule.trigger {
+                PhaseTrigger::Start => Some(fight.start_time),
+                PhaseTrigger::Health { percent } => lows.iter().find(|(_, low)| *low <= percent).map(|(time, _)| *time),
+                PhaseTrigger::Ability { ability_id } => self.ability_uses.get(&ability_id).copied(),
+                PhaseTrigger::Invulnerable { .. } | PhaseTrigger::Resumed => None,
+            };
+            Some((time?, rule))
+        }).collect();
+        starts.sort_by_key(|(time, _)| *time);
+
+        // Stretches between two hits on the boss long enough to count as the first invulnerable rule
+        let mut gaps = Vec::new();
+        if let Some((gap, rule)) = rules.iter().find_map(|rule| match rule.trigger {
+            PhaseTrigger::Invulnerable { gap } => Some((gap, rule)),
+            _ => None,
+        }) {
+            let hits = self.boss_hits.get(&monster_id).map_or(&[][..], Vec::as_slice);
+            let mut stretches: Vec<(u64, u64)> = Vec::new();
+            for pair in hits.windows(2).filter(|pair| pair[1].saturating_sub(pair[0]) >= gap) {
+                // A single hit between two stretches does not end the invulnerable phase
+                match stretches.last_mut() {
+                    Some((_, to)) if *to == pair[0] => *to = pair[1],
+                    _ => stretches.push((pair[0], pair[1])),
+                }
+            }
+            for (from, to) in stretches {
+                // The last hit before a stretch belongs to the phase before it
+                gaps.push((from + 1, Change::Invulnerable(rule)));
+                gaps.push((to, Change::Resumed));
+            }
+        }
+        let mut changes: Vec<(u64, Change)> = starts.into_iter().map(|(time, rule)| (time, Change::Begin(rule))).chain(gaps).collect();
+        changes.sort_by_key(|(time, _)| *time);
+
+        let mut boundaries: Vec<(u64, String, PhaseTrigger)> = Vec::new();
+        let mut begin = |time: u64, name: &str, trigger: PhaseTrigger| {
+            if boundaries.last().is_some_and(|(last, _, _)| *last == time) {
+                boundaries.pop();
+            }
+            boundaries.push((time, name.to_owned(), trigger));
+        };
+        let mut current = "";
+        let mut invulnerable = false;
+        for (time, change) in changes {
+            match change {
+                Change::Begin(rule) => {
+                    current = &rule.name;
+                    if !invulnerable {
+                        begin(time, &rule.name, rule.trigger);
+                    }
+                }
+                Change::Invulnerable(rule) => {
+                    invulnerable = true;
+                    begin(time, &rule.name, rule.trigger);
+                }
+                Change::Resumed => {
+                    invulnerable = false;
+                    begin(time, current, PhaseTrigger::Resumed);
+                }
+            }
+        }
+        if boundaries.first().is_none_or(|(time, _, _)| *time > fight.start_time) {
+            boundaries.insert(0, (fight.start_time, String::new(), PhaseTrigger::Start));
+        }
+        boundaries.retain(|(time, _, _)| *time <= fight.end_time);
+
+        boundaries.iter().enumerate().map(|(i, (start_time, name, trigger))| {
+            let end_time = boundaries.get(i + 1).map_or(fight.end_time, |next| next.0);
+            let last = i + 1 == boundaries.len();
+            let within = |time: u64| time >= *start_time && (time < end_time || (last && time <= end_time));
+            // Phases without a rule naming them are numbered
+            let name = if name.is_empty() { format!("Phase {}", i + 1) } else { name.clone() };
+            self.phase(name, *trigger, *start_time, end_time, within, session)
+        }).collect()
+    }
+
+    fn phase(&self, name: String, trigger: PhaseTrigger, start_time: u64, end_time: u64, within: impl Fn(u64) -> bool, session: &Session) -> Phase {
+        let seconds = end_time.saturating_sub(start_time) as f64 / 1000.0;
+        let per_second = |damage: u64| if seconds > 0.0 { damage as f64 / seconds } else { 0.0 };
+
+        let mut damage: HashMap<u32, u64> = HashMap::new();
+        for &(time, player, amount) in &self.damage {
+            if within(time) {
+                *damage.entry(player).or_default() += amount;
+            }
+        }
+        let mut players: Vec<PlayerPhase> = damage.into_iter().map(|(unit_id, damage_done)| {
+            let (name, display_name) = session.player(unit_id).map(|p| (p.name.clone(), p.display_name.clone())).unwrap_or_default();
+            PlayerPhase { unit_id, name, display_name, damage_done, dps: per_second(damage_done) }
+        }).collect();
+        players.sort_by(|a, b| b.damage_done.cmp(&a.damage_done).then(a.name.cmp(&b.name)));
+        let damage_done = players.iter().map(|p| p.damage_done).sum();
+
+        Phase {
+            name,
+            trigger,
+            start_time,
+            end_time,
+            damage_done,
+            dps: per_second(damage_done),
+            players,
+            deaths: self.deaths.iter().filter(|d| within(d.time)).cloned().collect(),
+        }
+    }
+}
+
+/// Splits every boss fight of a log into phases using `definitions`, with the damage done and deaths in each phase.
+pub fn fight_phases<R: BufRead>(reader: R, definitions: &PhaseDefinitions) -> io::Result<Vec<FightPhases>> {
+    summarise_fights(reader, PhaseAccumulator::new(definitions), |accumulator, line, tracker| {
+        if tracker.current_fight().is_none() {return}
+        match line {
+            LogLine::CombatEvent(event) => accumulator.handle_event(event, &tracker.session),
+            LogLine::BeginCast(cast) => accumulator.ability_used(cast.ability_id, cast.source_unit_state.unit_id, cast.time, &tracker.session),
+            _ => {}
+        }
+    }, PhaseAccumulator::finish)
+}
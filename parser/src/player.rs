@@ -2,9 +2,10 @@ use std::fmt;
 
 use esosim::{data::item_type::{EnchantType, GearSlot, GearTrait, ItemQuality}, models::player::{GearPiece, Loadout}};
 
-use crate::{effect::Ability, unit::UnitState};
+use crate::{effect::Ability, parse, unit::UnitState};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Player {
     pub unit_id: u32,
     pub is_local_player: bool,
@@ -19,7 +20,12 @@ pub struct Player {
     pub is_grouped_with_local_player: bool,
     pub unit_state: UnitState,
     pub effects: Vec<u32>,
+    /// Left out of serialized players, the esosim loadout has no serialized form. `gear_pieces` is serialized in its place
+    #[serde(skip)]
     pub gear: Loadout,
+    /// The pieces of `gear`, as the log gives them
+    #[serde(rename = "gear")]
+    pub gear_pieces: Vec<GearView>,
     pub primary_abilities: Vec<Ability>,
     pub backup_abilities: Vec<Ability>,
 }
@@ -28,13 +34,57 @@ impl Player {
     pub fn insert_gear_piece(&mut self, slot: &GearSlot, gear_piece: GearPiece) {
         self.gear.set_gear_piece(slot, gear_piece);
     }
+
+    /// Replaces the player's gear with the `<equipmentInfo>` entries of a PLAYER_INFO line
+    pub fn set_gear(&mut self, pieces: &[&str]) {
+        self.gear = empty_loadout();
+        self.gear_pieces.clear();
+        for piece in pieces {
+            if let Ok(Some((gear_piece, slot))) = parse::gear_piece(piece) {
+                self.insert_gear_piece(&slot, gear_piece);
+                self.gear_pieces.extend(GearView::parse(piece));
+            }
+        }
+    }
+}
+
+/// A piece of gear as serialized players show it
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct GearView {
+    /// As the log names it, e.g. `MAIN_HAND`
+    pub slot: String,
+    pub item_id: u32,
+    pub set_id: u16,
+    /// As the log names it, e.g. `ARMOR_DIVINES`
+    pub gear_trait: Option<String>,
+    /// As the log names it, e.g. `LEGENDARY`
+    pub quality: String,
+    /// As the log names it, e.g. `INCREASE_SPELL_DAMAGE`
+    pub enchant: Option<String>,
+}
+
+impl GearView {
+    /// Reads one `<equipmentInfo>` entry of a PLAYER_INFO line
+    fn parse(text: &str) -> Option<Self> {
+        let fields: Vec<&str> = text.split(',').collect();
+        if fields.len() < 10 {return None}
+        let known = |value: &&str| !value.is_empty() && *value != "NONE" && *value != "INVALID";
+        Some(Self {
+            slot: fields[0].to_owned(),
+            item_id: fields[1].parse().unwrap_or(0),
+            set_id: fields[6].parse().unwrap_or(0),
+            gear_trait: Some(fields[4]).filter(known).map(str::to_owned),
+            quality: fields[5].to_owned(),
+            enchant: Some(fields[7]).filter(known).map(str::to_owned),
+        })
+    }
 }
 
 pub fn empty_loadout() -> Loadout {
     Loadout::default()
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum Class {
     Dragonknight,
     Sorcerer,
@@ -75,7 +125,7 @@ pub fn match_class(string: &str) -> Class {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Race {
     None,
     Breton,
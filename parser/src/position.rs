@@ -0,0 +1,196 @@
+use std::{collections::{HashMap, HashSet}, io::{self, BufRead}};
+
+use serde::Serialize;
+
+use crate::{event::{Event, EventResult, is_death_event}, fights::{Fight, FightHeader, summarise_fights}, line::LogLine, session::{Session, SessionMap, SessionUnit}, unit::UnitState};
+
+/// Where a unit was at one point in a fight, recorded whenever it moves or turns.
+/// Coordinates are fractions of the map's width and height, from its top left corner.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct PositionSample {
+    /// Milliseconds since logging began
+    pub time: u64,
+    pub x: f32,
+    pub y: f32,
+    /// Radians
+    pub heading: f32,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct UnitTrack {
+    pub unit_id: u32,
+    pub name: String,
+    /// Account name, empty for units other than players
+    pub display_name: String,
+    pub is_player: bool,
+    pub is_boss: bool,
+    pub samples: Vec<PositionSample>,
+}
+
+impl UnitTrack {
+    /// Where the unit was at `time`, going by the last sample at or before it.
+    pub fn position_at(&self, time: u64) -> Option<&PositionSample> {
+        let after = self.samples.partition_point(|s| s.time <= time);
+        after.checked_sub(1).map(|i| &self.samples[i])
+    }
+
+    /// Total distance moved, in map coordinates.
+    pub fn distance_travelled(&self) -> f64 {
+        self.samples.windows(2).map(|pair| distance(&pair[0], &pair[1])).sum()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct DeathPosition {
+    pub unit_id: u32,
+    pub name: String,
+    pub time: u64,
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct DistanceSample {
+    pub time: u64,
+    /// In map coordinates
+    pub distance: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightPositions {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    /// The map the fight began on
+    pub map: Option<SessionMap>,
+    /// Players first, then bosses, then other units, each sorted by name
+    pub tracks: Vec<UnitTrack>,
+    /// Where players and bosses died
+    pub deaths: Vec<DeathPosition>,
+}
+
+impl FightPositions {
+    /// Tracks of units with this name, ignoring case, or players with this account name.
+    pub fn tracks_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a UnitTrack> {
+        self.tracks.iter().filter(move |t| t.name.eq_ignore_ascii_case(name) || (t.is_player && t.display_name.eq_ignore_ascii_case(name)))
+    }
+
+    /// Distance between two units every `step` milliseconds through the fight, while both have a known position.
+    pub fn distances(&self, a: &UnitTrack, b: &UnitTrack, step: u64) -> Vec<DistanceSample> {
+        (self.fight.start_time..=self.fight.end_time)
+            .step_by(step.max(1) as usize)
+            .filter_map(|time| {
+                let (a, b) = (a.position_at(time)?, b.position_at(time)?);
+                Some(DistanceSample { time, distance: distance(a, b) })
+            })
+            .collect()
+    }
+}
+
+pub fn distance(a: &PositionSample, b: &PositionSample) -> f64 {
+    (a.x as f64 - b.x as f64).hypot(a.y as f64 - b.y as f64)
+}
+
+/// Records the position of every unit in the fight currently in progress.
+#[derive(Debug, Default)]
+pub struct PositionAccumulator {
+    tracks: HashMap<u32, Vec<PositionSample>>,
+    deaths: Vec<DeathPosition>,
+    /// Units that died and have not been resurrected, so the several death results logged for one death count once
+    dead: HashSet<u32>,
+    map: Option<SessionMap>,
+}
+
+impl PositionAccumulator {
+    /// Records where every player was when the fight began.
+    pub fn start(&mut self, session: &Session, time: u64) {
+        self.tracks.clear();
+        self.deaths.clear();
+        self.dead.clear();
+        self.map = session.map.clone();
+        for player in session.players() {
+            self.update(&player.unit_state, time);
+        }
+    }
+
+    /// Call with every unit state a line carries. States without a position are ignored.
+    pub fn update(&mut self, state: &UnitState, time: u64) {
+        if state.unit_id == 0 || (state.map_x == 0.0 && state.map_y == 0.0) {return}
+        let sample = PositionSample { time, x: state.map_x, y: state.map_y, heading: state.heading };
+        let track = self.tracks.entry(state.unit_id).or_default();
+        if track.last().is_some_and(|last| last.x == sample.x && last.y == sample.y && last.heading == sample.heading) {return}
+        track.push(sample);
+    }
+
+    pub fn handle_event(&mut self, event: &Event, session: &Session) {
+        let target = &event.target_unit_state;
+        if matches!(event.result, EventResult::Resurrect | EventResult::SoulGemResurrectionAccepted) {
+            self.dead.remove(&target.unit_id);
+        } else if is_death_event(event.result) && self.dead.insert(target.unit_id) {
+            self.died(target, event.time, session);
+        }
+    }
+
+    fn died(&mut self, state: &UnitState, time: u64, session: &Session) {
+        let Some(unit) = session.unit(state.unit_id) else {return};
+        let is_boss = matches!(unit, SessionUnit::Other(u) if u.is_boss);
+        if !is_boss && unit.as_player().is_none() {return}
+        let Some(last) = self.tracks.get(&state.unit_id).and_then(|t| t.last()) else {return};
+        self.deaths.push(DeathPosition { unit_id: state.unit_id, name: unit.name().to_owned(), time, x: last.x, y: last.y });
+    }
+
+    /// Produces the tracks for `fight` and clears the accumulator for the next one.
+    pub fn finish(&mut self, fight: &Fight, session: &Session) -> FightPositions {
+        self.dead.clear();
+        let mut tracks: Vec<UnitTrack> = self.tracks.drain().map(|(unit_id, samples)| {
+            let player = fight.players.iter().find(|p| p.unit_id == unit_id);
+            let boss = fight.bosses.iter().find(|b| b.unit_id == unit_id);
+            let session_player = session.player(unit_id);
+            UnitTrack {
+                unit_id,
+                display_name: player.map(|p| p.display_name.clone())
+                    .or_else(|| session_player.map(|p| p.display_name.clone()))
+                    .unwrap_or_default(),
+                name: player.map(|p| p.name.clone())
+                    .or_else(|| boss.map(|b| b.name.clone()))
+                    .or_else(|| session.unit(unit_id).map(|u| u.name().to_owned()))
+                    .unwrap_or_default(),
+                is_player: player.is_some() || session_player.is_some(),
+                is_boss: boss.is_some(),
+                samples,
+            }
+        }).collect();
+        tracks.sort_by(|a, b| b.is_player.cmp(&a.is_player).then(b.is_boss.cmp(&a.is_boss)).then(a.name.cmp(&b.name)).then(a.unit_id.cmp(&b.unit_id)));
+
+        FightPositions {
+            fight: FightHeader::from(fight),
+            map: self.map.take(),
+            tracks,
+            deaths: std::mem::take(&mut self.deaths),
+        }
+    }
+}
+
+/// Tracks the position of every unit in every fight of a log.
+pub fn fight_positions<R: BufRead>(reader: R) -> io::Result<Vec<FightPositions>> {
+    summarise_fights(reader, PositionAccumulator::default(), |accumulator, line, tracker| {
+        if tracker.current_fight().is_none() {return}
+        match line {
+            LogLine::BeginCombat { time } => accumulator.start(&tracker.session, *time),
+            LogLine::CombatEvent(event) => {
+                accumulator.update(&event.source_unit_state, event.time);
+                accumulator.update(&event.target_unit_state, event.time);
+                accumulator.handle_event(event, &tracker.session);
+            }
+            LogLine::BeginCast(cast) => {
+                accumulator.update(&cast.source_unit_state, cast.time);
+                accumulator.update(&cast.target_unit_state, cast.time);
+            }
+            LogLine::EffectChanged(effect) => {
+                accumulator.update(&effect.source_unit_state, effect.time);
+                accumulator.update(&effect.target_unit_state, effect.time);
+            }
+            LogLine::HealthRegen(regen) => accumulator.update(&regen.unit_state, regen.time),
+            _ => {}
+        }
+    }, PositionAccumulator::finish)
+}
@@ -0,0 +1,264 @@
+use std::{collections::HashMap, io::{self, BufRead}};
+
+use serde::Serialize;
+
+use crate::{event::{Event, EventResult}, fights::{Fight, FightHeader, summarise_fights}, line::LogLine, session::{Session, SessionUnit}, unit::UnitState};
+
+pub const POWER_TYPE_MAGICKA: u32 = 1;
+pub const POWER_TYPE_STAMINA: u32 = 4;
+pub const POWER_TYPE_ULTIMATE: u32 = 8;
+
+/// Fraction of the primary resource below which a player counts as running out
+pub const LOW_RESOURCE: f64 = 0.1;
+
+/// The smallest drop in ultimate counted as casting an ultimate, as the cheapest ultimates cost 70
+const MIN_ULTIMATE_COST: u32 = 50;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum Resource {
+    Magicka,
+    Stamina,
+}
+
+/// A player's resources at one point in a fight, recorded whenever any of them changes.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct ResourceSample {
+    /// Milliseconds since logging began
+    pub time: u64,
+    pub magicka: u32,
+    pub max_magicka: u32,
+    pub stamina: u32,
+    pub max_stamina: u32,
+    pub ultimate: u32,
+    pub max_ultimate: u32,
+}
+
+impl ResourceSample {
+    fn from_state(state: &UnitState, time: u64) -> Self {
+        Self {
+            time,
+            magicka: state.magicka,
+            max_magicka: state.max_magicka,
+            stamina: state.stamina,
+            max_stamina: state.max_stamina,
+            ultimate: state.ultimate,
+            max_ultimate: state.max_ultimate,
+        }
+    }
+
+    fn same_values(&self, other: &Self) -> bool {
+        Self { time: other.time, ..*self } == *other
+    }
+
+    fn fraction(&self, resource: Resource) -> f64 {
+        let (value, max) = match resource {
+            Resource::Magicka => (self.magicka, self.max_magicka),
+            Resource::Stamina => (self.stamina, self.max_stamina),
+        };
+        if max == 0 { 0.0 } else { value as f64 / max as f64 }
+    }
+
+    fn is_low(&self, resource: Resource) -> bool {
+        self.fraction(resource) < LOW_RESOURCE
+    }
+
+    fn ultimate_full(&self) -> bool {
+        self.max_ultimate > 0 && self.ultimate >= self.max_ultimate
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Period {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PlayerResources {
+    pub unit_id: u32,
+    pub name: String,
+    pub display_name: String,
+    /// The resource with the larger pool
+    pub primary_resource: Resource,
+    /// Time-weighted averages, as fractions of the maximum
+    pub average_magicka: f64,
+    pub average_stamina: f64,
+    /// Restored by POWER_ENERGIZE events, per second of the fight
+    pub magicka_restored_per_second: f64,
+    pub stamina_restored_per_second: f64,
+    /// Milliseconds with the primary resource below [`LOW_RESOURCE`], and when
+    pub low_resource_ms: u64,
+    pub low_resource_periods: Vec<Period>,
+    /// Milliseconds with a full ultimate bar, when any ultimate generated is wasted
+    pub max_ultimate_ms: u64,
+    /// Ultimate gained and spent, from changes in the ultimate bar
+    pub ultimate_generated: u32,
+    pub ultimate_spent: u32,
+    pub ultimate_casts: u32,
+    pub samples: Vec<ResourceSample>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightResources {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    /// Sorted by name
+    pub players: Vec<PlayerResources>,
+}
+
+#[derive(Debug, Default)]
+struct PlayerSeries {
+    samples: Vec<ResourceSample>,
+    magicka_restored: u64,
+    stamina_restored: u64,
+}
+
+/// Records the resources of every player in the fight currently in progress.
+#[derive(Debug, Default)]
+pub struct ResourceAccumulator {
+    players: HashMap<u32, PlayerSeries>,
+}
+
+impl ResourceAccumulator {
+    /// Records the resources every player had when the fight began.
+    pub fn start(&mut self, session: &Session, time: u64) {
+        self.players.clear();
+        for player in session.players() {
+            self.update(session, &player.unit_state, time);
+        }
+    }
+
+    /// Call with every unit state a line carries.
+    pub fn update(&mut self, session: &Session, state: &UnitState, time: u64) {
+        if session.player(state.unit_id).is_none() || (state.max_magicka == 0 && state.max_stamina == 0) {return}
+        let sample = ResourceSample::from_state(state, time);
+        let series = self.players.entry(state.unit_id).or_default();
+        if series.samples.last().is_some_and(|last| last.same_values(&sample)) {return}
+        series.samples.push(sample);
+    }
+
+    pub fn handle_event(&mut self, event: &Event, session: &Session) {
+        if event.result != EventResult::PowerEnergize || session.player(event.target_unit_state.unit_id).is_none() {return}
+        let series = self.players.entry(event.target_unit_state.unit_id).or_default();
+        match event.power_type {
+            POWER_TYPE_MAGICKA => series.magicka_restored += event.hit_value as u64,
+            POWER_TYPE_STAMINA => series.stamina_restored += event.hit_value as u64,
+            _ => {}
+        }
+    }
+
+    /// Produces the resource report for `fight` and clears the accumulator for the next one.
+    pub fn finish(&mut self, fight: &Fight, session: &Session) -> FightResources {
+        let seconds = fight.duration() as f64 / 1000.0;
+        let mut players: Vec<PlayerResources> = self.players.drain()
+            .filter(|(_, series)| !series.samples.is_empty())
+            .map(|(unit_id, series)| {
+                let (name, display_name) = match session.unit(unit_id) {
+                    Some(SessionUnit::Player(p)) => (p.name.clone(), p.display_name.clone()),
+                    _ => fight.players.iter().find(|p| p.unit_id == unit_id)
+                        .map(|p| (p.name.clone(), p.display_name.clone()))
+                        .unwrap_or_default(),
+                };
+                let per_second = |restored: u64| if seconds > 0.0 { restored as f64 / seconds } else { 0.0 };
+                let mut resources = summarise(&series.samples, fight.end_time);
+                resources.unit_id = unit_id;
+                resources.name = name;
+                resources.display_name = display_name;
+                resources.magicka_restored_per_second = per_second(series.magicka_restored);
+                resources.stamina_restored_per_second = per_second(series.stamina_restored);
+                resources.samples = series.samples;
+                resources
+            })
+            .collect();
+        players.sort_by(|a, b| a.name.cmp(&b.name));
+
+        FightResources {
+            fight: FightHeader::from(fight),
+            players,
+        }
+    }
+}
+
+/// Works out the statistics of a series of samples, each holding until the next one or `end_time`.
+fn summarise(samples: &[ResourceSample], end_time: u64) -> PlayerResources {
+    let max_magicka = samples.iter().map(|s| s.max_magicka).max().unwrap_or(0);
+    let max_stamina = samples.iter().map(|s| s.max_stamina).max().unwrap_or(0);
+    let primary_resource = if max_magicka >= max_stamina { Resource::Magicka } else { Resource::Stamina };
+
+    let mut resources = PlayerResources {
+        unit_id: 0,
+        name: String::new(),
+        display_name: String::new(),
+        primary_resource,
+        average_magicka: 0.0,
+        average_stamina: 0.0,
+        magicka_restored_per_second: 0.0,
+        stamina_restored_per_second: 0.0,
+        low_resource_ms: 0,
+        low_resource_periods: Vec::new(),
+        max_ultimate_ms: 0,
+        ultimate_generated: 0,
+        ultimate_spent: 0,
+        ultimate_casts: 0,
+        samples: Vec::new(),
+    };
+
+    let mut total_ms = 0;
+    let (mut magicka_ms, mut stamina_ms) = (0.0, 0.0);
+    for (i, sample) in samples.iter().enumerate() {
+        let until = samples.get(i + 1).map_or(end_time, |next| next.time).max(sample.time);
+        let held = until - sample.time;
+        total_ms += held;
+        magicka_ms += sample.fraction(Resource::Magicka) * held as f64;
+        stamina_ms += sample.fraction(Resource::Stamina) * held as f64;
+        if sample.ultimate_full() {
+            resources.max_ultimate_ms += held;
+        }
+        if sample.is_low(primary_resource) {
+            resources.low_resource_ms += held;
+            match resources.low_resource_periods.last_mut() {
+                Some(period) if period.end == sample.time => period.end = until,
+                _ => resources.low_resource_periods.push(Period { start: sample.time, end: until }),
+            }
+        }
+        if let Some(previous) = i.checked_sub(1).map(|j| &samples[j]) {
+            if sample.ultimate > previous.ultimate {
+                resources.ultimate_generated += sample.ultimate - previous.ultimate;
+            } else if previous.ultimate - sample.ultimate >= MIN_ULTIMATE_COST {
+                resources.ultimate_spent += previous.ultimate - sample.ultimate;
+                resources.ultimate_casts += 1;
+            }
+        }
+    }
+    if total_ms > 0 {
+        resources.average_magicka = magicka_ms / total_ms as f64;
+        resources.average_stamina = stamina_ms / total_ms as f64;
+    }
+    resources
+}
+
+/// Tracks the magicka, stamina and ultimate of every player in every fight of a log.
+pub fn fight_resources<R: BufRead>(reader: R) -> io::Result<Vec<FightResources>> {
+    summarise_fights(reader, ResourceAccumulator::default(), |accumulator, line, tracker| {
+        if tracker.current_fight().is_none() {return}
+        let session = &tracker.session;
+        match line {
+            LogLine::BeginCombat { time } => accumulator.start(session, *time),
+            LogLine::CombatEvent(event) => {
+                accumulator.update(session, &event.source_unit_state, event.time);
+                accumulator.update(session, &event.target_unit_state, event.time);
+                accumulator.handle_event(event, session);
+            }
+            LogLine::BeginCast(cast) => {
+                accumulator.update(session, &cast.source_unit_state, cast.time);
+                accumulator.update(session, &cast.target_unit_state, cast.time);
+            }
+            LogLine::EffectChanged(effect) => {
+                accumulator.update(session, &effect.source_unit_state, effect.time);
+                accumulator.update(session, &effect.target_unit_state, effect.time);
+            }
+            LogLine::HealthRegen(regen) => accumulator.update(session, &regen.unit_state, regen.time),
+            _ => {}
+        }
+    }, ResourceAccumulator::finish)
+}
@@ -0,0 +1,263 @@
+use std::{collections::HashMap, io::{self, BufRead}};
+
+use serde::Serialize;
+
+use crate::{event::{Cast, CastEndReason, Event, EventResult}, fights::{Fight, FightHeader, summarise_fights}, line::{EndCast, LogLine}, session::{Session, SessionUnit}};
+
+/// The "Swap Weapons" ability players cast to change bars
+pub const SWAP_WEAPONS_ID: u32 = 28541;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum Bar {
+    Front,
+    Back,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TimelineCast {
+    /// Milliseconds since logging began
+    pub time: u64,
+    pub ability_id: u32,
+    pub name: String,
+    /// Cast or channel time in milliseconds, zero for instant abilities
+    pub cast_time: u32,
+    pub channeled: bool,
+    /// `None` while the END_CAST has not been seen before the fight ended
+    pub end_reason: Option<CastEndReason>,
+    pub end_time: Option<u64>,
+    /// Milliseconds since the previous cast began
+    pub gap: Option<u64>,
+    /// Bar the ability was cast from, when it can be told
+    pub bar: Option<Bar>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct AbilityCasts {
+    pub ability_id: u32,
+    pub name: String,
+    pub casts: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct PlayerRotation {
+    pub unit_id: u32,
+    pub name: String,
+    pub display_name: String,
+    /// Casts other than weapon swaps
+    pub casts: u32,
+    pub casts_per_minute: f64,
+    /// Average milliseconds between the start of one cast and the next
+    pub average_gap: f64,
+    pub cancelled_casts: u32,
+    pub interrupted_casts: u32,
+    /// Casts that ended FAILED, or that the game refused, such as for lack of resources or a target out of range
+    pub failed_casts: u32,
+    pub weapon_swaps: u32,
+    /// Milliseconds on each bar, when casts from a bar's own abilities show which bar the player started on
+    pub front_bar_ms: Option<u64>,
+    pub back_bar_ms: Option<u64>,
+    /// Sorted by casts, most first
+    pub abilities: Vec<AbilityCasts>,
+    pub timeline: Vec<TimelineCast>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightRotations {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    /// Sorted by name
+    pub players: Vec<PlayerRotation>,
+}
+
+#[derive(Debug, Default)]
+struct PlayerCasts {
+    rotation: PlayerRotation,
+    swap_times: Vec<u64>,
+    /// Number of swaps before a cast, and the bar that cast was made from
+    bar_observations: Vec<(usize, Bar)>,
+}
+
+/// Collects the casts of every player in the fight currently in progress.
+#[derive(Debug, Default)]
+pub struct RotationAccumulator {
+    players: HashMap<u32, PlayerCasts>,
+    /// Player and timeline index of each cast waiting for its END_CAST
+    open_casts: HashMap<u32, (u32, usize)>,
+}
+
+impl RotationAccumulator {
+    pub fn begin_cast(&mut self, cast: &Cast, session: &Session) {
+        let unit_id = cast.source_unit_state.unit_id;
+        let Some(SessionUnit::Player(player)) = session.unit(unit_id) else {return};
+        let on_front = player.primary_abilities.iter().any(|a| a.id == cast.ability_id);
+        let on_back = player.backup_abilities.iter().any(|a| a.id == cast.ability_id);
+        let bar = match (on_front, on_back) {
+            (true, false) => Some(Bar::Front),
+            (false, true) => Some(Bar::Back),
+            _ => None,
+        };
+
+        let casts = self.player(unit_id, session);
+        if cast.ability_id == SWAP_WEAPONS_ID {
+            casts.rotation.weapon_swaps += 1;
+            casts.swap_times.push(cast.time);
+            return;
+        }
+        if let Some(bar) = bar {
+            casts.bar_observations.push((casts.swap_times.len(), bar));
+        }
+
+        let timeline = &mut casts.rotation.timeline;
+        let gap = timeline.last().map(|previous| cast.time.saturating_sub(previous.time));
+        timeline.push(TimelineCast {
+            time: cast.time,
+            ability_id: cast.ability_id,
+            name: session.ability(cast.ability_id).map(|a| a.name.to_string()).unwrap_or_default(),
+            cast_time: cast.duration,
+            channeled: cast.channeled,
+            end_reason: None,
+            end_time: None,
+            gap,
+            bar,
+        });
+        let index = timeline.len() - 1;
+        self.open_casts.insert(cast.cast_track_id, (unit_id, index));
+    }
+
+    pub fn end_cast(&mut self, end_cast: &EndCast) {
+        let Some((unit_id, index)) = self.open_casts.remove(&end_cast.cast_track_id) else {return};
+        let Some(casts) = self.players.get_mut(&unit_id) else {return};
+        let rotation = &mut casts.rotation;
+        match end_cast.end_reason {
+            Some(CastEndReason::PlayerCancelled) => rotation.cancelled_casts += 1,
+            Some(CastEndReason::Interrupted) => rotation.interrupted_casts += 1,
+            Some(CastEndReason::Failed) => rotation.failed_casts += 1,
+            _ => {}
+        }
+        if let Some(cast) = rotation.timeline.get_mut(index) {
+            cast.end_reason = end_cast.end_reason;
+            cast.end_time = Some(end_cast.time);
+        }
+    }
+
+    /// Counts abilities the game refused to cast, which are logged as COMBAT_EVENTs rather than casts.
+    pub fn handle_event(&mut self, event: &Event, session: &Session) {
+        let unit_id = event.source_unit_state.unit_id;
+        if !is_refused_cast(event.result) || session.player(unit_id).is_none() {return}
+        self.player(unit_id, session).rotation.failed_casts += 1;
+    }
+
+    fn player(&mut self, unit_id: u32, session: &Session) -> &mut PlayerCasts {
+        self.players.entry(unit_id).or_insert_with(|| {
+            let (name, display_name) = match session.unit(unit_id) {
+                Some(SessionUnit::Player(p)) => (p.name.clone(), p.display_name.clone()),
+                _ => Default::default(),
+            };
+            PlayerCasts { rotation: PlayerRotation { unit_id, name, display_name, ..Default::default() }, ..Default::default() }
+        })
+    }
+
+    /// Produces the rotations for `fight` and clears the accumulator for the next one.
+    pub fn finish(&mut self, fight: &Fight) -> FightRotations {
+        self.open_casts.clear();
+        let duration = fight.duration();
+        let mut players: Vec<PlayerRotation> = self.players.drain().map(|(_, casts)| {
+            let PlayerCasts { mut rotation, swap_times, bar_observations } = casts;
+            rotation.casts = rotation.timeline.len() as u32;
+            if duration > 0 {
+                rotation.casts_per_minute = rotation.casts as f64 * 60_000.0 / duration as f64;
+            }
+            let gaps: Vec<u64> = rotation.timeline.iter().filter_map(|c| c.gap).collect();
+            if !gaps.is_empty() {
+                rotation.average_gap = gaps.iter().sum::<u64>() as f64 / gaps.len() as f64;
+            }
+
+            let mut abilities: HashMap<u32, AbilityCasts> = HashMap::new();
+            for cast in &rotation.timeline {
+                abilities.entry(cast.ability_id)
+                    .or_insert_with(|| AbilityCasts { ability_id: cast.ability_id, name: cast.name.clone(), casts: 0 })
+                    .casts += 1;
+            }
+            rotation.abilities = abilities.into_values().collect();
+            rotation.abilities.sort_by(|a, b| b.casts.cmp(&a.casts).then(a.ability_id.cmp(&b.ability_id)));
+
+            if let Some(starting_bar) = starting_bar(&bar_observations) {
+                let (front, back) = time_on_bars(starting_bar, &swap_times, fight.start_time, fight.end_time);
+                rotation.front_bar_ms = Some(front);
+                rotation.back_bar_ms = Some(back);
+                // Once the starting bar is known, every cast's bar follows from the swaps before it
+                let mut swaps = swap_times.iter().peekable();
+                let mut bar = starting_bar;
+                for cast in &mut rotation.timeline {
+                    while swaps.next_if(|&&swap| swap <= cast.time).is_some() {
+                        bar = other_bar(bar);
+                    }
+                    cast.bar = Some(bar);
+                }
+            }
+            rotation
+        }).collect();
+        players.sort_by(|a, b| a.name.cmp(&b.name));
+
+        FightRotations {
+            fight: FightHeader::from(fight),
+            players,
+        }
+    }
+}
+
+fn is_refused_cast(event_result: EventResult) -> bool {
+    matches!(event_result, EventResult::InsufficientResource | EventResult::AbilityOnCooldown | EventResult::CannotUse
+        | EventResult::BadTarget | EventResult::TargetOutOfRange | EventResult::TargetNotInView | EventResult::CantSeeTarget
+        | EventResult::FailedRequirements | EventResult::Failed)
+}
+
+fn other_bar(bar: Bar) -> Bar {
+    match bar {
+        Bar::Front => Bar::Back,
+        Bar::Back => Bar::Front,
+    }
+}
+
+/// The bar a player started the fight on, going by the most common answer among the casts that show their bar.
+fn starting_bar(observations: &[(usize, Bar)]) -> Option<Bar> {
+    let front_votes = observations.iter()
+        .filter(|(swaps, bar)| (*bar == Bar::Front) == swaps.is_multiple_of(2))
+        .count();
+    let back_votes = observations.len() - front_votes;
+    match front_votes.cmp(&back_votes) {
+        std::cmp::Ordering::Greater => Some(Bar::Front),
+        std::cmp::Ordering::Less => Some(Bar::Back),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// Milliseconds spent on the front and back bars from `start` to `end`, swapping at each of `swap_times`.
+fn time_on_bars(starting_bar: Bar, swap_times: &[u64], start: u64, end: u64) -> (u64, u64) {
+    let (mut front, mut back) = (0, 0);
+    let mut bar = starting_bar;
+    let mut since = start;
+    for &swap in swap_times.iter().chain(std::iter::once(&end)) {
+        let swap = swap.clamp(since, end);
+        match bar {
+            Bar::Front => front += swap - since,
+            Bar::Back => back += swap - since,
+        }
+        since = swap;
+        bar = other_bar(bar);
+    }
+    (front, back)
+}
+
+/// Collects the cast timeline of every player in every fight of a log.
+pub fn fight_rotations<R: BufRead>(reader: R) -> io::Result<Vec<FightRotations>> {
+    summarise_fights(reader, RotationAccumulator::default(), |accumulator, line, tracker| {
+        if tracker.current_fight().is_none() {return}
+        match line {
+            LogLine::BeginCast(cast) => accumulator.begin_cast(cast, &tracker.session),
+            LogLine::EndCast(end_cast) => accumulator.end_cast(end_cast),
+            LogLine::CombatEvent(event) => accumulator.handle_event(event, &tracker.session),
+            _ => {}
+        }
+    }, |accumulator, fight, _| accumulator.finish(fight))
+}
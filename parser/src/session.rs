@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use crate::{UnitAddedEventType, effect::{Ability, Effect, EffectChangeType, EffectEvent}, error::ParseError, line::{AbilityInfo, EffectInfo, LogLine, PlayerInfo, UnitAdded, UnitChanged}, parse, player::{self, Player}, unit::{self, Unit, UnitState, UnitType}, zone::DungeonDifficulty};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum SessionUnit {
+    Player(Player),
+    Other(Unit),
+}
+
+impl SessionUnit {
+    pub fn unit_id(&self) -> u32 {
+        match self {
+            SessionUnit::Player(p) => p.unit_id,
+            SessionUnit::Other(u) => u.unit_id,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            SessionUnit::Player(p) => &p.name,
+            SessionUnit::Other(u) => &u.name,
+        }
+    }
+
+    /// Zero for players and for units without an owner
+    pub fn owner_unit_id(&self) -> u32 {
+        match self {
+            SessionUnit::Player(_) => 0,
+            SessionUnit::Other(u) => u.owner_unit_id,
+        }
+    }
+
+    pub fn unit_state(&self) -> &UnitState {
+        match self {
+            SessionUnit::Player(p) => &p.unit_state,
+            SessionUnit::Other(u) => &u.unit_state,
+        }
+    }
+
+    fn unit_state_mut(&mut self) -> &mut UnitState {
+        match self {
+            SessionUnit::Player(p) => &mut p.unit_state,
+            SessionUnit::Other(u) => &mut u.unit_state,
+        }
+    }
+
+    pub fn as_player(&self) -> Option<&Player> {
+        match self {
+            SessionUnit::Player(p) => Some(p),
+            SessionUnit::Other(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ActiveEffect {
+    pub ability_id: u32,
+    pub source_unit_id: u32,
+    pub stack_count: u16,
+    pub cast_track_id: u32,
+    /// Time the effect was first gained, in milliseconds since logging began
+    pub gained_at: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SessionZone {
+    pub id: u16,
+    pub name: String,
+    pub difficulty: DungeonDifficulty,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SessionMap {
+    pub id: u32,
+    pub name: String,
+    pub texture_path: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct CombatPeriod {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Replays log lines into a model of the world as it stands at the most recent line.
+///
+/// Unit ids are only unique within one BEGIN_LOG segment, so all unit, effect and combat state is cleared when a new log begins.
+/// Ability and effect definitions are kept, since the game does not always repeat them.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Milliseconds since logging began, taken from the latest line
+    pub time: u64,
+    /// Unix time in milliseconds of the latest BEGIN_LOG
+    pub log_start_time: Option<u64>,
+    pub realm: Option<String>,
+    pub zone: Option<SessionZone>,
+    pub map: Option<SessionMap>,
+    pub combat_start: Option<u64>,
+    pub combat_periods: Vec<CombatPeriod>,
+    units: HashMap<u32, SessionUnit>,
+    abilities: HashMap<u32, Ability>,
+    effects: HashMap<u32, Effect>,
+    active_effects: HashMap<u32, Vec<ActiveEffect>>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_line(&mut self, line: &str) -> Result<(), ParseError> {
+        let log_line = LogLine::parse(line)?;
+        self.apply(&log_line);
+        Ok(())
+    }
+
+    pub fn apply(&mut self, line: &LogLine) {
+        self.time = line.time();
+        match line {
+            LogLine::BeginLog(begin) => {
+                self.reset();
+                self.log_start_time = Some(begin.log_time);
+                self.realm = Some(begin.realm.to_owned());
+            }
+            LogLine::BeginCombat { time } => self.combat_start = Some(*time),
+            LogLine::EndCombat { time } => {
+                if let Some(start) = self.combat_start.take() {
+                    self.combat_periods.push(CombatPeriod { start, end: *time });
+                }
+            }
+            LogLine::UnitAdded(added) => self.add_unit(added),
+            LogLine::UnitChanged(changed) => self.change_unit(changed),
+            LogLine::UnitRemoved { unit_id, .. } => {
+                self.units.remove(unit_id);
+                self.active_effects.remove(unit_id);
+            }
+            LogLine::PlayerInfo(info) => self.update_player_info(info),
+            LogLine::AbilityInfo(info) => self.add_ability(info),
+            LogLine::EffectInfo(info) => self.add_effect(info),
+            LogLine::CombatEvent(event) => {
+                self.update_unit_state(&event.source_unit_state);
+                self.update_unit_state(&event.target_unit_state);
+            }
+            LogLine::BeginCast(cast) => {
+                self.update_unit_state(&cast.source_unit_state);
+                self.update_unit_state(&cast.target_unit_state);
+            }
+            LogLine::EffectChanged(effect_event) => {
+                self.update_unit_state(&effect_event.source_unit_state);
+                self.update_unit_state(&effect_event.target_unit_state);
+                self.change_effect(effect_event);
+            }
+            LogLine::HealthRegen(regen) => self.update_unit_state(&regen.unit_state),
+            LogLine::MapChanged(map) => {
+                self.map = Some(SessionMap {
+                    id: map.map_id,
+                    name: map.name.to_owned(),
+                    texture_path: map.texture_path.to_owned(),
+                });
+            }
+            LogLine::ZoneChanged(zone) => {
+                self.zone = Some(SessionZone {
+                    id: zone.zone_id,
+                    name: zone.name.to_owned(),
+                    difficulty: zone.difficulty,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.log_start_time = None;
+        self.realm = None;
+        self.zone = None;
+        self.map = None;
+        self.combat_start = None;
+        self.combat_periods.clear();
+        self.units.clear();
+        self.active_effects.clear();
+    }
+
+    pub fn in_combat(&self) -> bool {
+        self.combat_start.is_some()
+    }
+
+    pub fn unit(&self, unit_id: u32) -> Option<&SessionUnit> {
+        self.units.get(&unit_id)
+    }
+
+    pub fn player(&self, unit_id: u32) -> Option<&Player> {
+        self.unit(unit_id).and_then(SessionUnit::as_player)
+    }
+
+    pub fn units(&self) -> impl Iterator<Item = &SessionUnit> {
+        self.units.values()
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &Player> {
+        self.units.values().filter_map(SessionUnit::as_player)
+    }
+
+    pub fn ability(&self, ability_id: u32) -> Option<&Ability> {
+        self.abilities.get(&ability_id)
+    }
+
+    /// EFFECT_INFO definition for an ability
+    pub fn effect(&self, ability_id: u32) -> Option<&Effect> {
+        self.effects.get(&ability_id)
+    }
+
+    pub fn active_effects(&self, unit_id: u32) -> &[ActiveEffect] {
+        self.active_effects.get(&unit_id).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn has_effect(&self, unit_id: u32, ability_id: u32) -> bool {
+        self.active_effects(unit_id).iter().any(|e| e.ability_id == ability_id)
+    }
+
+    /// Follows the ownership chain of pets and other summoned units up to the unit that owns them.
+    /// Returns `unit_id` itself for units without an owner.
+    pub fn owner(&self, unit_id: u32) -> u32 {
+        let mut current = unit_id;
+        // ownership chains are short, the limit only guards against cycles from reused ids
+        for _ in 0..8 {
+            match self.unit(current).map(SessionUnit::owner_unit_id) {
+                Some(owner) if owner != 0 && owner != current => current = owner,
+                _ => break,
+            }
+        }
+        current
+    }
+
+    pub fn pets(&self, owner_unit_id: u32) -> impl Iterator<Item = &SessionUnit> {
+        self.units.values().filter(move |u| u.owner_unit_id() == owner_unit_id && owner_unit_id != 0)
+    }
+
+    fn add_unit(&mut self, added: &UnitAdded) {
+        let unit = match added.unit_type {
+            UnitAddedEventType::Player => SessionUnit::Player(Player {
+                unit_id: added.unit_id,
+                is_local_player: added.is_local_player,
+                player_per_session_id: added.player_per_session_id,
+                class_id: added.class_id,
+                race_id: added.race_id.clone(),
+                name: added.name.to_owned(),
+                display_name: added.display_name.to_owned(),
+                character_id: added.character_id,
+                level: added.level,
+                champion_points: added.champion_points,
+                is_grouped_with_local_player: added.is_grouped_with_local_player,
+                unit_state: unit::blank_unit_state(),
+                effects: Vec::new(),
+                gear: player::empty_loadout(),
+                gear_pieces: Vec::new(),
+                primary_abilities: Vec::new(),
+                backup_abilities: Vec::new(),
+            }),
+            UnitAddedEventType::Monster | UnitAddedEventType::Object | UnitAddedEventType::SiegeWeapon => SessionUnit::Other(Unit {
+                unit_id: added.unit_id,
+                unit_type: if added.unit_type == UnitAddedEventType::Monster { UnitType::Monster } else { UnitType::Object },
+                monster_id: added.monster_id,
+                is_boss: added.is_boss,
+                name: added.name.to_owned(),
+                level: added.level,
+                champion_points: added.champion_points,
+                owner_unit_id: added.owner_unit_id,
+                reaction: added.reaction,
+                unit_state: unit::blank_unit_state(),
+                effects: Vec::new(),
+            }),
+            UnitAddedEventType::Unknown => return,
+        };
+        self.active_effects.remove(&added.unit_id);
+        self.units.insert(added.unit_id, unit);
+    }
+
+    fn change_unit(&mut self, changed: &UnitChanged) {
+        match self.units.get_mut(&changed.unit_id) {
+            Some(SessionUnit::Player(p)) => {
+                p.class_id = changed.class_id;
+                p.race_id = changed.race_id.clone();
+                p.name = changed.name.to_owned();
+                p.display_name = changed.display_name.to_owned();
+                p.character_id = changed.character_id;
+                p.level = changed.level;
+                p.champion_points = changed.champion_points;
+                p.is_grouped_with_local_player = changed.is_grouped_with_local_player;
+            }
+            Some(SessionUnit::Other(u)) => {
+                u.name = changed.name.to_owned();
+                u.level = changed.level;
+                u.champion_points = changed.champion_points;
+                u.owner_unit_id = changed.owner_unit_id;
+                u.reaction = changed.reaction;
+            }
+            None => {}
+        }
+    }
+
+    fn update_player_info(&mut self, info: &PlayerInfo) {
+        let abilities = &self.abilities;
+        let Some(SessionUnit::Player(player)) = self.units.get_mut(&info.unit_id) else {return};
+
+        player.effects = info.long_term_effects().map(|(id, _)| id).collect();
+        player.set_gear(&info.gear);
+        player.primary_abilities = info.primary_ability_ids().filter_map(|id| abilities.get(&id).cloned()).collect();
+        player.backup_abilities = info.backup_ability_ids().filter_map(|id| abilities.get(&id).cloned()).collect();
+    }
+
+    fn add_ability(&mut self, info: &AbilityInfo) {
+        self.abilities.insert(info.ability_id, Ability {
+            id: info.ability_id,
+            name: info.name.into(),
+            icon: parse::icon_file_name(info.icon_path).into(),
+            interruptible: info.interruptible,
+            blockable: info.blockable,
+            scribing: info.scribing.map(|s| s.iter().map(|x| x.to_string()).collect()),
+        });
+    }
+
+    fn add_effect(&mut self, info: &EffectInfo) {
+        let Some(ability) = self.abilities.get(&info.ability_id) else {return};
+        self.effects.insert(info.ability_id, Effect {
+            ability: ability.clone(),
+            stack_count: 0,
+            effect_type: info.effect_type.clone(),
+            status_effect_type: info.status_effect_type.clone(),
+            synergy: info.synergy_ability_id,
+        });
+    }
+
+    fn update_unit_state(&mut self, state: &UnitState) {
+        if state.unit_id == 0 {return}
+        if let Some(unit) = self.units.get_mut(&state.unit_id) {
+            *unit.unit_state_mut() = *state;
+        }
+    }
+
+    fn change_effect(&mut self, effect_event: &EffectEvent) {
+        let target = effect_event.target_unit_state.unit_id;
+        let source = effect_event.source_unit_state.unit_id;
+        let effects = self.active_effects.entry(target).or_default();
+        let existing = effects.iter().position(|e| e.ability_id == effect_event.ability_id && e.source_unit_id == source);
+
+        match (effect_event.change_type, existing) {
+            (EffectChangeType::Faded, Some(index)) => {
+                effects.swap_remove(index);
+            }
+            (EffectChangeType::Gained | EffectChangeType::Updated, Some(index)) => {
+                let effect = &mut effects[index];
+                effect.stack_count = effect_event.stack_count;
+                effect.cast_track_id = effect_event.cast_track_id;
+            }
+            (EffectChangeType::Gained | EffectChangeType::Updated, None) => {
+                effects.push(ActiveEffect {
+                    ability_id: effect_event.ability_id,
+                    source_unit_id: source,
+                    stack_count: effect_event.stack_count,
+                    cast_track_id: effect_event.cast_track_id,
+                    gained_at: effect_event.time,
+                });
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::{self, BufRead};
+
+use serde::Serialize;
+
+use crate::{fights::{Fight, FightOutcome, FightTracker}, line::{LogLine, for_each_line}, zone::{DungeonDifficulty, ZONE_TO_NAME}};
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TrialBoss {
+    pub name: String,
+    pub monster_id: u32,
+    pub pulls: u32,
+    pub wipes: u32,
+    /// Milliseconds from the start of the run to the end of the fight the boss died in
+    pub kill_time: Option<u64>,
+    /// Length of the fight the boss died in, in milliseconds
+    pub kill_duration: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TrialRun {
+    pub trial_id: u32,
+    pub zone_id: Option<u16>,
+    pub zone_name: Option<String>,
+    pub difficulty: Option<DungeonDifficulty>,
+    /// Milliseconds since logging began
+    pub start_time: u64,
+    pub end_time: u64,
+    /// Unix time in milliseconds the run started
+    pub unix_start_time: Option<u64>,
+    /// Whether an END_TRIAL closed the run, rather than leaving the trial or the log ending
+    pub completed: bool,
+    pub success: bool,
+    /// Run length as the game timed it, `None` for runs that were not completed
+    pub duration: Option<u64>,
+    pub final_score: Option<u32>,
+    pub vitality_bonus: Option<u32>,
+    pub fights: u32,
+    pub wipes: u32,
+    pub deaths: u32,
+    /// In the order they were first pulled
+    pub bosses: Vec<TrialBoss>,
+    /// Account names of everyone who took part in a fight, sorted
+    pub players: Vec<String>,
+}
+
+impl TrialRun {
+    fn add_fight(&mut self, fight: &Fight) {
+        self.fights += 1;
+        self.deaths += fight.players.iter().map(|p| p.deaths).sum::<u32>();
+        for player in &fight.players {
+            if !self.players.contains(&player.display_name) {
+                self.players.push(player.display_name.clone());
+            }
+        }
+        if fight.outcome == FightOutcome::Wipe {
+            self.wipes += 1;
+        }
+        let Some(first_boss) = fight.bosses.first() else {return};
+        let boss = match self.bosses.iter().position(|b| b.name == first_boss.name) {
+            Some(i) => &mut self.bosses[i],
+            None => {
+                self.bosses.push(TrialBoss {
+                    name: first_boss.name.clone(),
+                    monster_id: first_boss.monster_id,
+                    pulls: 0,
+                    wipes: 0,
+                    kill_time: None,
+                    kill_duration: None,
+                });
+                self.bosses.last_mut().expect("just pushed")
+            }
+        };
+        boss.pulls += 1;
+        match fight.outcome {
+            FightOutcome::Wipe => boss.wipes += 1,
+            FightOutcome::Kill if boss.kill_time.is_none() => {
+                boss.kill_time = Some(fight.end_time.saturating_sub(self.start_time));
+                boss.kill_duration = Some(fight.duration());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Follows trial runs as lines are fed to it alongside a [`FightTracker`].
+/// A run begins with BEGIN_TRIAL, or a TRIAL_INIT for a trial already in progress when logging began, and ends with END_TRIAL.
+/// A run is also ended, without a score, when the group leaves the trial's zone, another run begins or the log ends.
+#[derive(Debug, Default)]
+pub struct TrialTracker {
+    runs: Vec<TrialRun>,
+    /// Unix time in milliseconds of the BEGIN_LOG each run belongs to
+    log_start_times: Vec<Option<u64>>,
+    current: Option<TrialRun>,
+    log_start_time: Option<u64>,
+}
+
+impl TrialTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call after the fight tracker has handled `line`, except for BEGIN_LOG, which ends the run of the log before it and so comes first.
+    pub fn handle_line(&mut self, line: &LogLine, tracker: &FightTracker) {
+        let session = &tracker.session;
+        match line {
+            LogLine::BeginLog(_) => self.close_run(session.time),
+            LogLine::BeginTrial(begin) => self.open_run(begin.trial_id, begin.time, tracker),
+            LogLine::TrialInit(init) if init.in_progress && !init.completed => self.open_run(init.trial_id, init.time, tracker),
+            LogLine::ZoneChanged(zone) if self.current.as_ref().is_some_and(|run| run.zone_id.is_some_and(|id| id != zone.zone_id)) => {
+                self.close_run(zone.time);
+            }
+            LogLine::EndTrial(end) => {
+                // Logging may have started partway through a run without a TRIAL_INIT, so the run is assumed to have lasted as long as the game says
+                if self.current.as_ref().is_none_or(|run| run.trial_id != end.trial_id) {
+                    self.open_run(end.trial_id, end.time.saturating_sub(end.duration), tracker);
+                }
+                let Some(run) = self.current.as_mut() else {return};
+                run.completed = true;
+                run.success = end.success;
+                run.duration = Some(end.duration);
+                run.final_score = Some(end.final_score);
+                run.vitality_bonus = Some(end.final_vitality_bonus);
+                self.close_run(end.time);
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes a run left open at the end of the input and adds every fight from `fights` to the run it took place in.
+    pub fn finish(mut self, end_time: u64, fights: &[Fight]) -> Vec<TrialRun> {
+        self.close_run(end_time);
+        for fight in fights {
+            let run = self.runs.iter_mut().zip(&self.log_start_times)
+                .find(|(run, log_start_time)| **log_start_time == fight.log_start_time && fight.start_time >= run.start_time && fight.start_time <= run.end_time);
+            if let Some((run, _)) = run {
+                run.add_fight(fight);
+            }
+        }
+        for run in &mut self.runs {
+            run.players.sort();
+        }
+        self.runs
+    }
+
+    fn open_run(&mut self, trial_id: u32, time: u64, tracker: &FightTracker) {
+        self.close_run(time);
+        let session = &tracker.session;
+        self.log_start_time = session.log_start_time;
+        // A run may begin partway through a fight, which then counts towards the run
+        let start_time = tracker.current_fight().map_or(time, |fight| fight.start_time.min(time));
+        self.current = Some(TrialRun {
+            trial_id,
+            zone_id: session.zone.as_ref().map(|z| z.id),
+            zone_name: session.zone.as_ref().map(|z| {
+                ZONE_TO_NAME.get(&z.id).map(|n| n.to_string()).unwrap_or_else(|| z.name.clone())
+            }),
+            difficulty: session.zone.as_ref().map(|z| z.difficulty),
+            start_time,
+            end_time: start_time,
+            unix_start_time: self.log_start_time.map(|t| t + start_time),
+            completed: false,
+            success: false,
+            duration: None,
+            final_score: None,
+            vitality_bonus: None,
+            fights: 0,
+            wipes: 0,
+            deaths: 0,
+            bosses: Vec::new(),
+            players: Vec::new(),
+        });
+    }
+
+    fn close_run(&mut self, time: u64) {
+        let Some(mut run) = self.current.take() else {return};
+        run.end_time = time.max(run.start_time);
+        self.runs.push(run);
+        self.log_start_times.push(self.log_start_time);
+    }
+}
+
+/// Finds every trial run in a log, with the fights that took place during it.
+pub fn trial_runs<R: BufRead>(reader: R) -> io::Result<Vec<TrialRun>> {
+    let mut tracker = FightTracker::new();
+    let mut trials = TrialTracker::new();
+
+    for_each_line(reader, |line, offset, length| {
+        if matches!(line, LogLine::BeginLog(_)) {
+            trials.handle_line(line, &tracker);
+        }
+        tracker.handle_line(line, offset, length);
+        if !matches!(line, LogLine::BeginLog(_)) {
+            trials.handle_line(line, &tracker);
+        }
+    })?;
+
+    let end_time = tracker.session.time;
+    tracker.close_open_fight();
+    Ok(trials.finish(end_time, &tracker.fights))
+}
@@ -1,11 +1,13 @@
-#[derive(Debug, PartialEq, Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum UnitType {
     Monster,
     Object,
 }
 
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum Reaction {
     PlayerAlly,
     NpcAlly,
@@ -26,7 +28,7 @@ pub fn match_reaction(string: &str) -> Reaction {
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub struct UnitState {
     pub unit_id: u32,
     pub health: u32,
@@ -66,7 +68,7 @@ pub fn blank_unit_state() -> UnitState {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Unit {
     pub unit_id: u32,
     pub unit_type: UnitType,
@@ -0,0 +1,142 @@
+use std::{collections::HashMap, io::{self, BufRead}};
+
+use serde::Serialize;
+
+use crate::{effect::EffectType, fights::{Fight, FightHeader, summarise_fights}, line::LogLine, session::{Session, SessionUnit}};
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct EffectUptime {
+    pub ability_id: u32,
+    pub name: String,
+    pub uptime_ms: u64,
+    /// Fraction of the fight the effect was active, from 0 to 1
+    pub uptime: f64,
+    /// Average stack count while the effect was active
+    pub average_stacks: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TargetUptimes {
+    pub unit_id: u32,
+    pub name: String,
+    pub is_boss: bool,
+    /// Sorted by uptime, highest first
+    pub effects: Vec<EffectUptime>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FightUptimes {
+    #[serde(flatten)]
+    pub fight: FightHeader,
+    pub targets: Vec<TargetUptimes>,
+}
+
+#[derive(Debug, Default)]
+struct UptimeState {
+    stacks: u16,
+    since: u64,
+    active_ms: u64,
+    stack_ms: u64,
+}
+
+impl UptimeState {
+    fn advance(&mut self, time: u64) {
+        let elapsed = time.saturating_sub(self.since);
+        if self.stacks > 0 {
+            self.active_ms += elapsed;
+            self.stack_ms += elapsed * self.stacks as u64;
+        }
+        self.since = time;
+    }
+}
+
+/// Tracks buffs on players and debuffs on bosses for the fight currently in progress.
+/// Effects from several sources on the same target count once, using the highest stack count among them.
+#[derive(Debug, Default)]
+pub struct UptimeAccumulator {
+    effects: HashMap<(u32, u32), UptimeState>,
+}
+
+impl UptimeAccumulator {
+    /// Picks up effects that were already active when the fight began.
+    pub fn start(&mut self, session: &Session, time: u64) {
+        self.effects.clear();
+        for unit in session.units() {
+            for effect in session.active_effects(unit.unit_id()) {
+                self.update(session, unit.unit_id(), effect.ability_id, time);
+            }
+        }
+    }
+
+    /// Call after the session has applied an EFFECT_CHANGED line.
+    pub fn update(&mut self, session: &Session, target_unit_id: u32, ability_id: u32, time: u64) {
+        if !is_tracked(session, target_unit_id, ability_id) {return}
+        let stacks = session.active_effects(target_unit_id).iter()
+            .filter(|e| e.ability_id == ability_id)
+            .map(|e| e.stack_count.max(1))
+            .max()
+            .unwrap_or(0);
+        let state = self.effects.entry((target_unit_id, ability_id)).or_insert_with(|| UptimeState { since: time, ..Default::default() });
+        state.advance(time);
+        state.stacks = stacks;
+    }
+
+    /// Produces the uptimes for `fight` and clears the accumulator for the next one.
+    pub fn finish(&mut self, fight: &Fight, session: &Session) -> FightUptimes {
+        let duration = fight.duration();
+        let mut targets: HashMap<u32, TargetUptimes> = HashMap::new();
+
+        for ((unit_id, ability_id), mut state) in self.effects.drain() {
+            state.advance(fight.end_time);
+            if state.active_ms == 0 {continue}
+            let target = targets.entry(unit_id).or_insert_with(|| TargetUptimes {
+                unit_id,
+                name: fight.players.iter().find(|p| p.unit_id == unit_id).map(|p| p.name.clone())
+                    .or_else(|| fight.bosses.iter().find(|b| b.unit_id == unit_id).map(|b| b.name.clone()))
+                    .or_else(|| session.unit(unit_id).map(|u| u.name().to_owned()))
+                    .unwrap_or_default(),
+                is_boss: fight.bosses.iter().any(|b| b.unit_id == unit_id),
+                effects: Vec::new(),
+            });
+            target.effects.push(EffectUptime {
+                ability_id,
+                name: session.ability(ability_id).map(|a| a.name.to_string()).unwrap_or_default(),
+                uptime_ms: state.active_ms,
+                uptime: if duration > 0 { (state.active_ms as f64 / duration as f64).min(1.0) } else { 0.0 },
+                average_stacks: state.stack_ms as f64 / state.active_ms as f64,
+            });
+        }
+
+        let mut targets: Vec<TargetUptimes> = targets.into_values().collect();
+        for target in &mut targets {
+            target.effects.sort_by(|a, b| b.uptime_ms.cmp(&a.uptime_ms).then(a.ability_id.cmp(&b.ability_id)));
+        }
+        targets.sort_by(|a, b| b.is_boss.cmp(&a.is_boss).then(a.name.cmp(&b.name)));
+
+        FightUptimes {
+            fight: FightHeader::from(fight),
+            targets,
+        }
+    }
+}
+
+fn is_tracked(session: &Session, unit_id: u32, ability_id: u32) -> bool {
+    let Some(effect) = session.effect(ability_id) else {return false};
+    match session.unit(unit_id) {
+        Some(SessionUnit::Player(_)) => effect.effect_type == EffectType::Buff,
+        Some(SessionUnit::Other(u)) => u.is_boss && effect.effect_type == EffectType::Debuff,
+        None => false,
+    }
+}
+
+/// Computes buff uptimes on players and debuff uptimes on bosses for every fight in a log.
+pub fn fight_uptimes<R: BufRead>(reader: R) -> io::Result<Vec<FightUptimes>> {
+    summarise_fights(reader, UptimeAccumulator::default(), |accumulator, line, tracker| {
+        if let LogLine::BeginCombat { time } = line {
+            accumulator.start(&tracker.session, *time);
+        } else if let LogLine::EffectChanged(effect_event) = line
+            && tracker.current_fight().is_some() {
+            accumulator.update(&tracker.session, effect_event.target_unit_state.unit_id, effect_event.ability_id, effect_event.time);
+        }
+    }, UptimeAccumulator::finish)
+}
@@ -0,0 +1,207 @@
+use std::{collections::{BTreeMap, HashSet}, fmt::{self, Display}, io::{self, BufRead}};
+
+use serde::Serialize;
+
+use crate::line::LogLine;
+
+/// Structural problems a log can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Problem {
+    /// A line before any BEGIN_LOG, or after an END_LOG and before the next BEGIN_LOG
+    MissingBeginLog,
+    /// A session ended by another BEGIN_LOG or the end of the file rather than an END_LOG
+    MissingEndLog,
+    /// An END_COMBAT while not in combat
+    EndCombatWithoutBeginCombat,
+    /// A line with an earlier time than the line before it in the same session
+    TimeWentBackwards,
+    /// An EFFECT_INFO for an ability with no ABILITY_INFO before it in the session
+    EffectInfoUnknownAbility,
+    /// A unit id used before its UNIT_ADDED in the session
+    UnitNotAdded,
+    /// A line that could not be parsed
+    MalformedLine,
+    /// A line type the parser does not know
+    UnknownLineType,
+}
+
+impl Problem {
+    pub fn description(self) -> &'static str {
+        match self {
+            Problem::MissingBeginLog => "Lines outside a BEGIN_LOG",
+            Problem::MissingEndLog => "Sessions without an END_LOG",
+            Problem::EndCombatWithoutBeginCombat => "END_COMBAT without BEGIN_COMBAT",
+            Problem::TimeWentBackwards => "Times going backwards",
+            Problem::EffectInfoUnknownAbility => "EFFECT_INFO for an unknown ability",
+            Problem::UnitNotAdded => "Units used before UNIT_ADDED",
+            Problem::MalformedLine => "Malformed lines",
+            Problem::UnknownLineType => "Unknown line types",
+        }
+    }
+}
+
+/// How often a problem occurs, and where it first does.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProblemCount {
+    pub problem: Problem,
+    pub count: usize,
+    /// Line number of the first occurrence, counting from 1
+    pub first_line: usize,
+    /// The first offending line, or the parse error for a malformed line
+    pub first_text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub lines: usize,
+    pub sessions: usize,
+    /// Sorted by problem
+    pub problems: Vec<ProblemCount>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn count(&self, problem: Problem) -> usize {
+        self.problems.iter().find(|p| p.problem == problem).map_or(0, |p| p.count)
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} lines in {} sessions", self.lines, self.sessions)?;
+        if self.is_valid() {
+            return write!(f, ", no problems found");
+        }
+        for problem in &self.problems {
+            write!(f, "\n  {:<36} {:>9}  first at line {}: {}", problem.problem.description(), problem.count, problem.first_line, problem.first_text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the validator is in the current session.
+#[derive(Debug, Default)]
+struct Validator {
+    problems: BTreeMap<Problem, ProblemCount>,
+    sessions: usize,
+    in_session: bool,
+    in_combat: bool,
+    last_time: u64,
+    last_line_number: usize,
+    units: HashSet<u32>,
+    abilities: HashSet<u32>,
+}
+
+impl Validator {
+    fn record(&mut self, problem: Problem, line_number: usize, text: &str) {
+        self.problems.entry(problem)
+            .or_insert_with(|| ProblemCount { problem, count: 0, first_line: line_number, first_text: text.to_owned() })
+            .count += 1;
+    }
+
+    fn check_unit(&mut self, unit_id: u32, line_number: usize, text: &str) {
+        if unit_id != 0 && !self.units.contains(&unit_id) {
+            self.record(Problem::UnitNotAdded, line_number, text);
+        }
+    }
+
+    fn line(&mut self, line: &LogLine, line_number: usize, text: &str) {
+        self.last_line_number = line_number;
+        if let LogLine::BeginLog(_) = line {
+            if self.in_session {
+                self.record(Problem::MissingEndLog, line_number, text);
+            }
+            self.sessions += 1;
+            self.in_session = true;
+            self.in_combat = false;
+            self.last_time = 0;
+            self.units.clear();
+            self.abilities.clear();
+            return;
+        }
+        if !self.in_session {
+            self.record(Problem::MissingBeginLog, line_number, text);
+        }
+
+        let time = line.time();
+        if time < self.last_time {
+            self.record(Problem::TimeWentBackwards, line_number, text);
+        }
+        self.last_time = time;
+
+        match line {
+            LogLine::EndLog { .. } => self.in_session = false,
+            LogLine::BeginCombat { .. } => self.in_combat = true,
+            LogLine::EndCombat { .. } => {
+                if !self.in_combat {
+                    self.record(Problem::EndCombatWithoutBeginCombat, line_number, text);
+                }
+                self.in_combat = false;
+            }
+            LogLine::UnitAdded(unit) => {
+                self.units.insert(unit.unit_id);
+            }
+            LogLine::AbilityInfo(ability) => {
+                self.abilities.insert(ability.ability_id);
+            }
+            LogLine::EffectInfo(effect) if !self.abilities.contains(&effect.ability_id) => {
+                self.record(Problem::EffectInfoUnknownAbility, line_number, text);
+            }
+            LogLine::CombatEvent(event) => {
+                self.check_unit(event.source_unit_state.unit_id, line_number, text);
+                self.check_unit(event.target_unit_state.unit_id, line_number, text);
+            }
+            LogLine::BeginCast(cast) => {
+                self.check_unit(cast.source_unit_state.unit_id, line_number, text);
+                self.check_unit(cast.target_unit_state.unit_id, line_number, text);
+            }
+            LogLine::EffectChanged(effect) => {
+                self.check_unit(effect.source_unit_state.unit_id, line_number, text);
+                self.check_unit(effect.target_unit_state.unit_id, line_number, text);
+            }
+            LogLine::HealthRegen(regen) => self.check_unit(regen.unit_state.unit_id, line_number, text),
+            LogLine::PlayerInfo(info) => self.check_unit(info.unit_id, line_number, text),
+            LogLine::UnitChanged(unit) => self.check_unit(unit.unit_id, line_number, text),
+            LogLine::UnitRemoved { unit_id, .. } => self.check_unit(*unit_id, line_number, text),
+            LogLine::Unknown(_) => self.record(Problem::UnknownLineType, line_number, text),
+            _ => {}
+        }
+    }
+
+    fn finish(mut self, lines: usize) -> ValidationReport {
+        if self.in_session {
+            let line_number = self.last_line_number;
+            self.record(Problem::MissingEndLog, line_number, "end of file");
+        }
+        ValidationReport { lines, sessions: self.sessions, problems: self.problems.into_values().collect() }
+    }
+}
+
+/// Reads a whole log and counts its structural problems, so a log that fails to upload can be told apart from a broken upload.
+/// Unit ids are expected to be added once per session; a unit removed and used again is not a problem.
+pub fn validate_log<R: BufRead>(mut reader: R) -> io::Result<ValidationReport> {
+    let mut validator = Validator::default();
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {break}
+        line_number += 1;
+        let text = String::from_utf8_lossy(&buffer);
+        let text = text.trim_end();
+        if text.is_empty() {continue}
+        match LogLine::parse(text) {
+            Ok(line) => validator.line(&line, line_number, text),
+            Err(e) => {
+                validator.last_line_number = line_number;
+                validator.record(Problem::MalformedLine, line_number, &e.to_string());
+            }
+        }
+    }
+
+    Ok(validator.finish(line_number))
+}
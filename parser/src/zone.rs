@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 
 lazy_static! {
     pub static ref ZONE_TO_PARENT: HashMap<u16, u16> = {
@@ -98,4 +99,18 @@ pub fn print_dungeon_zones() {
 
 pub fn is_dungeon(zone_id: u16) -> bool {
     *ZONE_TO_DUNGEON.get(&zone_id).unwrap_or(&false)
-}
\ No newline at end of file
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum DungeonDifficulty {
+    None,
+    Normal,
+    Veteran,
+}
+
+pub fn parse_dungeon_difficulty(string: &str) -> DungeonDifficulty {
+    match string {
+        "NORMAL" => DungeonDifficulty::Normal,
+        "VETERAN" => DungeonDifficulty::Veteran,
+        _ => DungeonDifficulty::None,
+    }
+}